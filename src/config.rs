@@ -2,16 +2,60 @@ use anyhow::Context;
 
 use tracing::debug;
 
+use schemars::JsonSchema;
+
 use serde::{Deserialize, Serialize};
 
 use tokio::{fs::File, io::AsyncReadExt, sync::OnceCell, time::Duration};
 
+use std::collections::BTreeMap;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ResponseHeaderRule {
+    pub host_regex: Option<String>,
+    pub path_regex: Option<String>,
+    #[serde(default)]
+    pub add: BTreeMap<String, String>,
+    #[serde(default)]
+    pub set: BTreeMap<String, String>,
+    #[serde(default)]
+    pub remove: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CorsConfiguration {
+    pub allowed_origins: Vec<String>,
+    pub allowed_origin_regexes: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+    pub allow_credentials: bool,
+    #[serde(with = "humantime_serde")]
+    pub max_age: Option<Duration>,
+}
+
+impl Default for CorsConfiguration {
+    fn default() -> Self {
+        Self {
+            allowed_origins: Vec::new(),
+            allowed_origin_regexes: Vec::new(),
+            allowed_methods: vec!["GET".to_owned()],
+            allowed_headers: Vec::new(),
+            allow_credentials: false,
+            max_age: None,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ContextConfiguration {
     pub dynamic_route_context: String,
+    #[serde(default)]
+    pub header_rules: Vec<ResponseHeaderRule>,
+    pub cors: Option<CorsConfiguration>,
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, JsonSchema)]
 pub enum ServerSocketType {
     #[serde(rename = "TCP")]
     Tcp,
@@ -20,28 +64,103 @@ pub enum ServerSocketType {
     Unix,
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CanonicalRedirectRule {
+    pub host_regex: Option<String>,
+    pub scheme: Option<String>,
+    pub host: Option<String>,
+    pub status_code: Option<u16>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum IpAccessAction {
+    #[serde(rename = "ALLOW")]
+    Allow,
+
+    #[serde(rename = "DENY")]
+    Deny,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct IpAccessRule {
+    pub action: IpAccessAction,
+    pub cidrs: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RouteIpAccessRule {
+    pub path_prefix: String,
+    pub rules: Vec<IpAccessRule>,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ServerListenerConfiguration {
     pub socket_type: ServerSocketType,
     pub bind_address: String,
+    #[serde(default)]
+    pub canonical_redirect_rules: Vec<CanonicalRedirectRule>,
+    #[serde(default)]
+    pub ip_access_rules: Vec<IpAccessRule>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ServerConnectionConfiguration {
     pub limit: usize,
+    pub per_client_limit: Option<usize>,
     #[serde(with = "humantime_serde")]
     pub max_lifetime: Duration,
     #[serde(with = "humantime_serde")]
     pub graceful_shutdown_timeout: Duration,
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ServerRequestLimitsConfiguration {
+    #[serde(with = "humantime_serde")]
+    pub first_request_timeout: Duration,
+    #[serde(with = "humantime_serde")]
+    pub http1_header_read_timeout: Duration,
+    pub http1_max_header_size: usize,
+    pub http1_max_headers: usize,
+    pub http2_max_header_list_size: u32,
+    pub max_request_body_size: usize,
+}
+
+impl Default for ServerRequestLimitsConfiguration {
+    fn default() -> Self {
+        Self {
+            first_request_timeout: Duration::from_secs(10),
+            http1_header_read_timeout: Duration::from_secs(10),
+            http1_max_header_size: 64 * 1024,
+            http1_max_headers: 100,
+            http2_max_header_list_size: 64 * 1024,
+            max_request_body_size: 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ForwardedHeadersConfiguration {
+    pub trusted_cidrs: Vec<String>,
+    pub trust_unix_peers: bool,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ServerConfiguration {
     pub listeners: Vec<ServerListenerConfiguration>,
     pub connection: ServerConnectionConfiguration,
+    #[serde(default)]
+    pub request_limits: ServerRequestLimitsConfiguration,
+    #[serde(default)]
+    pub forwarded_headers: ForwardedHeadersConfiguration,
+    #[serde(default)]
+    pub canonical_redirect_rules: Vec<CanonicalRedirectRule>,
+    #[serde(default)]
+    pub route_ip_access_rules: Vec<RouteIpAccessRule>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct CommandInfo {
     pub id: String,
     pub description: String,
@@ -60,13 +179,22 @@ pub struct CommandConfiguration {
     pub commands: Vec<CommandInfo>,
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, JsonSchema)]
 pub enum StaticFileCacheRuleType {
     #[serde(rename = "MOD_TIME_PLUS_DELTA")]
     ModTimePlusDelta,
 
     #[serde(rename = "FIXED_TIME")]
     FixedTime,
+
+    #[serde(rename = "PRIVATE")]
+    Private,
+
+    #[serde(rename = "IMMUTABLE")]
+    Immutable,
+
+    #[serde(rename = "NO_STORE")]
+    NoStore,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -74,8 +202,54 @@ pub struct StaticFileCacheRule {
     pub host_regex: Option<String>,
     pub path_regex: Option<String>,
     pub rule_type: StaticFileCacheRuleType,
-    #[serde(with = "humantime_serde")]
-    pub duration: Duration,
+    #[serde(default, with = "humantime_serde")]
+    pub duration: Option<Duration>,
+    #[serde(default, with = "humantime_serde")]
+    pub stale_while_revalidate: Option<Duration>,
+    #[serde(default, with = "humantime_serde")]
+    pub stale_if_error: Option<Duration>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StaticFileTryFilesRule {
+    pub host_regex: Option<String>,
+    pub path_regex: Option<String>,
+    pub try_files: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum StaticFileRewriteRuleType {
+    #[serde(rename = "REDIRECT")]
+    Redirect,
+
+    #[serde(rename = "REWRITE")]
+    Rewrite,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StaticFileRewriteRule {
+    pub host_regex: Option<String>,
+    pub path_regex: String,
+    pub rule_type: StaticFileRewriteRuleType,
+    pub target: String,
+    pub status_code: Option<u16>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StaticFileContentTypeRule {
+    pub host_regex: Option<String>,
+    pub path_regex: Option<String>,
+    pub content_type: String,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct StaticFileDotPathConfiguration {
+    #[serde(default)]
+    pub allow_prefixes: Vec<String>,
+    #[serde(default)]
+    pub allow_regexes: Vec<String>,
+    #[serde(default)]
+    pub deny_regexes: Vec<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -84,12 +258,238 @@ pub struct StaticFilePrecompressedConfiguration {
     pub gz: bool,
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct StaticFilePrecompressConfiguration {
+    pub min_size: u64,
+    pub zstd: bool,
+    pub extensions: Vec<String>,
+}
+
+impl Default for StaticFilePrecompressConfiguration {
+    fn default() -> Self {
+        Self {
+            min_size: 1024,
+            zstd: false,
+            extensions: [
+                "css",
+                "htm",
+                "html",
+                "js",
+                "json",
+                "map",
+                "mjs",
+                "svg",
+                "txt",
+                "wasm",
+                "webmanifest",
+                "xml",
+            ]
+            .into_iter()
+            .map(str::to_owned)
+            .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StaticFileMountConfiguration {
+    pub url_prefix: String,
+    pub root: String,
+    pub precompressed: StaticFilePrecompressedConfiguration,
+    #[serde(default)]
+    pub autoindex: bool,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct StaticFileConfiguration {
     pub root: String,
     pub precompressed: StaticFilePrecompressedConfiguration,
-    pub client_error_page_path: String,
+    #[serde(default)]
+    pub autoindex: bool,
+    #[serde(default)]
+    pub mounts: Vec<StaticFileMountConfiguration>,
+    pub client_error_page_path: Option<String>,
+    #[serde(default)]
+    pub error_pages: BTreeMap<String, String>,
     pub cache_rules: Vec<StaticFileCacheRule>,
+    #[serde(default)]
+    pub mime_types: BTreeMap<String, String>,
+    pub extensionless_mime_type: Option<String>,
+    pub default_charset: Option<String>,
+    #[serde(default)]
+    pub content_type_rules: Vec<StaticFileContentTypeRule>,
+    #[serde(default)]
+    pub dot_paths: StaticFileDotPathConfiguration,
+    #[serde(default)]
+    pub rewrite_rules: Vec<StaticFileRewriteRule>,
+    #[serde(default)]
+    pub try_files_rules: Vec<StaticFileTryFilesRule>,
+    #[serde(default)]
+    pub header_rules: Vec<ResponseHeaderRule>,
+    #[serde(default)]
+    pub content_hash_etags: bool,
+    #[serde(default)]
+    pub static_manifest: bool,
+    #[serde(default)]
+    pub cache_rule_explain: bool,
+    #[serde(default)]
+    pub precompress: StaticFilePrecompressConfiguration,
+    #[serde(default, with = "humantime_serde")]
+    pub archive_reload_interval: Option<Duration>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct AuthRule {
+    pub host_regex: Option<String>,
+    pub path_regex: Option<String>,
+    #[serde(default)]
+    pub route_ids: Vec<String>,
+    pub realm: Option<String>,
+    #[serde(default)]
+    pub users: BTreeMap<String, String>,
+    pub htpasswd_file: Option<String>,
+    #[serde(default)]
+    pub bearer_tokens: BTreeMap<String, String>,
+}
+
+/// Only user and token names are shown, the configuration is logged at debug level.
+impl std::fmt::Debug for AuthRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuthRule")
+            .field("host_regex", &self.host_regex)
+            .field("path_regex", &self.path_regex)
+            .field("route_ids", &self.route_ids)
+            .field("realm", &self.realm)
+            .field("users", &self.users.keys().collect::<Vec<_>>())
+            .field("htpasswd_file", &self.htpasswd_file)
+            .field(
+                "bearer_tokens",
+                &self.bearer_tokens.keys().collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum RequestIdFormat {
+    #[serde(rename = "UUID")]
+    Uuid,
+
+    #[serde(rename = "ULID")]
+    Ulid,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RequestIdConfiguration {
+    pub header_name: String,
+    pub format: RequestIdFormat,
+    pub max_length: usize,
+    pub trusted_cidrs: Vec<String>,
+    pub trust_unix_peers: bool,
+}
+
+impl Default for RequestIdConfiguration {
+    fn default() -> Self {
+        Self {
+            header_name: "x-request-id".to_owned(),
+            format: RequestIdFormat::Uuid,
+            max_length: 128,
+            trusted_cidrs: Vec::new(),
+            trust_unix_peers: false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RateLimitRule {
+    pub path_prefix: String,
+    pub burst: u32,
+    pub refill_per_second: f64,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub enum ProxyUpstreamProtocol {
+    #[default]
+    #[serde(rename = "HTTP1")]
+    Http1,
+
+    #[serde(rename = "H2C")]
+    H2c,
+}
+
+fn default_proxy_connect_timeout() -> Duration {
+    Duration::from_secs(5)
+}
+
+fn default_proxy_request_timeout() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_proxy_pool_idle_timeout() -> Duration {
+    Duration::from_secs(90)
+}
+
+fn default_proxy_pool_max_idle() -> usize {
+    32
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ProxyUpstreamConfiguration {
+    pub socket_type: ServerSocketType,
+    pub address: String,
+    #[serde(default)]
+    pub protocol: ProxyUpstreamProtocol,
+    #[serde(default = "default_proxy_connect_timeout", with = "humantime_serde")]
+    pub connect_timeout: Duration,
+    #[serde(default = "default_proxy_request_timeout", with = "humantime_serde")]
+    pub request_timeout: Duration,
+    #[serde(default = "default_proxy_pool_idle_timeout", with = "humantime_serde")]
+    pub pool_idle_timeout: Duration,
+    #[serde(default = "default_proxy_pool_max_idle")]
+    pub pool_max_idle: usize,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ProxyRoute {
+    pub path_prefix: String,
+    pub rewrite_prefix: Option<String>,
+    pub upstream: ProxyUpstreamConfiguration,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, JsonSchema)]
+pub enum MiddlewareType {
+    #[serde(rename = "REQUEST_LOG")]
+    RequestLog,
+
+    #[serde(rename = "CANONICAL_REDIRECT")]
+    CanonicalRedirect,
+
+    #[serde(rename = "RATE_LIMIT")]
+    RateLimit,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MiddlewareConfiguration {
+    pub middleware_type: MiddlewareType,
+    pub listener_bind_address: Option<String>,
+    pub path_prefix: Option<String>,
+}
+
+fn default_middlewares() -> Vec<MiddlewareConfiguration> {
+    [
+        MiddlewareType::RequestLog,
+        MiddlewareType::CanonicalRedirect,
+        MiddlewareType::RateLimit,
+    ]
+    .into_iter()
+    .map(|middleware_type| MiddlewareConfiguration {
+        middleware_type,
+        listener_bind_address: None,
+        path_prefix: None,
+    })
+    .collect()
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -98,6 +498,16 @@ pub struct Configuration {
     pub static_file_configuration: StaticFileConfiguration,
     pub context_configuration: ContextConfiguration,
     pub command_configuration: CommandConfiguration,
+    #[serde(default)]
+    pub auth_rules: Vec<AuthRule>,
+    #[serde(default = "default_middlewares")]
+    pub middlewares: Vec<MiddlewareConfiguration>,
+    #[serde(default)]
+    pub request_id_configuration: RequestIdConfiguration,
+    #[serde(default)]
+    pub rate_limit_rules: Vec<RateLimitRule>,
+    #[serde(default)]
+    pub proxy_routes: Vec<ProxyRoute>,
 }
 
 static CONFIGURATION_INSTANCE: OnceCell<Configuration> = OnceCell::const_new();
@@ -1,8 +1,17 @@
+mod access_check;
+mod body_limit;
+mod cache_rule_explain;
 mod commands;
 mod connection_info;
+mod middleware;
+mod openapi;
+mod proxy;
+mod rate_limit_info;
 mod request_info;
 mod route;
+mod route_info;
 mod static_file;
+mod static_manifest;
 mod time_utils;
 mod version_info;
 
@@ -15,22 +24,56 @@ use crate::{request::HttpRequest, response::ResponseBody};
 #[async_trait]
 pub trait RequestHandler: Send + Sync {
     async fn handle(&self, request: &HttpRequest) -> Response<ResponseBody>;
+
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
 }
 
-pub async fn create_handlers() -> anyhow::Result<Box<dyn RequestHandler>> {
+async fn create_routes() -> anyhow::Result<Vec<route::RouteInfo>> {
     let mut routes = Vec::new();
 
+    routes.extend(cache_rule_explain::create_routes());
+
     routes.extend(commands::create_routes().await?);
 
     routes.extend(connection_info::create_routes().await);
 
+    routes.extend(rate_limit_info::create_routes());
+
     routes.extend(request_info::create_routes());
 
-    routes.extend(version_info::create_routes().await);
+    routes.extend(route_info::create_routes());
+
+    routes.extend(static_manifest::create_routes().await);
+
+    routes.extend(version_info::create_routes().await?);
+
+    Ok(routes)
+}
+
+pub async fn create_handlers() -> anyhow::Result<Box<dyn RequestHandler>> {
+    let mut routes = create_routes().await?;
+
+    let openapi_routes = openapi::create_routes(&routes)?;
+
+    routes.extend(openapi_routes);
+
+    let proxy_handlers = proxy::create_proxy_handlers()?;
+
+    let default_route = static_file::create_default_route().await;
 
-    let default_route = static_file::create_default_route();
+    route_info::set_routes(&routes, &proxy_handlers, default_route.as_ref())?;
+
+    let router = Box::new(route::Router::new(routes, proxy_handlers, default_route)?);
+
+    let body_limit_handler = body_limit::create_body_limit_handler(router).await;
+
+    middleware::create_middleware_chain(body_limit_handler)
+}
 
-    let router = Box::new(route::Router::new(routes, default_route)?);
+pub async fn write_openapi_document(output_file: &str) -> anyhow::Result<()> {
+    let routes = create_routes().await?;
 
-    Ok(router)
+    openapi::write_openapi_document(&routes, output_file).await
 }
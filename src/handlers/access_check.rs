@@ -0,0 +1,88 @@
+use hyper::http::{header, HeaderValue, Response, StatusCode};
+
+use tracing::debug;
+
+use crate::{
+    handlers::{HttpRequest, ResponseBody},
+    service::{
+        auth::{AuthResult, AuthService},
+        error_page::ErrorPageService,
+        ip_access::IpAccessService,
+    },
+};
+
+pub enum AccessCheckResult {
+    /// authenticated is true if an auth rule required and accepted credentials.
+    Allowed {
+        authenticated: bool,
+    },
+    Denied(Response<ResponseBody>),
+}
+
+/// Checks a request against the ip access and auth rules of the paths it reads.
+pub struct AccessChecker {
+    auth_service: &'static AuthService,
+    ip_access_service: &'static IpAccessService,
+    error_page_service: &'static ErrorPageService,
+}
+
+impl AccessChecker {
+    pub fn new() -> Self {
+        Self {
+            auth_service: crate::service::auth::auth_service_instance(),
+            ip_access_service: crate::service::ip_access::ip_access_service_instance(),
+            error_page_service: crate::service::error_page::error_page_service_instance(),
+        }
+    }
+
+    /// The request must pass the rules for every path in access_paths, otherwise answer 403 or 401.
+    pub async fn check(
+        &self,
+        request: &HttpRequest,
+        access_paths: &[&str],
+        route_id_option: Option<&str>,
+    ) -> AccessCheckResult {
+        if !access_paths
+            .iter()
+            .all(|path| self.ip_access_service.allows_route(path, request.peer_ip))
+        {
+            debug!("ip access denied peer_ip = {:?}", request.peer_ip);
+            return AccessCheckResult::Denied(
+                self.error_page_service
+                    .build_error_page_response(request, StatusCode::FORBIDDEN)
+                    .await,
+            );
+        }
+
+        let mut authenticated = false;
+
+        for path in access_paths {
+            match self
+                .auth_service
+                .authenticate(request, path, route_id_option)
+                .await
+            {
+                AuthResult::NotRequired => {}
+                AuthResult::Authenticated(username) => {
+                    tracing::Span::current().record("user", username.as_str());
+                    authenticated = true;
+                }
+                AuthResult::Unauthorized(challenges) => {
+                    let mut response = self
+                        .error_page_service
+                        .build_error_page_response(request, StatusCode::UNAUTHORIZED)
+                        .await;
+
+                    let headers = response.headers_mut();
+                    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+                    for challenge in challenges {
+                        headers.append(header::WWW_AUTHENTICATE, challenge.clone());
+                    }
+                    return AccessCheckResult::Denied(response);
+                }
+            }
+        }
+
+        AccessCheckResult::Allowed { authenticated }
+    }
+}
@@ -0,0 +1,78 @@
+use async_trait::async_trait;
+
+use hyper::http::{header, HeaderMap, Response, StatusCode};
+
+use crate::{
+    handlers::{HttpRequest, RequestHandler, ResponseBody},
+    service::{
+        connection::{ConnectionTrackerService, RequestLimitViolation},
+        error_page::ErrorPageService,
+    },
+};
+
+fn content_length_exceeds(headers: &HeaderMap, limit: usize) -> bool {
+    headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .is_some_and(|content_length| content_length > limit as u64)
+}
+
+/// Answers 413 for a Content-Length over request_limits.max_request_body_size before
+/// the handler reads the body. Bodies without a Content-Length are limited as they are read.
+struct BodyLimitHandler {
+    max_request_body_size: usize,
+    connection_tracker: &'static ConnectionTrackerService,
+    error_page_service: &'static ErrorPageService,
+    handler: Box<dyn RequestHandler>,
+}
+
+#[async_trait]
+impl RequestHandler for BodyLimitHandler {
+    async fn handle(&self, request: &HttpRequest) -> Response<ResponseBody> {
+        if !content_length_exceeds(request.hyper_request.headers(), self.max_request_body_size) {
+            return self.handler.handle(request).await;
+        }
+
+        self.connection_tracker
+            .add_request_limit_violation(request.connection_id, RequestLimitViolation::BodyLimit)
+            .await;
+
+        self.error_page_service
+            .build_error_page_response(request, StatusCode::PAYLOAD_TOO_LARGE)
+            .await
+    }
+}
+
+pub async fn create_body_limit_handler(
+    handler: Box<dyn RequestHandler>,
+) -> Box<dyn RequestHandler> {
+    Box::new(BodyLimitHandler {
+        max_request_body_size: crate::config::instance()
+            .server_configuration
+            .request_limits
+            .max_request_body_size,
+        connection_tracker: ConnectionTrackerService::instance().await,
+        error_page_service: crate::service::error_page::error_page_service_instance(),
+        handler,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use hyper::http::HeaderValue;
+
+    #[test]
+    fn test_content_length_exceeds() {
+        let mut headers = HeaderMap::new();
+        assert!(!content_length_exceeds(&headers, 10));
+
+        headers.insert(header::CONTENT_LENGTH, HeaderValue::from_static("10"));
+        assert!(!content_length_exceeds(&headers, 10));
+
+        headers.insert(header::CONTENT_LENGTH, HeaderValue::from_static("11"));
+        assert!(content_length_exceeds(&headers, 10));
+    }
+}
@@ -0,0 +1,168 @@
+use async_trait::async_trait;
+
+use hyper::http::{HeaderMap, Method, Response, StatusCode};
+
+use schemars::{JsonSchema, SchemaGenerator};
+
+use serde::{Deserialize, Serialize};
+
+use tracing::warn;
+
+use std::path::PathBuf;
+
+use crate::{
+    handlers::{
+        route::RouteInfo,
+        static_file::{StaticFileOutcome, StaticFileResolver},
+        time_utils::current_local_date_time_string,
+        HttpRequest, RequestHandler, ResponseBody,
+    },
+    response::{build_json_response, CacheControl},
+    service::{
+        error_page::ErrorPageService,
+        static_file::{vfs::ResolveResult, CacheRulesExplanation, StaticFileRulesService},
+    },
+};
+
+#[derive(Debug, Deserialize)]
+struct CacheRuleExplainQuery {
+    host: Option<String>,
+    path: String,
+}
+
+#[derive(Debug, Default, Serialize, JsonSchema)]
+struct CacheRuleExplainResponse {
+    now: String,
+    host: Option<String>,
+    path: String,
+    rewritten_path: Option<String>,
+    redirect_location: Option<String>,
+    blocked: bool,
+    resolve_result: Option<&'static str>,
+    resolved_path: Option<String>,
+    cache_rules: Option<CacheRulesExplanation>,
+}
+
+fn resolve_result_name(resolve_result: &ResolveResult) -> &'static str {
+    match resolve_result {
+        ResolveResult::MethodNotMatched => "MethodNotMatched",
+        ResolveResult::NotFound => "NotFound",
+        ResolveResult::PermissionDenied => "PermissionDenied",
+        ResolveResult::IsDirectory { .. } => "IsDirectory",
+        ResolveResult::Found(_) => "Found",
+    }
+}
+
+struct CacheRuleExplainHandler {
+    static_file_resolver: StaticFileResolver,
+    static_file_rules_service: &'static StaticFileRulesService,
+    error_page_service: &'static ErrorPageService,
+}
+
+impl CacheRuleExplainHandler {
+    fn new() -> Self {
+        Self {
+            static_file_resolver: StaticFileResolver::new(),
+            static_file_rules_service: crate::service::static_file::rules_service_instance(),
+            error_page_service: crate::service::error_page::error_page_service_instance(),
+        }
+    }
+
+    /// Resolve the path the way the static file handler does, without accept-encoding.
+    async fn explain(
+        &self,
+        query: CacheRuleExplainQuery,
+    ) -> std::io::Result<CacheRuleExplainResponse> {
+        let host_option = query.host.as_deref();
+
+        let resolution = self
+            .static_file_resolver
+            .resolve(
+                &Method::GET,
+                &HeaderMap::new(),
+                host_option,
+                &query.path,
+                None,
+            )
+            .await?;
+
+        let mut response = CacheRuleExplainResponse {
+            now: current_local_date_time_string(),
+            host: query.host.clone(),
+            path: query.path.clone(),
+            rewritten_path: resolution.rewritten_path,
+            ..Default::default()
+        };
+
+        match resolution.outcome {
+            StaticFileOutcome::Redirect { location, .. } => {
+                response.redirect_location = Some(location);
+            }
+            StaticFileOutcome::Blocked => {
+                response.blocked = true;
+            }
+            StaticFileOutcome::Autoindex { .. } => {
+                response.resolve_result = Some("Autoindex");
+            }
+            StaticFileOutcome::Resolved { resolve_result, .. } => {
+                response.resolve_result = Some(resolve_result_name(&resolve_result));
+
+                if let ResolveResult::Found(resolved_file) = &resolve_result {
+                    response.resolved_path =
+                        Some(resolved_file.path.to_string_lossy().into_owned());
+                    response.cache_rules = Some(
+                        self.static_file_rules_service
+                            .explain_cache_rules(host_option, resolved_file),
+                    );
+                }
+            }
+        }
+
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl RequestHandler for CacheRuleExplainHandler {
+    async fn handle(&self, request: &HttpRequest) -> Response<ResponseBody> {
+        let query_string = request.hyper_request.uri().query().unwrap_or_default();
+
+        let query: CacheRuleExplainQuery = match serde_urlencoded::from_str(query_string) {
+            Ok(query) => query,
+            Err(e) => {
+                warn!("CacheRuleExplainHandler query error: {}", e);
+                return self
+                    .error_page_service
+                    .build_error_page_response(request, StatusCode::BAD_REQUEST)
+                    .await;
+            }
+        };
+
+        match self.explain(query).await {
+            Ok(response) => build_json_response(request, response, CacheControl::NoCache),
+            Err(e) => {
+                warn!("CacheRuleExplainHandler::explain error: {}", e);
+                self.error_page_service
+                    .build_error_page_response(request, StatusCode::INTERNAL_SERVER_ERROR)
+                    .await
+            }
+        }
+    }
+}
+
+pub fn create_routes() -> Vec<RouteInfo> {
+    if !crate::config::instance()
+        .static_file_configuration
+        .cache_rule_explain
+    {
+        return Vec::new();
+    }
+
+    vec![RouteInfo {
+        method: &Method::GET,
+        path_suffix: PathBuf::from("cache_rule_explain"),
+        summary: "Explain which rewrite, blocking and cache rules apply to a static file path",
+        response_schema: SchemaGenerator::subschema_for::<CacheRuleExplainResponse>,
+        handler: Box::new(CacheRuleExplainHandler::new()),
+    }]
+}
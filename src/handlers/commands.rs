@@ -12,6 +12,8 @@ use tokio::{
     time::{Duration, Instant},
 };
 
+use schemars::{JsonSchema, SchemaGenerator};
+
 use serde::Serialize;
 
 use std::{path::PathBuf, process::Stdio, sync::Arc};
@@ -21,31 +23,29 @@ use crate::{
         route::RouteInfo, time_utils::current_local_date_time_string, HttpRequest, RequestHandler,
         ResponseBody,
     },
-    response::{
-        build_json_body_response, build_json_response, build_status_code_response,
-        static_string_response_body, CacheControl,
-    },
+    response::{build_json_response, build_static_json_response, CacheControl, StaticJson},
+    service::error_page::ErrorPageService,
 };
 
 struct AllCommandsHandler;
 
 impl AllCommandsHandler {
-    async fn json_string() -> anyhow::Result<&'static str> {
-        static INSTANCE: OnceCell<String> = OnceCell::const_new();
+    async fn static_json() -> anyhow::Result<&'static StaticJson> {
+        static INSTANCE: OnceCell<StaticJson> = OnceCell::const_new();
 
-        let string = INSTANCE
+        let static_json = INSTANCE
             .get_or_try_init(|| async move {
                 let commands = &crate::config::instance().command_configuration.commands;
-                serde_json::to_string(commands)
+                StaticJson::new(commands)
             })
             .await
-            .context("AllCommandsHandler::json_string: INSTANCE.get_or_try_init error")?;
+            .context("AllCommandsHandler::static_json: INSTANCE.get_or_try_init error")?;
 
-        Ok(string)
+        Ok(static_json)
     }
 
     async fn instance() -> anyhow::Result<Self> {
-        Self::json_string().await?;
+        Self::static_json().await?;
 
         Ok(Self)
     }
@@ -53,12 +53,9 @@ impl AllCommandsHandler {
 
 #[async_trait]
 impl RequestHandler for AllCommandsHandler {
-    async fn handle(&self, _request: &HttpRequest) -> Response<ResponseBody> {
-        let json_string = Self::json_string().await.unwrap();
-        build_json_body_response(
-            static_string_response_body(json_string),
-            CacheControl::NoCache,
-        )
+    async fn handle(&self, request: &HttpRequest) -> Response<ResponseBody> {
+        let static_json = Self::static_json().await.unwrap();
+        build_static_json_response(request, static_json, CacheControl::NoCache)
     }
 }
 
@@ -93,7 +90,7 @@ impl RunCommandSemapore {
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 struct RunCommandResponse<'a> {
     now: String,
     command_duration_ms: u128,
@@ -104,6 +101,7 @@ struct RunCommandResponse<'a> {
 struct RunCommandHandler {
     run_command_semaphore: Arc<RunCommandSemapore>,
     command_info: &'static crate::config::CommandInfo,
+    error_page_service: &'static ErrorPageService,
 }
 
 impl RunCommandHandler {
@@ -114,6 +112,7 @@ impl RunCommandHandler {
         Self {
             run_command_semaphore,
             command_info,
+            error_page_service: crate::service::error_page::error_page_service_instance(),
         }
     }
 
@@ -130,6 +129,7 @@ impl RunCommandHandler {
 
     fn handle_command_result(
         &self,
+        request: &HttpRequest,
         command_result: Result<std::process::Output, std::io::Error>,
         command_duration: Duration,
     ) -> Response<ResponseBody> {
@@ -152,20 +152,20 @@ impl RunCommandHandler {
             },
         };
 
-        build_json_response(response, CacheControl::NoCache)
+        build_json_response(request, response, CacheControl::NoCache)
     }
 }
 
 #[async_trait]
 impl RequestHandler for RunCommandHandler {
-    async fn handle(&self, _request: &HttpRequest) -> Response<ResponseBody> {
+    async fn handle(&self, request: &HttpRequest) -> Response<ResponseBody> {
         let run_command_permit = match self.run_command_semaphore.acquire().await {
             Err(err) => {
                 warn!("run_command_semaphore.acquire error: {}", err);
-                return build_status_code_response(
-                    StatusCode::TOO_MANY_REQUESTS,
-                    CacheControl::NoCache,
-                );
+                return self
+                    .error_page_service
+                    .build_error_page_response(request, StatusCode::TOO_MANY_REQUESTS)
+                    .await;
             }
             Ok(permit) => permit,
         };
@@ -176,7 +176,7 @@ impl RequestHandler for RunCommandHandler {
 
         drop(run_command_permit);
 
-        self.handle_command_result(command_result, command_duration)
+        self.handle_command_result(request, command_result, command_duration)
     }
 }
 
@@ -188,6 +188,8 @@ pub async fn create_routes() -> anyhow::Result<Vec<RouteInfo>> {
     routes.push(RouteInfo {
         method: &Method::GET,
         path_suffix: PathBuf::from("commands"),
+        summary: "Configured commands",
+        response_schema: SchemaGenerator::subschema_for::<Vec<crate::config::CommandInfo>>,
         handler: Box::new(AllCommandsHandler::instance().await?),
     });
 
@@ -199,6 +201,8 @@ pub async fn create_routes() -> anyhow::Result<Vec<RouteInfo>> {
         routes.push(RouteInfo {
             method: &Method::GET,
             path_suffix,
+            summary: &command_info.description,
+            response_schema: SchemaGenerator::subschema_for::<RunCommandResponse<'static>>,
             handler: Box::new(RunCommandHandler::new(
                 Arc::clone(&run_command_semaphore),
                 command_info,
@@ -4,11 +4,13 @@ use async_trait::async_trait;
 
 use hyper::http::{Method, Response};
 
+use schemars::{JsonSchema, SchemaGenerator};
+
 use serde::Serialize;
 
 use tokio::time::Instant;
 
-use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use std::{collections::BTreeMap, net::IpAddr, sync::Arc, time::Duration};
 
 use crate::{
     config::ServerSocketType,
@@ -23,12 +25,15 @@ use crate::{
     },
 };
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 struct ConnectionInfoDTO {
     id: usize,
     server_socket_type: ServerSocketType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    peer_ip: Option<IpAddr>,
     creation_time: String,
     #[serde(with = "humantime_serde")]
+    #[schemars(with = "String")]
     age: Duration,
     num_requests: usize,
 }
@@ -41,6 +46,7 @@ impl From<Arc<ConnectionInfo>> for ConnectionInfoDTO {
         Self {
             id: connection_info.id.as_usize(),
             server_socket_type: connection_info.server_socket_type,
+            peer_ip: connection_info.peer_ip,
             creation_time: local_date_time_to_string(&LocalDateTime::from(
                 connection_info.creation_time,
             )),
@@ -50,13 +56,26 @@ impl From<Arc<ConnectionInfo>> for ConnectionInfoDTO {
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
+struct PerClientLimitOffenderDTO {
+    peer_ip: IpAddr,
+    per_client_limit_hits: usize,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
 struct ConnectionTrackerStateSnaphotDTO {
     max_open_connections: usize,
     connection_limit_hits: usize,
+    per_client_limit_hits: usize,
+    top_per_client_limit_offenders: Vec<PerClientLimitOffenderDTO>,
+    header_read_timeouts: usize,
+    header_limit_violations: usize,
+    body_limit_violations: usize,
     #[serde(with = "humantime_serde")]
+    #[schemars(with = "String")]
     min_connection_lifetime: Duration,
     #[serde(with = "humantime_serde")]
+    #[schemars(with = "String")]
     max_connection_lifetime: Duration,
     max_requests_per_connection: usize,
     num_open_connections: usize,
@@ -92,6 +111,20 @@ impl From<ConnectionTrackerStateSnapshot> for ConnectionTrackerStateSnaphotDTO {
         Self {
             max_open_connections: state_snapshot.max_open_connections,
             connection_limit_hits: state_snapshot.connection_limit_hits,
+            per_client_limit_hits: state_snapshot.per_client_limit_hits,
+            top_per_client_limit_offenders: state_snapshot
+                .top_per_client_limit_offenders
+                .into_iter()
+                .map(
+                    |(peer_ip, per_client_limit_hits)| PerClientLimitOffenderDTO {
+                        peer_ip,
+                        per_client_limit_hits,
+                    },
+                )
+                .collect(),
+            header_read_timeouts: state_snapshot.header_read_timeouts,
+            header_limit_violations: state_snapshot.header_limit_violations,
+            body_limit_violations: state_snapshot.body_limit_violations,
             min_connection_lifetime,
             max_connection_lifetime,
             max_requests_per_connection: state_snapshot.max_requests_per_connection,
@@ -115,14 +148,14 @@ impl ServerInfoHandler {
 
 #[async_trait]
 impl RequestHandler for ServerInfoHandler {
-    async fn handle(&self, _request: &HttpRequest) -> Response<ResponseBody> {
+    async fn handle(&self, request: &HttpRequest) -> Response<ResponseBody> {
         let connection_tracker_state_dto: ConnectionTrackerStateSnaphotDTO = self
             .connection_tracker
             .connection_tracker_state_snapshot()
             .await
             .into();
 
-        build_json_response(connection_tracker_state_dto, CacheControl::NoCache)
+        build_json_response(request, connection_tracker_state_dto, CacheControl::NoCache)
     }
 }
 
@@ -130,6 +163,8 @@ pub async fn create_routes() -> Vec<RouteInfo> {
     vec![RouteInfo {
         method: &Method::GET,
         path_suffix: PathBuf::from("connection_info"),
+        summary: "Connection tracker metrics and the newest open connections",
+        response_schema: SchemaGenerator::subschema_for::<ConnectionTrackerStateSnaphotDTO>,
         handler: Box::new(ServerInfoHandler::new().await),
     }]
 }
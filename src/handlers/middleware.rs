@@ -0,0 +1,196 @@
+mod canonical_redirect;
+mod rate_limit;
+mod request_log;
+
+use anyhow::Context;
+
+use async_trait::async_trait;
+
+use hyper::http::Response;
+
+use tracing::debug;
+
+use crate::{
+    config::{Configuration, MiddlewareType},
+    handlers::{HttpRequest, RequestHandler, ResponseBody},
+    service::request_matcher::path_has_prefix,
+};
+
+/// Hooks run around a RequestHandler.
+#[async_trait]
+pub trait Middleware: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Runs before the handler in chain order, returning a response skips the rest of the chain.
+    async fn before(&self, _request: &HttpRequest) -> Option<Response<ResponseBody>> {
+        None
+    }
+
+    /// Runs after the handler in reverse chain order, for every middleware whose before hook ran.
+    async fn after(&self, _request: &HttpRequest, _response: &mut Response<ResponseBody>) {}
+}
+
+/// Which requests a middleware applies to, None matches everything.
+#[derive(Debug)]
+struct MiddlewareScope {
+    listener_index_option: Option<usize>,
+    path_prefix_option: Option<String>,
+}
+
+impl MiddlewareScope {
+    fn new(
+        middleware_configuration: &crate::config::MiddlewareConfiguration,
+    ) -> anyhow::Result<Self> {
+        let listener_index_option = match &middleware_configuration.listener_bind_address {
+            None => None,
+            Some(bind_address) => Some(
+                crate::config::instance()
+                    .server_configuration
+                    .listeners
+                    .iter()
+                    .position(|listener| &listener.bind_address == bind_address)
+                    .with_context(|| {
+                        format!("no listener with bind_address = {:?}", bind_address)
+                    })?,
+            ),
+        };
+
+        Ok(Self {
+            listener_index_option,
+            path_prefix_option: middleware_configuration.path_prefix.clone(),
+        })
+    }
+
+    fn matches(&self, request: &HttpRequest) -> bool {
+        self.listener_index_option
+            .is_none_or(|listener_index| listener_index == request.listener_index)
+            && self
+                .path_prefix_option
+                .as_deref()
+                .is_none_or(|path_prefix| path_has_prefix(&request.normalized_path, path_prefix))
+    }
+}
+
+fn create_middleware(middleware_type: MiddlewareType) -> Box<dyn Middleware> {
+    match middleware_type {
+        MiddlewareType::RequestLog => Box::new(request_log::RequestLogMiddleware),
+        MiddlewareType::CanonicalRedirect => {
+            Box::new(canonical_redirect::CanonicalRedirectMiddleware::new())
+        }
+        MiddlewareType::RateLimit => Box::new(rate_limit::RateLimitMiddleware::new()),
+    }
+}
+
+struct MiddlewareChain {
+    middlewares: Vec<(MiddlewareScope, Box<dyn Middleware>)>,
+    handler: Box<dyn RequestHandler>,
+}
+
+#[async_trait]
+impl RequestHandler for MiddlewareChain {
+    async fn handle(&self, request: &HttpRequest) -> Response<ResponseBody> {
+        let middlewares: Vec<&dyn Middleware> = self
+            .middlewares
+            .iter()
+            .filter(|(scope, _)| scope.matches(request))
+            .map(|(_, middleware)| middleware.as_ref())
+            .collect();
+
+        let mut entered = 0;
+        let mut response_option = None;
+
+        for middleware in &middlewares {
+            entered += 1;
+
+            if let Some(response) = middleware.before(request).await {
+                debug!("middleware {} returned response", middleware.name());
+                response_option = Some(response);
+                break;
+            }
+        }
+
+        let mut response = match response_option {
+            Some(response) => response,
+            None => self.handler.handle(request).await,
+        };
+
+        for middleware in middlewares[..entered].iter().rev() {
+            middleware.after(request, &mut response).await;
+        }
+
+        response
+    }
+}
+
+/// Rules are only enforced by their middleware, so configuring them without it is an error.
+fn check_required_middlewares(configuration: &Configuration) -> anyhow::Result<()> {
+    let has_middleware = |is_type: fn(&MiddlewareType) -> bool| {
+        configuration
+            .middlewares
+            .iter()
+            .any(|middleware_configuration| is_type(&middleware_configuration.middleware_type))
+    };
+
+    if !configuration.rate_limit_rules.is_empty()
+        && !has_middleware(|t| matches!(t, MiddlewareType::RateLimit))
+    {
+        anyhow::bail!("rate_limit_rules are configured but middlewares has no RATE_LIMIT");
+    }
+
+    let server_configuration = &configuration.server_configuration;
+
+    let has_canonical_redirect_rules = !server_configuration.canonical_redirect_rules.is_empty()
+        || server_configuration
+            .listeners
+            .iter()
+            .any(|listener| !listener.canonical_redirect_rules.is_empty());
+
+    if has_canonical_redirect_rules
+        && !has_middleware(|t| matches!(t, MiddlewareType::CanonicalRedirect))
+    {
+        anyhow::bail!(
+            "canonical_redirect_rules are configured but middlewares has no CANONICAL_REDIRECT"
+        );
+    }
+
+    Ok(())
+}
+
+/// Wrap handler in the configured middlewares, the first configured middleware is outermost.
+pub fn create_middleware_chain(
+    handler: Box<dyn RequestHandler>,
+) -> anyhow::Result<Box<dyn RequestHandler>> {
+    check_required_middlewares(crate::config::instance())
+        .context("create_middleware_chain: missing middleware")?;
+
+    let middlewares = crate::config::instance()
+        .middlewares
+        .iter()
+        .map(|middleware_configuration| {
+            let scope = MiddlewareScope::new(middleware_configuration).with_context(|| {
+                format!(
+                    "create_middleware_chain: error in middleware_type = {:?}",
+                    middleware_configuration.middleware_type
+                )
+            })?;
+
+            Ok((
+                scope,
+                create_middleware(middleware_configuration.middleware_type),
+            ))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    debug!(
+        "middlewares = {:?}",
+        middlewares
+            .iter()
+            .map(|(scope, middleware)| (middleware.name(), scope))
+            .collect::<Vec<_>>()
+    );
+
+    Ok(Box::new(MiddlewareChain {
+        middlewares,
+        handler,
+    }))
+}
@@ -0,0 +1,44 @@
+use async_trait::async_trait;
+
+use hyper::http::Response;
+
+use crate::{
+    handlers::{middleware::Middleware, HttpRequest, ResponseBody},
+    service::{
+        canonical_redirect::CanonicalRedirectService, forwarded_headers::ForwardedHeadersService,
+    },
+};
+
+/// Redirects to the canonical scheme and host using the rules of the request's listener.
+pub struct CanonicalRedirectMiddleware {
+    canonical_redirect_service: &'static CanonicalRedirectService,
+    forwarded_headers_service: &'static ForwardedHeadersService,
+}
+
+impl CanonicalRedirectMiddleware {
+    pub fn new() -> Self {
+        Self {
+            canonical_redirect_service:
+                crate::service::canonical_redirect::canonical_redirect_service_instance(),
+            forwarded_headers_service:
+                crate::service::forwarded_headers::forwarded_headers_service_instance(),
+        }
+    }
+}
+
+#[async_trait]
+impl Middleware for CanonicalRedirectMiddleware {
+    fn name(&self) -> &'static str {
+        "canonical_redirect"
+    }
+
+    async fn before(&self, request: &HttpRequest) -> Option<Response<ResponseBody>> {
+        let request_scheme = self
+            .forwarded_headers_service
+            .request_scheme(request.hyper_request.headers(), request.peer_ip);
+
+        self.canonical_redirect_service
+            .listener_rules(request.listener_index)
+            .redirect(request, request_scheme)
+    }
+}
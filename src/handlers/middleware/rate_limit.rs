@@ -0,0 +1,69 @@
+use async_trait::async_trait;
+
+use hyper::http::{header, HeaderName, HeaderValue, Response, StatusCode};
+
+use tracing::debug;
+
+use crate::{
+    handlers::{middleware::Middleware, HttpRequest, ResponseBody},
+    service::{error_page::ErrorPageService, rate_limit::RateLimitService},
+};
+
+/// Answers 429 when the client's token bucket for the request path is empty.
+pub struct RateLimitMiddleware {
+    rate_limit_service: &'static RateLimitService,
+    error_page_service: &'static ErrorPageService,
+}
+
+impl RateLimitMiddleware {
+    pub fn new() -> Self {
+        Self {
+            rate_limit_service: crate::service::rate_limit::rate_limit_service_instance(),
+            error_page_service: crate::service::error_page::error_page_service_instance(),
+        }
+    }
+}
+
+#[async_trait]
+impl Middleware for RateLimitMiddleware {
+    fn name(&self) -> &'static str {
+        "rate_limit"
+    }
+
+    async fn before(&self, request: &HttpRequest) -> Option<Response<ResponseBody>> {
+        let rejection = self.rate_limit_service.check(request).err()?;
+
+        debug!("rate limited rejection = {:?}", rejection);
+
+        let mut response = self
+            .error_page_service
+            .build_error_page_response(request, StatusCode::TOO_MANY_REQUESTS)
+            .await;
+
+        let headers = response.headers_mut();
+
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+
+        headers.insert(
+            header::RETRY_AFTER,
+            HeaderValue::from(rejection.retry_after.as_secs().max(1)),
+        );
+
+        headers.insert(
+            HeaderName::from_static("ratelimit-limit"),
+            HeaderValue::from(rejection.limit),
+        );
+
+        headers.insert(
+            HeaderName::from_static("ratelimit-remaining"),
+            HeaderValue::from(0),
+        );
+
+        headers.insert(
+            HeaderName::from_static("ratelimit-reset"),
+            HeaderValue::from(rejection.reset.as_secs()),
+        );
+
+        Some(response)
+    }
+}
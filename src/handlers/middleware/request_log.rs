@@ -0,0 +1,35 @@
+use async_trait::async_trait;
+
+use hyper::http::Response;
+
+use tracing::{debug, info, warn};
+
+use crate::handlers::{middleware::Middleware, HttpRequest, ResponseBody};
+
+/// Records duration and status on the request span and logs at a level chosen by status.
+pub struct RequestLogMiddleware;
+
+#[async_trait]
+impl Middleware for RequestLogMiddleware {
+    fn name(&self) -> &'static str {
+        "request_log"
+    }
+
+    async fn after(&self, request: &HttpRequest, response: &mut Response<ResponseBody>) {
+        let duration = request.start_instant.elapsed();
+
+        let status = response.status();
+
+        tracing::Span::current()
+            .record("micros", duration.as_micros())
+            .record("status", status.as_u16());
+
+        if status.is_informational() || status.is_success() || status.is_redirection() {
+            debug!("request complete");
+        } else if status.is_client_error() {
+            info!("request complete");
+        } else {
+            warn!("request complete");
+        };
+    }
+}
@@ -0,0 +1,150 @@
+use anyhow::Context;
+
+use async_trait::async_trait;
+
+use hyper::http::{Method, Response};
+
+use schemars::{generate::SchemaSettings, Schema, SchemaGenerator};
+
+use serde_json::{json, Map, Value};
+
+use tracing::info;
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    handlers::{route::RouteInfo, HttpRequest, RequestHandler, ResponseBody},
+    response::{build_static_json_response, CacheControl, StaticJson},
+};
+
+const OPENAPI_VERSION: &str = "3.0.3";
+
+const OPENAPI_PATH_SUFFIX: &str = "openapi.json";
+
+const OPENAPI_SUMMARY: &str = "OpenAPI document for the dynamic routes";
+
+fn operation_id(path_suffix: &Path) -> String {
+    path_suffix
+        .to_string_lossy()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+fn add_operation(
+    paths: &mut Map<String, Value>,
+    path: String,
+    method: &Method,
+    path_suffix: &Path,
+    summary: &str,
+    response_schema: Schema,
+) {
+    let path_item = paths
+        .entry(path)
+        .or_insert_with(|| Value::Object(Map::new()));
+
+    path_item[method.as_str().to_ascii_lowercase()] = json!({
+        "summary": summary,
+        "operationId": operation_id(path_suffix),
+        "responses": {
+            "200": {
+                "description": "OK",
+                "content": {
+                    "application/json": {
+                        "schema": response_schema,
+                    },
+                },
+            },
+            "304": {
+                "description": "Not Modified, the ETag matches If-None-Match",
+            },
+        },
+    });
+}
+
+/// OpenAPI document for routes and the openapi.json route itself.
+pub fn build_openapi_document(routes: &[RouteInfo]) -> anyhow::Result<Value> {
+    let context_path = Path::new(
+        &crate::config::instance()
+            .context_configuration
+            .dynamic_route_context,
+    );
+
+    let mut generator = SchemaSettings::openapi3().for_serialize().into_generator();
+
+    let mut paths = Map::new();
+
+    for route in routes {
+        add_operation(
+            &mut paths,
+            route.path(context_path)?,
+            route.method,
+            &route.path_suffix,
+            route.summary,
+            (route.response_schema)(&mut generator),
+        );
+    }
+
+    add_operation(
+        &mut paths,
+        context_path
+            .join(OPENAPI_PATH_SUFFIX)
+            .to_string_lossy()
+            .into_owned(),
+        &Method::GET,
+        Path::new(OPENAPI_PATH_SUFFIX),
+        OPENAPI_SUMMARY,
+        generator.subschema_for::<Value>(),
+    );
+
+    Ok(json!({
+        "openapi": OPENAPI_VERSION,
+        "info": {
+            "title": env!("CARGO_PKG_NAME"),
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": paths,
+        "components": {
+            "schemas": generator.take_definitions(true),
+        },
+    }))
+}
+
+pub async fn write_openapi_document(routes: &[RouteInfo], output_file: &str) -> anyhow::Result<()> {
+    let document = build_openapi_document(routes)?;
+
+    let json = serde_json::to_vec_pretty(&document)
+        .context("write_openapi_document: serialization error")?;
+
+    tokio::fs::write(output_file, json)
+        .await
+        .with_context(|| format!("write_openapi_document: error writing '{}'", output_file))?;
+
+    info!("wrote openapi document to '{}'", output_file);
+
+    Ok(())
+}
+
+struct OpenApiHandler {
+    static_json: StaticJson,
+}
+
+#[async_trait]
+impl RequestHandler for OpenApiHandler {
+    async fn handle(&self, request: &HttpRequest) -> Response<ResponseBody> {
+        build_static_json_response(request, &self.static_json, CacheControl::NoCache)
+    }
+}
+
+pub fn create_routes(routes: &[RouteInfo]) -> anyhow::Result<Vec<RouteInfo>> {
+    let static_json = StaticJson::new(build_openapi_document(routes)?)
+        .context("openapi::create_routes: error serializing openapi document")?;
+
+    Ok(vec![RouteInfo {
+        method: &Method::GET,
+        path_suffix: PathBuf::from(OPENAPI_PATH_SUFFIX),
+        summary: OPENAPI_SUMMARY,
+        response_schema: SchemaGenerator::subschema_for::<Value>,
+        handler: Box::new(OpenApiHandler { static_json }),
+    }])
+}
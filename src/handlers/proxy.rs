@@ -0,0 +1,853 @@
+use anyhow::Context;
+
+use async_trait::async_trait;
+
+use bytes::Bytes;
+
+use http_body_util::{combinators::BoxBody, BodyExt};
+
+use hyper::{
+    body::Incoming,
+    http::{
+        header, HeaderMap, HeaderName, HeaderValue, Request, Response, StatusCode, Uri, Version,
+    },
+    rt::{Read, ReadBufCursor, Write},
+};
+
+use hyper_util::{
+    client::legacy::{
+        connect::{Connected, Connection, HttpConnector},
+        Client,
+    },
+    rt::{TokioExecutor, TokioIo, TokioTimer},
+};
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+
+use tokio::net::UnixStream;
+
+use tracing::{debug, warn};
+
+use std::{
+    error::Error as StdError,
+    future::Future,
+    io,
+    net::IpAddr,
+    path::PathBuf,
+    pin::Pin,
+    sync::Arc,
+    task::{Context as TaskContext, Poll},
+    time::Duration,
+};
+
+use crate::{
+    config::{ProxyRoute, ProxyUpstreamConfiguration, ProxyUpstreamProtocol, ServerSocketType},
+    handlers::{HttpRequest, RequestHandler, ResponseBody},
+    response::ResponseBodyError,
+    service::{
+        error_page::ErrorPageService, forwarded_headers::ForwardedHeadersService,
+        request_id::RequestIdService, request_matcher::normalize_path,
+    },
+};
+
+type UpstreamRequestBody = BoxBody<Bytes, Box<dyn StdError + Send + Sync>>;
+
+const UNIX_UPSTREAM_AUTHORITY: &str = "localhost";
+
+/// Characters encoded again when a normalized path is sent upstream.
+const UPSTREAM_PATH_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+const X_FORWARDED_HOST: HeaderName = HeaderName::from_static("x-forwarded-host");
+const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+const X_FORWARDED_PREFIX: HeaderName = HeaderName::from_static("x-forwarded-prefix");
+
+const HOP_BY_HOP_HEADERS: [HeaderName; 8] = [
+    header::CONNECTION,
+    HeaderName::from_static("keep-alive"),
+    HeaderName::from_static("proxy-connection"),
+    header::PROXY_AUTHENTICATE,
+    header::TE,
+    header::TRAILER,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+];
+
+/// Remove hop-by-hop headers, including those named in the Connection header.
+fn remove_hop_by_hop_headers(headers: &mut HeaderMap) {
+    let connection_header_names: Vec<HeaderName> = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| HeaderName::try_from(name.trim()).ok())
+        .collect();
+
+    for name in connection_header_names
+        .iter()
+        .chain(HOP_BY_HOP_HEADERS.iter())
+    {
+        headers.remove(name);
+    }
+}
+
+/// True if any error in the source chain is an io timeout, e.g. the connect timeout.
+fn is_timeout(error: &(dyn StdError + 'static)) -> bool {
+    let mut source_option = Some(error);
+
+    while let Some(source) = source_option {
+        if source
+            .downcast_ref::<io::Error>()
+            .is_some_and(|io_error| io_error.kind() == io::ErrorKind::TimedOut)
+        {
+            return true;
+        }
+        source_option = source.source();
+    }
+
+    false
+}
+
+#[derive(thiserror::Error, Debug)]
+enum ProxyError {
+    #[error("request body already taken")]
+    BodyTaken,
+
+    #[error("error building upstream request: {0}")]
+    BuildRequest(#[from] hyper::http::Error),
+
+    #[error("upstream request timeout: {0}")]
+    RequestTimeout(#[from] tokio::time::error::Elapsed),
+
+    #[error("upstream error: {0}")]
+    Upstream(#[from] hyper_util::client::legacy::Error),
+}
+
+impl ProxyError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::RequestTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            Self::Upstream(error) if is_timeout(error) => StatusCode::GATEWAY_TIMEOUT,
+            _ => StatusCode::BAD_GATEWAY,
+        }
+    }
+}
+
+/// Connects to a UNIX socket upstream regardless of the request uri.
+#[derive(Clone)]
+struct UnixConnector {
+    path: Arc<PathBuf>,
+    connect_timeout: Duration,
+}
+
+impl tower_service::Service<Uri> for UnixConnector {
+    type Response = UnixConnection;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<UnixConnection>> + Send>>;
+
+    fn poll_ready(&mut self, _: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _: Uri) -> Self::Future {
+        let path = Arc::clone(&self.path);
+        let connect_timeout = self.connect_timeout;
+
+        Box::pin(async move {
+            let stream = tokio::time::timeout(connect_timeout, UnixStream::connect(path.as_ref()))
+                .await
+                .map_err(|elapsed| io::Error::new(io::ErrorKind::TimedOut, elapsed))??;
+
+            Ok(UnixConnection(TokioIo::new(stream)))
+        })
+    }
+}
+
+struct UnixConnection(TokioIo<UnixStream>);
+
+impl Connection for UnixConnection {
+    fn connected(&self) -> Connected {
+        Connected::new()
+    }
+}
+
+impl Read for UnixConnection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl Write for UnixConnection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.0.is_write_vectored()
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write_vectored(cx, bufs)
+    }
+}
+
+enum UpstreamClient {
+    Tcp(Client<HttpConnector, UpstreamRequestBody>),
+    Unix(Client<UnixConnector, UpstreamRequestBody>),
+}
+
+/// Pooled client for one upstream, with its own timeouts.
+struct ProxyUpstream {
+    client: UpstreamClient,
+    authority: String,
+    version: Version,
+    request_timeout: Duration,
+}
+
+impl ProxyUpstream {
+    fn new(upstream_configuration: &ProxyUpstreamConfiguration) -> anyhow::Result<Self> {
+        let mut builder = Client::builder(TokioExecutor::new());
+
+        builder
+            .timer(TokioTimer::new())
+            .pool_timer(TokioTimer::new())
+            .pool_idle_timeout(upstream_configuration.pool_idle_timeout)
+            .pool_max_idle_per_host(upstream_configuration.pool_max_idle);
+
+        let version = match upstream_configuration.protocol {
+            ProxyUpstreamProtocol::Http1 => Version::HTTP_11,
+            ProxyUpstreamProtocol::H2c => {
+                builder.http2_only(true);
+                Version::HTTP_2
+            }
+        };
+
+        let (client, authority) = match upstream_configuration.socket_type {
+            ServerSocketType::Tcp => {
+                let mut connector = HttpConnector::new();
+                connector.set_connect_timeout(Some(upstream_configuration.connect_timeout));
+
+                (
+                    UpstreamClient::Tcp(builder.build(connector)),
+                    upstream_configuration.address.clone(),
+                )
+            }
+            ServerSocketType::Unix => (
+                UpstreamClient::Unix(builder.build(UnixConnector {
+                    path: Arc::new(PathBuf::from(&upstream_configuration.address)),
+                    connect_timeout: upstream_configuration.connect_timeout,
+                })),
+                UNIX_UPSTREAM_AUTHORITY.to_owned(),
+            ),
+        };
+
+        let upstream = Self {
+            client,
+            authority,
+            version,
+            request_timeout: upstream_configuration.request_timeout,
+        };
+
+        upstream.uri("/").with_context(|| {
+            format!(
+                "ProxyUpstream::new: invalid upstream address {:?}",
+                upstream_configuration.address
+            )
+        })?;
+
+        Ok(upstream)
+    }
+
+    fn uri(&self, path_and_query: &str) -> Result<Uri, hyper::http::Error> {
+        Uri::builder()
+            .scheme("http")
+            .authority(self.authority.as_str())
+            .path_and_query(path_and_query)
+            .build()
+    }
+
+    /// Send the request, timing out if response headers do not arrive within request_timeout.
+    async fn send(
+        &self,
+        request: Request<UpstreamRequestBody>,
+    ) -> Result<Response<Incoming>, ProxyError> {
+        let response_future = match &self.client {
+            UpstreamClient::Tcp(client) => client.request(request),
+            UpstreamClient::Unix(client) => client.request(request),
+        };
+
+        Ok(tokio::time::timeout(self.request_timeout, response_future).await??)
+    }
+}
+
+/// A path prefix and the upstream its requests are forwarded to.
+struct ProxyTarget {
+    path_prefix: String,
+    path_prefix_value: HeaderValue,
+    rewrite_prefix: Option<String>,
+    upstream: ProxyUpstream,
+}
+
+impl ProxyTarget {
+    fn new(proxy_route: &ProxyRoute) -> anyhow::Result<Self> {
+        if !proxy_route.path_prefix.starts_with('/') {
+            anyhow::bail!(
+                "ProxyTarget::new: path_prefix {:?} must start with '/'",
+                proxy_route.path_prefix
+            );
+        }
+
+        if let Some(rewrite_prefix) = &proxy_route.rewrite_prefix {
+            if !(rewrite_prefix.is_empty() || rewrite_prefix.starts_with('/')) {
+                anyhow::bail!(
+                    "ProxyTarget::new: rewrite_prefix {:?} must be empty or start with '/'",
+                    rewrite_prefix
+                );
+            }
+        }
+
+        let path_prefix_value = HeaderValue::try_from(proxy_route.path_prefix.as_str())
+            .with_context(|| {
+                format!(
+                    "ProxyTarget::new: invalid path_prefix {:?}",
+                    proxy_route.path_prefix
+                )
+            })?;
+
+        let upstream = ProxyUpstream::new(&proxy_route.upstream).with_context(|| {
+            format!(
+                "ProxyTarget::new: error creating upstream for path_prefix {:?}",
+                proxy_route.path_prefix
+            )
+        })?;
+
+        Ok(Self {
+            path_prefix: proxy_route.path_prefix.clone(),
+            path_prefix_value,
+            rewrite_prefix: proxy_route.rewrite_prefix.clone(),
+            upstream,
+        })
+    }
+
+    /// Replace path_prefix with rewrite_prefix, keeping the query string.
+    ///
+    /// The prefix is replaced in the normalized path the route was selected by,
+    /// without rewrite_prefix the request path is forwarded as received.
+    fn upstream_path_and_query(&self, uri: &Uri) -> String {
+        let path = match &self.rewrite_prefix {
+            None => uri.path().to_owned(),
+            Some(_) => utf8_percent_encode(&normalize_path(uri.path()), UPSTREAM_PATH_ENCODE_SET)
+                .to_string(),
+        };
+
+        let mut path_and_query = match (&self.rewrite_prefix, path.get(self.path_prefix.len()..)) {
+            (Some(rewrite_prefix), Some(rest)) => {
+                if rest.is_empty() && !self.path_prefix.ends_with('/') {
+                    rewrite_prefix.clone()
+                } else {
+                    format!(
+                        "{}/{}",
+                        rewrite_prefix.trim_end_matches('/'),
+                        rest.strip_prefix('/').unwrap_or(rest)
+                    )
+                }
+            }
+            _ => path,
+        };
+
+        if path_and_query.is_empty() {
+            path_and_query.push('/');
+        }
+
+        if let Some(query) = uri.query() {
+            path_and_query.push('?');
+            path_and_query.push_str(query);
+        }
+
+        path_and_query
+    }
+
+    fn build_upstream_request(
+        &self,
+        request_head: &Request<()>,
+        peer_ip: Option<IpAddr>,
+        trusted_peer: bool,
+        request_scheme: &'static str,
+        body: UpstreamRequestBody,
+    ) -> Result<Request<UpstreamRequestBody>, ProxyError> {
+        let uri = self
+            .upstream
+            .uri(&self.upstream_path_and_query(request_head.uri()))?;
+
+        let mut headers = request_head.headers().clone();
+
+        remove_hop_by_hop_headers(&mut headers);
+
+        // only a trusted proxy in front of us can vouch for the original client.
+        if !trusted_peer {
+            for name in [
+                &X_FORWARDED_FOR,
+                &X_FORWARDED_HOST,
+                &X_FORWARDED_PROTO,
+                &X_FORWARDED_PREFIX,
+            ] {
+                headers.remove(name);
+            }
+        }
+
+        // the client sets Host or :authority for the upstream from the uri.
+        let original_host = headers.remove(header::HOST).or_else(|| {
+            request_head
+                .uri()
+                .authority()
+                .and_then(|authority| HeaderValue::try_from(authority.as_str()).ok())
+        });
+
+        if let Some(peer_ip) = peer_ip {
+            let forwarded_for = headers
+                .get_all(&X_FORWARDED_FOR)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .map(str::to_owned)
+                .chain(std::iter::once(peer_ip.to_string()))
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            headers.remove(&X_FORWARDED_FOR);
+            if let Ok(forwarded_for) = HeaderValue::try_from(forwarded_for) {
+                headers.insert(X_FORWARDED_FOR, forwarded_for);
+            }
+        }
+
+        if !headers.contains_key(&X_FORWARDED_HOST) {
+            if let Some(original_host) = original_host {
+                headers.insert(X_FORWARDED_HOST, original_host);
+            }
+        }
+
+        headers.insert(X_FORWARDED_PROTO, HeaderValue::from_static(request_scheme));
+
+        if self.rewrite_prefix.is_some() {
+            headers.insert(X_FORWARDED_PREFIX, self.path_prefix_value.clone());
+        }
+
+        let mut upstream_request = Request::builder()
+            .method(request_head.method().clone())
+            .uri(uri)
+            .version(self.upstream.version)
+            .body(body)?;
+
+        *upstream_request.headers_mut() = headers;
+
+        Ok(upstream_request)
+    }
+
+    /// Forward the request and stream the upstream response back.
+    async fn forward(
+        &self,
+        upstream_request: Request<UpstreamRequestBody>,
+    ) -> Result<Response<ResponseBody>, ProxyError> {
+        let mut response = self.upstream.send(upstream_request).await?;
+
+        remove_hop_by_hop_headers(response.headers_mut());
+
+        Ok(response.map(|body| body.map_err(ResponseBodyError::from).boxed()))
+    }
+}
+
+/// Forwards requests under a path prefix to an upstream HTTP/1 or h2c server.
+pub struct ProxyHandler {
+    proxy_route: &'static ProxyRoute,
+    target: ProxyTarget,
+    request_id_service: &'static RequestIdService,
+    forwarded_headers_service: &'static ForwardedHeadersService,
+    error_page_service: &'static ErrorPageService,
+}
+
+impl ProxyHandler {
+    fn new(proxy_route: &'static ProxyRoute) -> anyhow::Result<Self> {
+        Ok(Self {
+            proxy_route,
+            target: ProxyTarget::new(proxy_route)?,
+            request_id_service: crate::service::request_id::request_id_service_instance(),
+            forwarded_headers_service:
+                crate::service::forwarded_headers::forwarded_headers_service_instance(),
+            error_page_service: crate::service::error_page::error_page_service_instance(),
+        })
+    }
+
+    pub fn proxy_route(&self) -> &'static ProxyRoute {
+        self.proxy_route
+    }
+
+    pub fn path_prefix(&self) -> &str {
+        &self.proxy_route.path_prefix
+    }
+
+    async fn proxy(&self, request: &HttpRequest) -> Result<Response<ResponseBody>, ProxyError> {
+        let body = request.take_body().ok_or(ProxyError::BodyTaken)?;
+
+        let headers = request.hyper_request.headers();
+
+        let mut upstream_request = self.target.build_upstream_request(
+            &request.hyper_request,
+            request.peer_ip,
+            self.forwarded_headers_service.is_trusted(request.peer_ip),
+            self.forwarded_headers_service
+                .request_scheme(headers, request.peer_ip),
+            body.boxed(),
+        )?;
+
+        upstream_request.headers_mut().insert(
+            self.request_id_service.header_name().clone(),
+            request.external_request_id.clone(),
+        );
+
+        debug!("upstream uri = {:?}", upstream_request.uri());
+
+        self.target.forward(upstream_request).await
+    }
+}
+
+#[async_trait]
+impl RequestHandler for ProxyHandler {
+    async fn handle(&self, request: &HttpRequest) -> Response<ResponseBody> {
+        match self.proxy(request).await {
+            Ok(response) => response,
+            Err(e) => {
+                warn!(
+                    "proxy error path_prefix = {:?}: {}",
+                    self.target.path_prefix, e
+                );
+
+                self.error_page_service
+                    .build_error_page_response(request, e.status_code())
+                    .await
+            }
+        }
+    }
+}
+
+/// Handlers for the configured proxy_routes, longest path_prefix first.
+pub fn create_proxy_handlers() -> anyhow::Result<Vec<ProxyHandler>> {
+    let mut proxy_handlers = crate::config::instance()
+        .proxy_routes
+        .iter()
+        .map(ProxyHandler::new)
+        .collect::<anyhow::Result<Vec<_>>>()
+        .context("create_proxy_handlers: error in proxy_routes")?;
+
+    proxy_handlers.sort_by(|a, b| {
+        b.path_prefix()
+            .len()
+            .cmp(&a.path_prefix().len())
+            .then_with(|| a.path_prefix().cmp(b.path_prefix()))
+    });
+
+    if let Some(duplicate) = proxy_handlers
+        .windows(2)
+        .find(|pair| pair[0].path_prefix() == pair[1].path_prefix())
+    {
+        anyhow::bail!(
+            "create_proxy_handlers: duplicate path_prefix {:?}",
+            duplicate[0].path_prefix()
+        );
+    }
+
+    Ok(proxy_handlers)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use http_body_util::Full;
+
+    use hyper::{server::conn, service::service_fn};
+
+    use tokio::net::{TcpListener, UnixListener};
+
+    fn proxy_route(
+        socket_type: ServerSocketType,
+        address: String,
+        protocol: ProxyUpstreamProtocol,
+        rewrite_prefix: Option<&str>,
+        request_timeout: Duration,
+    ) -> ProxyRoute {
+        ProxyRoute {
+            path_prefix: "/app".to_owned(),
+            rewrite_prefix: rewrite_prefix.map(str::to_owned),
+            upstream: ProxyUpstreamConfiguration {
+                socket_type,
+                address,
+                protocol,
+                connect_timeout: Duration::from_secs(1),
+                request_timeout,
+                pool_idle_timeout: Duration::from_secs(10),
+                pool_max_idle: 1,
+            },
+        }
+    }
+
+    fn full_body(body: &'static str) -> UpstreamRequestBody {
+        Full::new(Bytes::from_static(body.as_bytes()))
+            .map_err(|never| match never {})
+            .boxed()
+    }
+
+    /// Stand-in upstream answering with the request line, forwarding headers and body.
+    async fn echo(request: Request<Incoming>) -> Result<Response<Full<Bytes>>, hyper::Error> {
+        let (parts, body) = request.into_parts();
+        let body = body.collect().await?.to_bytes();
+
+        let header = |name: &HeaderName| {
+            parts
+                .headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_owned()
+        };
+
+        let echo = format!(
+            "{} {}\nhost={}\nfor={}\nforwarded_host={}\nproto={}\nprefix={}\nbody={}",
+            parts.method,
+            parts
+                .uri
+                .path_and_query()
+                .map(|p| p.as_str())
+                .unwrap_or_default(),
+            header(&header::HOST),
+            header(&X_FORWARDED_FOR),
+            header(&X_FORWARDED_HOST),
+            header(&X_FORWARDED_PROTO),
+            header(&X_FORWARDED_PREFIX),
+            String::from_utf8_lossy(&body),
+        );
+
+        Ok(Response::builder()
+            .header(header::CONNECTION, "x-hop")
+            .header("x-hop", "1")
+            .body(Full::new(Bytes::from(echo)))
+            .unwrap())
+    }
+
+    async fn forward(
+        target: &ProxyTarget,
+        trusted_peer: bool,
+        request_scheme: &'static str,
+    ) -> (StatusCode, HeaderMap, String) {
+        let request_head = Request::builder()
+            .method("POST")
+            .uri("/app/items?id=1")
+            .header(header::HOST, "www.example.com")
+            .header(X_FORWARDED_FOR, "192.0.2.1")
+            .header(X_FORWARDED_HOST, "client.example.com")
+            .header(X_FORWARDED_PROTO, "https")
+            .header(X_FORWARDED_PREFIX, "/outer")
+            .body(())
+            .unwrap();
+
+        let upstream_request = target
+            .build_upstream_request(
+                &request_head,
+                Some("198.51.100.7".parse().unwrap()),
+                trusted_peer,
+                request_scheme,
+                full_body("hello"),
+            )
+            .unwrap();
+
+        let response = target.forward(upstream_request).await.unwrap();
+        let (parts, body) = response.into_parts();
+        let body = body.collect().await.unwrap().to_bytes();
+
+        (
+            parts.status,
+            parts.headers,
+            String::from_utf8_lossy(&body).into_owned(),
+        )
+    }
+
+    #[test]
+    fn test_upstream_path_and_query() {
+        let target = |rewrite_prefix| {
+            ProxyTarget::new(&proxy_route(
+                ServerSocketType::Tcp,
+                "127.0.0.1:1".to_owned(),
+                ProxyUpstreamProtocol::Http1,
+                rewrite_prefix,
+                Duration::from_secs(1),
+            ))
+            .unwrap()
+        };
+
+        let path = |target: &ProxyTarget, uri: &'static str| {
+            target.upstream_path_and_query(&Uri::from_static(uri))
+        };
+
+        let unchanged = target(None);
+        assert_eq!(path(&unchanged, "/app/x?a=b"), "/app/x?a=b");
+        assert_eq!(path(&unchanged, "//app/%78"), "//app/%78");
+
+        let strip = target(Some(""));
+        assert_eq!(path(&strip, "/app"), "/");
+        assert_eq!(path(&strip, "/app/"), "/");
+        assert_eq!(path(&strip, "/app/x/y?a=b"), "/x/y?a=b");
+        assert_eq!(path(&strip, "//app/./x"), "/x");
+        assert_eq!(path(&strip, "/%61pp/a%20b%3F"), "/a%20b%3F");
+        assert_eq!(path(&strip, "/app/../app/x"), "/x");
+
+        let rewrite = target(Some("/v2/"));
+        assert_eq!(path(&rewrite, "/app"), "/v2/");
+        assert_eq!(path(&rewrite, "/app/x"), "/v2/x");
+
+        let rewrite = target(Some("/v2"));
+        assert_eq!(path(&rewrite, "/app"), "/v2");
+        assert_eq!(path(&rewrite, "/app/x"), "/v2/x");
+    }
+
+    #[tokio::test]
+    async fn test_proxy_forward() {
+        let tcp_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tcp_address = tcp_listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = tcp_listener.accept().await {
+                tokio::spawn(
+                    conn::http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service_fn(echo)),
+                );
+            }
+        });
+
+        let unix_path =
+            std::env::temp_dir().join(format!("rhs-proxy-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&unix_path);
+        let unix_listener = UnixListener::bind(&unix_path).unwrap();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = unix_listener.accept().await {
+                tokio::spawn(
+                    conn::http2::Builder::new(TokioExecutor::new())
+                        .serve_connection(TokioIo::new(stream), service_fn(echo)),
+                );
+            }
+        });
+
+        let tcp_target = ProxyTarget::new(&proxy_route(
+            ServerSocketType::Tcp,
+            tcp_address.clone(),
+            ProxyUpstreamProtocol::Http1,
+            Some(""),
+            Duration::from_secs(5),
+        ))
+        .unwrap();
+
+        let (status, headers, body) = forward(&tcp_target, true, "https").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(!headers.contains_key("x-hop"));
+        assert_eq!(
+            body,
+            format!(
+                "POST /items?id=1\nhost={}\nfor=192.0.2.1, 198.51.100.7\nforwarded_host=client.example.com\nproto=https\nprefix=/app\nbody=hello",
+                tcp_address
+            )
+        );
+
+        // forwarding headers from an untrusted peer are replaced.
+        let (status, _, body) = forward(&tcp_target, false, "http").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body,
+            format!(
+                "POST /items?id=1\nhost={}\nfor=198.51.100.7\nforwarded_host=www.example.com\nproto=http\nprefix=/app\nbody=hello",
+                tcp_address
+            )
+        );
+
+        let unix_target = ProxyTarget::new(&proxy_route(
+            ServerSocketType::Unix,
+            unix_path.to_string_lossy().into_owned(),
+            ProxyUpstreamProtocol::H2c,
+            None,
+            Duration::from_secs(5),
+        ))
+        .unwrap();
+
+        let (status, _, body) = forward(&unix_target, false, "http").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.starts_with("POST /app/items?id=1\n"));
+        assert!(body.contains("\nprefix=\n"));
+        assert!(body.ends_with("\nbody=hello"));
+
+        let _ = std::fs::remove_file(&unix_path);
+    }
+
+    #[tokio::test]
+    async fn test_proxy_errors() {
+        let refused_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let refused_address = refused_listener.local_addr().unwrap().to_string();
+        drop(refused_listener);
+
+        let silent_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let silent_address = silent_listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let mut streams = Vec::new();
+            while let Ok((stream, _)) = silent_listener.accept().await {
+                streams.push(stream);
+            }
+        });
+
+        let request_head = Request::builder().uri("/app").body(()).unwrap();
+
+        for (address, expected_status_code) in [
+            (refused_address, StatusCode::BAD_GATEWAY),
+            (silent_address, StatusCode::GATEWAY_TIMEOUT),
+        ] {
+            let target = ProxyTarget::new(&proxy_route(
+                ServerSocketType::Tcp,
+                address,
+                ProxyUpstreamProtocol::Http1,
+                None,
+                Duration::from_millis(200),
+            ))
+            .unwrap();
+
+            let upstream_request = target
+                .build_upstream_request(&request_head, None, false, "http", full_body(""))
+                .unwrap();
+
+            let error = target.forward(upstream_request).await.unwrap_err();
+            assert_eq!(error.status_code(), expected_status_code);
+        }
+    }
+}
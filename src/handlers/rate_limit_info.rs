@@ -0,0 +1,74 @@
+use async_trait::async_trait;
+
+use hyper::http::{Method, Response};
+
+use schemars::{JsonSchema, SchemaGenerator};
+
+use serde::Serialize;
+
+use std::path::PathBuf;
+
+use crate::{
+    handlers::{route::RouteInfo, HttpRequest, RequestHandler, ResponseBody},
+    response::{build_json_response, CacheControl},
+    service::rate_limit::{RateLimitRuleSnapshot, RateLimitService, RateLimitSnapshot},
+};
+
+#[derive(Debug, Serialize, JsonSchema)]
+struct RateLimitRuleDTO {
+    path_prefix: String,
+    burst: u32,
+    refill_per_second: f64,
+    rejections: usize,
+}
+
+impl From<RateLimitRuleSnapshot> for RateLimitRuleDTO {
+    fn from(rule_snapshot: RateLimitRuleSnapshot) -> Self {
+        Self {
+            path_prefix: rule_snapshot.path_prefix,
+            burst: rule_snapshot.burst,
+            refill_per_second: rule_snapshot.refill_per_second,
+            rejections: rule_snapshot.rejections,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+struct RateLimitSnapshotDTO {
+    active_buckets: usize,
+    rules: Vec<RateLimitRuleDTO>,
+}
+
+impl From<RateLimitSnapshot> for RateLimitSnapshotDTO {
+    fn from(snapshot: RateLimitSnapshot) -> Self {
+        Self {
+            active_buckets: snapshot.active_buckets,
+            rules: snapshot.rules.into_iter().map(|rule| rule.into()).collect(),
+        }
+    }
+}
+
+struct RateLimitInfoHandler {
+    rate_limit_service: &'static RateLimitService,
+}
+
+#[async_trait]
+impl RequestHandler for RateLimitInfoHandler {
+    async fn handle(&self, request: &HttpRequest) -> Response<ResponseBody> {
+        let snapshot_dto: RateLimitSnapshotDTO = self.rate_limit_service.snapshot().into();
+
+        build_json_response(request, snapshot_dto, CacheControl::NoCache)
+    }
+}
+
+pub fn create_routes() -> Vec<RouteInfo> {
+    vec![RouteInfo {
+        method: &Method::GET,
+        path_suffix: PathBuf::from("rate_limit_info"),
+        summary: "Rate limit rules with rejection counts",
+        response_schema: SchemaGenerator::subschema_for::<RateLimitSnapshotDTO>,
+        handler: Box::new(RateLimitInfoHandler {
+            rate_limit_service: crate::service::rate_limit::rate_limit_service_instance(),
+        }),
+    }]
+}
@@ -2,6 +2,8 @@ use async_trait::async_trait;
 
 use hyper::http::{Method, Response, Version};
 
+use schemars::{JsonSchema, SchemaGenerator};
+
 use serde::Serialize;
 
 use std::{collections::BTreeMap, path::PathBuf};
@@ -11,12 +13,13 @@ use crate::{
     response::{build_json_response, CacheControl, ResponseBody},
 };
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 struct RequestFields<'a> {
     connection_id: usize,
     http_version: &'a str,
     method: &'a str,
     request_id: usize,
+    external_request_id: &'a str,
     request_uri_path: &'a str,
 }
 
@@ -38,6 +41,7 @@ impl<'a> From<&'a HttpRequest> for RequestFields<'a> {
             http_version,
             method: hyper_request.method().as_str(),
             request_id: request.request_id.as_usize(),
+            external_request_id: request.external_request_id.to_str().unwrap_or("[Unknown]"),
             request_uri_path: hyper_request.uri().path(),
         }
     }
@@ -56,7 +60,7 @@ impl<'a> From<&'a HttpRequest> for SortedRequestHeaders<'a> {
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 struct RequestInfoResponse<'a> {
     request_fields: RequestFields<'a>,
     request_headers: SortedRequestHeaders<'a>,
@@ -78,7 +82,7 @@ impl RequestHandler for RequestInfoHandler {
     async fn handle(&self, request: &HttpRequest) -> Response<ResponseBody> {
         let response: RequestInfoResponse<'_> = request.into();
 
-        build_json_response(response, CacheControl::NoCache)
+        build_json_response(request, response, CacheControl::NoCache)
     }
 }
 
@@ -86,6 +90,8 @@ pub fn create_routes() -> Vec<RouteInfo> {
     vec![RouteInfo {
         method: &Method::GET,
         path_suffix: PathBuf::from("request_info"),
+        summary: "Fields and headers of this request",
+        response_schema: SchemaGenerator::subschema_for::<RequestInfoResponse<'static>>,
         handler: Box::new(RequestInfoHandler),
     }]
 }
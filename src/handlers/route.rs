@@ -4,7 +4,9 @@ use anyhow::Context;
 
 use async_trait::async_trait;
 
-use hyper::http::{Method, Response};
+use hyper::http::{Method, Response, StatusCode};
+
+use schemars::{Schema, SchemaGenerator};
 
 use tracing::debug;
 
@@ -13,14 +15,50 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use crate::handlers::{HttpRequest, RequestHandler, ResponseBody};
+use crate::{
+    handlers::{
+        access_check::{AccessCheckResult, AccessChecker},
+        proxy::ProxyHandler,
+        HttpRequest, RequestHandler, ResponseBody,
+    },
+    service::{
+        auth::make_cache_control_private,
+        cors::CorsService,
+        error_page::ErrorPageService,
+        request_matcher::{normalize_path, path_has_prefix},
+        response_header::ResponseHeaderRulesService,
+        static_file::{rewrite::RewriteResult, StaticFileRulesService},
+    },
+};
+
+/// Returns the schema of a route's JSON response, e.g. `SchemaGenerator::subschema_for::<DTO>`.
+pub type ResponseSchemaFn = fn(&mut SchemaGenerator) -> Schema;
 
 pub struct RouteInfo {
     pub method: &'static Method,
     pub path_suffix: PathBuf,
+    pub summary: &'static str,
+    pub response_schema: ResponseSchemaFn,
     pub handler: Box<dyn RequestHandler>,
 }
 
+impl RouteInfo {
+    /// Request path of the route under context_path.
+    pub fn path(&self, context_path: &Path) -> anyhow::Result<String> {
+        let path = context_path.join(&self.path_suffix);
+
+        Ok(path
+            .to_str()
+            .with_context(|| {
+                format!(
+                    "RouteInfo::path error: uri_pathbuf.to_str error uri_pathbuf = '{:?}'",
+                    path,
+                )
+            })?
+            .to_owned())
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
 struct RouteKey<'a> {
     method: &'a Method,
@@ -36,19 +74,37 @@ impl<'a> From<&'a HttpRequest> for RouteKey<'a> {
     }
 }
 
+struct RouteEntry {
+    route_id: String,
+    handler: Box<dyn RequestHandler>,
+}
+
 pub struct Router {
-    route_key_to_handler: AHashMap<RouteKey<'static>, Box<dyn RequestHandler>>,
+    route_key_to_entry: AHashMap<RouteKey<'static>, RouteEntry>,
+    proxy_handlers: Vec<ProxyHandler>,
     default_route: Box<dyn RequestHandler>,
+    header_rules_service: &'static ResponseHeaderRulesService,
+    static_file_rules_service: &'static StaticFileRulesService,
+    access_checker: AccessChecker,
+    cors_service: &'static CorsService,
+    error_page_service: &'static ErrorPageService,
 }
 
 impl Router {
     pub fn new(
         routes: Vec<RouteInfo>,
+        proxy_handlers: Vec<ProxyHandler>,
         default_route: Box<dyn RequestHandler>,
     ) -> anyhow::Result<Self> {
         let mut router = Self {
-            route_key_to_handler: AHashMap::with_capacity(routes.len()),
+            route_key_to_entry: AHashMap::with_capacity(routes.len()),
+            proxy_handlers,
             default_route,
+            header_rules_service: crate::service::response_header::header_rules_service_instance(),
+            static_file_rules_service: crate::service::static_file::rules_service_instance(),
+            access_checker: AccessChecker::new(),
+            cors_service: crate::service::cors::cors_service_instance(),
+            error_page_service: crate::service::error_page::error_page_service_instance(),
         };
 
         let context_path = Path::new(
@@ -60,9 +116,14 @@ impl Router {
         for route in routes {
             let route_key = Self::build_route_key(context_path, &route)?;
 
+            let route_entry = RouteEntry {
+                route_id: route.path_suffix.to_string_lossy().into_owned(),
+                handler: route.handler,
+            };
+
             if router
-                .route_key_to_handler
-                .insert(route_key.clone(), route.handler)
+                .route_key_to_entry
+                .insert(route_key.clone(), route_entry)
                 .is_some()
             {
                 anyhow::bail!(
@@ -78,23 +139,98 @@ impl Router {
         context_path: &Path,
         route: &RouteInfo,
     ) -> anyhow::Result<RouteKey<'static>> {
-        let path = context_path.join(&route.path_suffix);
-
-        let path = path
-            .to_str()
-            .with_context(|| {
-                format!(
-                    "Router::build_route_key error: uri_pathbuf.to_str error uri_pathbuf = '{:?}'",
-                    path,
-                )
-            })?
-            .to_owned();
+        let path = route
+            .path(context_path)
+            .context("Router::build_route_key error")?;
 
         Ok(RouteKey {
             method: route.method,
             path: Cow::from(path),
         })
     }
+
+    /// Proxy handlers are sorted longest path_prefix first.
+    fn proxy_handler(&self, request: &HttpRequest) -> Option<&ProxyHandler> {
+        self.proxy_handlers.iter().find(|proxy_handler| {
+            path_has_prefix(&request.normalized_path, proxy_handler.path_prefix())
+        })
+    }
+
+    /// Answer CORS preflight requests for dynamic routes, before auth since preflights carry no credentials.
+    async fn handle_cors_preflight(&self, request: &HttpRequest) -> Option<Response<ResponseBody>> {
+        if !self.cors_service.is_enabled() {
+            return None;
+        }
+
+        let requested_method = crate::service::cors::preflight_request_method(request)?;
+
+        let route_key = RouteKey {
+            method: &requested_method,
+            path: Cow::from(request.hyper_request.uri().path()),
+        };
+
+        if !self.route_key_to_entry.contains_key(&route_key) {
+            return None;
+        }
+
+        let mut response = match self
+            .cors_service
+            .preflight_response(request, &requested_method)
+        {
+            Some(response) => response,
+            None => {
+                self.error_page_service
+                    .build_error_page_response(request, StatusCode::FORBIDDEN)
+                    .await
+            }
+        };
+
+        self.header_rules_service
+            .apply_dynamic_route_rules(request, response.headers_mut());
+
+        Some(response)
+    }
+
+    /// Run the handler if the request passes the ip access and auth rules for every
+    /// path in access_paths, otherwise answer 403 or 401.
+    async fn handle_with_access_checks(
+        &self,
+        request: &HttpRequest,
+        access_paths: &[&str],
+        route_id_option: Option<&str>,
+        handler: &dyn RequestHandler,
+    ) -> Response<ResponseBody> {
+        match self
+            .access_checker
+            .check(request, access_paths, route_id_option)
+            .await
+        {
+            AccessCheckResult::Denied(response) => response,
+            AccessCheckResult::Allowed { authenticated } => {
+                let mut response = handler.handle(request).await;
+                if authenticated {
+                    make_cache_control_private(response.headers_mut());
+                }
+                response
+            }
+        }
+    }
+
+    /// Handle a request with the default route. An internal rewrite serves the file
+    /// at another path, so the rewritten path must pass the access checks too.
+    /// The static file handler checks the path a try_files candidate resolves to.
+    async fn handle_default_route(&self, request: &HttpRequest) -> Response<ResponseBody> {
+        let rewritten_path_option = match self.static_file_rules_service.rewrite(request) {
+            RewriteResult::Rewrite(rewritten_path) => Some(normalize_path(&rewritten_path)),
+            _ => None,
+        };
+
+        let mut access_paths = vec![request.normalized_path.as_str()];
+        access_paths.extend(rewritten_path_option.as_deref());
+
+        self.handle_with_access_checks(request, &access_paths, None, self.default_route.as_ref())
+            .await
+    }
 }
 
 #[async_trait]
@@ -102,11 +238,50 @@ impl RequestHandler for Router {
     async fn handle(&self, request: &HttpRequest) -> Response<ResponseBody> {
         debug!("begin handle");
 
-        let handler_option = self.route_key_to_handler.get(&RouteKey::from(request));
+        if let Some(response) = self.handle_cors_preflight(request).await {
+            debug!("end handle cors preflight");
+            return response;
+        }
+
+        let route_entry_option = self.route_key_to_entry.get(&RouteKey::from(request));
 
-        let response = match handler_option {
-            Some(handler) => handler.handle(request).await,
-            None => self.default_route.handle(request).await,
+        let response = match route_entry_option {
+            Some(route_entry) => {
+                let mut response = self
+                    .handle_with_access_checks(
+                        request,
+                        &[&request.normalized_path],
+                        Some(&route_entry.route_id),
+                        route_entry.handler.as_ref(),
+                    )
+                    .await;
+                self.header_rules_service
+                    .apply_dynamic_route_rules(request, response.headers_mut());
+                self.cors_service.apply(request, response.headers_mut());
+                response
+            }
+            None => match self.proxy_handler(request) {
+                // CORS is left to the upstream, which also answers the proxied preflight requests.
+                Some(proxy_handler) => {
+                    let mut response = self
+                        .handle_with_access_checks(
+                            request,
+                            &[&request.normalized_path],
+                            None,
+                            proxy_handler,
+                        )
+                        .await;
+                    self.header_rules_service
+                        .apply_dynamic_route_rules(request, response.headers_mut());
+                    response
+                }
+                None => {
+                    let mut response = self.handle_default_route(request).await;
+                    self.header_rules_service
+                        .apply_static_file_rules(request, response.headers_mut());
+                    response
+                }
+            },
         };
 
         debug!("end handle");
@@ -0,0 +1,270 @@
+use anyhow::Context;
+
+use async_trait::async_trait;
+
+use hyper::http::{Method, Response};
+
+use schemars::{JsonSchema, SchemaGenerator};
+
+use serde::Serialize;
+
+use tokio::sync::OnceCell;
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    config::{MiddlewareType, ProxyUpstreamProtocol, ServerSocketType},
+    handlers::{proxy::ProxyHandler, route::RouteInfo, HttpRequest, RequestHandler, ResponseBody},
+    response::{build_static_json_response, CacheControl, StaticJson},
+    service::{auth::AuthRuleSummary, request_matcher::path_has_prefix},
+};
+
+const DEFAULT_ROUTE_DESCRIPTION: &str =
+    "static files from static_mounts for requests matching no dynamic route";
+
+/// Path used to evaluate rules for a prefix, with a trailing slash.
+fn prefix_rules_path(prefix: &str) -> String {
+    if prefix.ends_with('/') {
+        prefix.to_owned()
+    } else {
+        format!("{}/", prefix)
+    }
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+struct MiddlewareDTO {
+    middleware_type: MiddlewareType,
+    listener_bind_address: Option<String>,
+    path_prefix: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+struct AuthRuleDTO {
+    index: usize,
+    realm: String,
+    host_regex: Option<String>,
+    path_regex: Option<String>,
+}
+
+impl From<AuthRuleSummary> for AuthRuleDTO {
+    fn from(auth_rule_summary: AuthRuleSummary) -> Self {
+        Self {
+            index: auth_rule_summary.index,
+            realm: auth_rule_summary.realm,
+            host_regex: auth_rule_summary.host_regex,
+            path_regex: auth_rule_summary.path_regex,
+        }
+    }
+}
+
+/// Middlewares, auth rules and route ip access rule that can apply to a path.
+#[derive(Debug, Serialize, JsonSchema)]
+struct AppliedRulesDTO {
+    middlewares: Vec<MiddlewareDTO>,
+    auth_rules: Vec<AuthRuleDTO>,
+    ip_access_path_prefix: Option<String>,
+}
+
+impl AppliedRulesDTO {
+    /// Middlewares whose path_prefix overlaps path apply to some requests under it.
+    fn new(path: &str, route_id_option: Option<&str>) -> Self {
+        let middlewares = crate::config::instance()
+            .middlewares
+            .iter()
+            .filter(|middleware_configuration| {
+                middleware_configuration
+                    .path_prefix
+                    .as_deref()
+                    .is_none_or(|path_prefix| {
+                        path_has_prefix(path, path_prefix) || path_has_prefix(path_prefix, path)
+                    })
+            })
+            .map(|middleware_configuration| MiddlewareDTO {
+                middleware_type: middleware_configuration.middleware_type,
+                listener_bind_address: middleware_configuration.listener_bind_address.clone(),
+                path_prefix: middleware_configuration.path_prefix.clone(),
+            })
+            .collect();
+
+        let auth_rules = crate::service::auth::auth_service_instance()
+            .route_rules(path, route_id_option)
+            .into_iter()
+            .map(AuthRuleDTO::from)
+            .collect();
+
+        let ip_access_path_prefix = crate::service::ip_access::ip_access_service_instance()
+            .route_rule_path_prefix(path)
+            .map(str::to_owned);
+
+        Self {
+            middlewares,
+            auth_rules,
+            ip_access_path_prefix,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+struct RouteDTO {
+    method: String,
+    path: String,
+    summary: &'static str,
+    handler: &'static str,
+    #[serde(flatten)]
+    applied_rules: AppliedRulesDTO,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+struct DefaultRouteDTO {
+    description: &'static str,
+    handler: &'static str,
+}
+
+/// Rules for a mount are those matching the url_prefix with a trailing slash.
+#[derive(Debug, Serialize, JsonSchema)]
+struct StaticMountDTO {
+    url_prefix: String,
+    root: String,
+    archive: bool,
+    autoindex: bool,
+    #[serde(flatten)]
+    applied_rules: AppliedRulesDTO,
+}
+
+/// Rules for a proxy route are those matching the path_prefix with a trailing slash.
+#[derive(Debug, Serialize, JsonSchema)]
+struct ProxyRouteDTO {
+    path_prefix: String,
+    rewrite_prefix: Option<String>,
+    upstream_socket_type: ServerSocketType,
+    upstream_address: String,
+    upstream_protocol: ProxyUpstreamProtocol,
+    handler: &'static str,
+    #[serde(flatten)]
+    applied_rules: AppliedRulesDTO,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+struct RoutesResponse {
+    routes: Vec<RouteDTO>,
+    proxy_routes: Vec<ProxyRouteDTO>,
+    default_route: DefaultRouteDTO,
+    static_mounts: Vec<StaticMountDTO>,
+}
+
+static ROUTES_RESPONSE_INSTANCE: OnceCell<StaticJson> = OnceCell::const_new();
+
+/// Describe routes, proxy routes and the default route, called with the full route list before the router is built.
+pub fn set_routes(
+    routes: &[RouteInfo],
+    proxy_handlers: &[ProxyHandler],
+    default_route: &dyn RequestHandler,
+) -> anyhow::Result<()> {
+    let context_path = Path::new(
+        &crate::config::instance()
+            .context_configuration
+            .dynamic_route_context,
+    );
+
+    let mut route_dtos = routes
+        .iter()
+        .map(|route| {
+            let path = route.path(context_path)?;
+            let route_id = route.path_suffix.to_string_lossy();
+
+            Ok(RouteDTO {
+                method: route.method.to_string(),
+                applied_rules: AppliedRulesDTO::new(&path, Some(&route_id)),
+                path,
+                summary: route.summary,
+                handler: route.handler.type_name(),
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    route_dtos.sort_by(|a, b| a.path.cmp(&b.path).then_with(|| a.method.cmp(&b.method)));
+
+    let mut static_mounts: Vec<StaticMountDTO> =
+        crate::service::static_file::mount::mounts_instance()
+            .iter()
+            .map(|mount| {
+                // the default root mount has an empty url_prefix.
+                let url_prefix = match mount.url_prefix() {
+                    "" => "/",
+                    url_prefix => url_prefix,
+                };
+
+                StaticMountDTO {
+                    url_prefix: url_prefix.to_owned(),
+                    root: mount.root().to_string_lossy().into_owned(),
+                    archive: mount.archive_fs().is_some(),
+                    autoindex: mount.autoindex(),
+                    applied_rules: AppliedRulesDTO::new(&prefix_rules_path(url_prefix), None),
+                }
+            })
+            .collect();
+
+    static_mounts.sort_by(|a, b| a.url_prefix.cmp(&b.url_prefix));
+
+    let mut proxy_routes: Vec<ProxyRouteDTO> = proxy_handlers
+        .iter()
+        .map(|proxy_handler| {
+            let proxy_route = proxy_handler.proxy_route();
+
+            ProxyRouteDTO {
+                path_prefix: proxy_route.path_prefix.clone(),
+                rewrite_prefix: proxy_route.rewrite_prefix.clone(),
+                upstream_socket_type: proxy_route.upstream.socket_type,
+                upstream_address: proxy_route.upstream.address.clone(),
+                upstream_protocol: proxy_route.upstream.protocol,
+                handler: proxy_handler.type_name(),
+                applied_rules: AppliedRulesDTO::new(
+                    &prefix_rules_path(&proxy_route.path_prefix),
+                    None,
+                ),
+            }
+        })
+        .collect();
+
+    proxy_routes.sort_by(|a, b| a.path_prefix.cmp(&b.path_prefix));
+
+    let routes_response = RoutesResponse {
+        routes: route_dtos,
+        proxy_routes,
+        default_route: DefaultRouteDTO {
+            description: DEFAULT_ROUTE_DESCRIPTION,
+            handler: default_route.type_name(),
+        },
+        static_mounts,
+    };
+
+    let static_json =
+        StaticJson::new(routes_response).context("route_info::set_routes: serialization error")?;
+
+    ROUTES_RESPONSE_INSTANCE
+        .set(static_json)
+        .context("ROUTES_RESPONSE_INSTANCE.set error")?;
+
+    Ok(())
+}
+
+struct RoutesHandler;
+
+#[async_trait]
+impl RequestHandler for RoutesHandler {
+    async fn handle(&self, request: &HttpRequest) -> Response<ResponseBody> {
+        let static_json = ROUTES_RESPONSE_INSTANCE.get().unwrap();
+
+        build_static_json_response(request, static_json, CacheControl::NoCache)
+    }
+}
+
+pub fn create_routes() -> Vec<RouteInfo> {
+    vec![RouteInfo {
+        method: &Method::GET,
+        path_suffix: PathBuf::from("routes"),
+        summary: "Registered dynamic routes, proxy routes, the default route and static mounts",
+        response_schema: SchemaGenerator::subschema_for::<RoutesResponse>,
+        handler: Box::new(RoutesHandler),
+    }]
+}
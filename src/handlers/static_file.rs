@@ -1,19 +1,34 @@
+mod autoindex;
+
 use async_trait::async_trait;
 
 use http_body_util::BodyExt;
 
-use hyper::http::{header, Request as HyperHttpRequest, Response, StatusCode};
-
-use hyper_staticfile::{vfs::TokioFileOpener, ResolveResult, Resolver};
+use hyper::http::{header, HeaderMap, HeaderValue, Method, Response, StatusCode};
 
 use tracing::{debug, warn};
 
-use tokio::time::Duration;
+use std::path::PathBuf;
 
 use crate::{
-    handlers::{HttpRequest, RequestHandler, ResponseBody},
-    response::{build_status_code_response, CacheControl},
-    service::static_file::StaticFileRulesService,
+    handlers::{
+        access_check::{AccessCheckResult, AccessChecker},
+        HttpRequest, RequestHandler, ResponseBody,
+    },
+    response::{build_redirect_response, empty_response_body, CacheControl},
+    service::{
+        auth::make_cache_control_private,
+        error_page::ErrorPageService,
+        request_matcher::{normalize_path, request_host},
+        static_file::{
+            content_hash::{if_none_match_matches, ContentHashService},
+            expand_try_file,
+            mount::{MountSnapshot, StaticFileMounts},
+            rewrite::RewriteResult,
+            vfs::ResolveResult,
+            StaticFileRulesService,
+        },
+    },
 };
 
 #[derive(thiserror::Error, Debug)]
@@ -21,161 +36,339 @@ enum StaticFileHandlerError {
     #[error("resolve error: {0}")]
     ResolveRequest(std::io::Error),
 
-    #[error("build response error: {0}")]
-    BuildResponse(hyper::http::Error),
+    #[error("autoindex error: {0}")]
+    Autoindex(std::io::Error),
 
-    #[error("client error page error: {0}")]
-    ClientErrorPage(ClientErrorPageError),
-}
-
-#[derive(thiserror::Error, Debug)]
-enum ClientErrorPageError {
-    #[error("build request error: {0}")]
-    BuildRequest(hyper::http::Error),
-
-    #[error("resolve request error: {0}")]
-    ResolveRequest(std::io::Error),
+    #[error("content hash error: {0}")]
+    ContentHash(std::io::Error),
 
     #[error("build response error: {0}")]
     BuildResponse(hyper::http::Error),
 }
 
-struct StaticFileHandler {
-    resolver: Resolver<TokioFileOpener>,
-    client_error_page_path: &'static str,
-    static_file_rules_service: &'static StaticFileRulesService,
+/// Where a static file request ends up after the rewrite, blocking and try_files rules.
+pub enum StaticFileOutcome {
+    Redirect {
+        status_code: StatusCode,
+        location: String,
+    },
+    /// The request path or the resolved path is blocked.
+    Blocked,
+    /// A directory without an index file on an autoindex mount.
+    Autoindex {
+        request_path: String,
+        directory: PathBuf,
+    },
+    Resolved {
+        mount_snapshot: MountSnapshot<'static>,
+        resolve_result: ResolveResult,
+    },
 }
 
-impl StaticFileHandler {
-    fn new() -> Self {
-        let static_file_configuration = &crate::config::instance().static_file_configuration;
-
-        let mut resolver = Resolver::new(&static_file_configuration.root);
-        resolver.allowed_encodings.gzip = static_file_configuration.precompressed.gz;
-        resolver.allowed_encodings.br = static_file_configuration.precompressed.br;
+pub struct StaticFileResolution {
+    pub rewritten_path: Option<String>,
+    pub outcome: StaticFileOutcome,
+}
 
-        debug!(
-            "resolver.allowed_encodings = {:?}",
-            resolver.allowed_encodings
-        );
+/// Resolves request paths for the static file handler and the cache_rule_explain route.
+pub struct StaticFileResolver {
+    mounts: &'static StaticFileMounts,
+    static_file_rules_service: &'static StaticFileRulesService,
+}
 
+impl StaticFileResolver {
+    pub fn new() -> Self {
         Self {
-            resolver,
-            client_error_page_path: &static_file_configuration.client_error_page_path,
+            mounts: crate::service::static_file::mount::mounts_instance(),
             static_file_rules_service: crate::service::static_file::rules_service_instance(),
         }
     }
 
-    fn build_cache_headers(
-        &self,
-        original_request: &HttpRequest,
-        resolve_result: &ResolveResult,
-    ) -> Option<u32> {
-        fn duration_to_u32_seconds(duration: Duration) -> u32 {
-            duration.as_secs().try_into().unwrap_or_default()
+    fn block_request_path(&self, request_path: &str) -> bool {
+        if self.static_file_rules_service.blocks_path(request_path) {
+            warn!("blocking request for path = {:?}", request_path);
+            return true;
         }
 
-        let host_option = original_request
-            .hyper_request
-            .headers()
-            .get(header::HOST)
-            .and_then(|v| v.to_str().ok());
+        false
+    }
 
-        match resolve_result {
-            ResolveResult::Found(resolved_file) => self
-                .static_file_rules_service
-                .build_cache_header(host_option, resolved_file)
-                .map(duration_to_u32_seconds),
+    fn block_resolved_path(&self, resolve_result: &ResolveResult) -> bool {
+        let str_path_option = match resolve_result {
+            ResolveResult::Found(resolved_file) => resolved_file.path.to_str(),
+            ResolveResult::IsDirectory { redirect_to } => Some(redirect_to.as_str()),
             _ => None,
+        };
+
+        if let Some(str_path) = str_path_option {
+            debug!("str_path = {}", str_path);
+            if self.static_file_rules_service.blocks_path(str_path) {
+                warn!("blocking request for resolved path = {:?}", str_path);
+                return true;
+            }
+        };
+
+        false
+    }
+
+    fn resolved_outcome(
+        &self,
+        mount_snapshot: MountSnapshot<'static>,
+        resolve_result: ResolveResult,
+    ) -> StaticFileOutcome {
+        if self.block_resolved_path(&resolve_result) {
+            StaticFileOutcome::Blocked
+        } else {
+            StaticFileOutcome::Resolved {
+                mount_snapshot,
+                resolve_result,
+            }
         }
     }
 
-    async fn build_client_error_page_response(
+    /// Resolve the try_files chain of the first matching rule,
+    /// returning the first candidate that is found and the mount snapshot it was found in.
+    async fn resolve_try_files(
         &self,
-        original_request: &HttpRequest,
-        status_code: StatusCode,
-    ) -> Result<Response<ResponseBody>, ClientErrorPageError> {
-        let mut client_error_page_request = HyperHttpRequest::get(self.client_error_page_path);
+        method: &Method,
+        headers: &HeaderMap,
+        host_option: Option<&str>,
+        request_path: &str,
+    ) -> std::io::Result<Option<(MountSnapshot<'static>, ResolveResult)>> {
+        let Some(try_files) = self
+            .static_file_rules_service
+            .try_files(host_option, request_path)
+        else {
+            return Ok(None);
+        };
 
-        // copy ACCEPT_ENCODING header from original request
-        // so we can try to use gz/bz client error page if possible.
-        if let Some(accept_encoding_header_value) = original_request
-            .hyper_request
-            .headers()
-            .get(header::ACCEPT_ENCODING)
+        for try_file in try_files {
+            let try_file_path = expand_try_file(try_file, request_path);
+
+            // the request path itself has already been resolved.
+            if try_file_path == request_path || self.block_request_path(&try_file_path) {
+                continue;
+            }
+
+            let (mount, mount_path) = self.mounts.find(&try_file_path);
+
+            let mount_snapshot = mount.snapshot();
+
+            let resolve_result = mount_snapshot.resolve(method, headers, mount_path).await?;
+
+            debug!(
+                "try_file_path = {:?} resolve_result = {:?}",
+                try_file_path, resolve_result
+            );
+
+            if matches!(resolve_result, ResolveResult::Found(_)) {
+                return Ok(Some((mount_snapshot, resolve_result)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn resolve_request_path(
+        &self,
+        method: &Method,
+        headers: &HeaderMap,
+        host_option: Option<&str>,
+        request_path: &str,
+    ) -> std::io::Result<StaticFileOutcome> {
+        if self.block_request_path(request_path) {
+            return Ok(StaticFileOutcome::Blocked);
+        }
+
+        let (mount, mount_path) = self.mounts.find(request_path);
+
+        debug!(
+            "mount.url_prefix = {:?} mount_path = {:?}",
+            mount.url_prefix(),
+            mount_path
+        );
+
+        let mount_snapshot = mount.snapshot();
+
+        let resolve_result = mount_snapshot.resolve(method, headers, mount_path).await?;
+
+        debug!("resolve_result = {:?}", resolve_result);
+
+        if !matches!(resolve_result, ResolveResult::NotFound) {
+            return Ok(self.resolved_outcome(mount_snapshot, resolve_result));
+        }
+
+        if mount.autoindex() && request_path.ends_with('/') {
+            if let Some(relative_directory) = mount_snapshot.resolve_directory(mount_path).await? {
+                return Ok(StaticFileOutcome::Autoindex {
+                    request_path: request_path.to_owned(),
+                    directory: mount.root().join(relative_directory),
+                });
+            }
+        }
+
+        match self
+            .resolve_try_files(method, headers, host_option, request_path)
+            .await?
         {
-            client_error_page_request = client_error_page_request
-                .header(header::ACCEPT_ENCODING, accept_encoding_header_value);
+            Some((mount_snapshot, resolve_result)) => {
+                Ok(self.resolved_outcome(mount_snapshot, resolve_result))
+            }
+            None => Ok(StaticFileOutcome::Resolved {
+                mount_snapshot,
+                resolve_result,
+            }),
         }
+    }
 
-        let client_error_page_request = client_error_page_request
-            .body(())
-            .map_err(ClientErrorPageError::BuildRequest)?;
+    /// Apply the rewrite rules, then resolve the request path through the mounts,
+    /// autoindex and try_files rules. Blocked paths are checked before and after resolving.
+    pub async fn resolve(
+        &self,
+        method: &Method,
+        headers: &HeaderMap,
+        host_option: Option<&str>,
+        path: &str,
+        query_option: Option<&str>,
+    ) -> std::io::Result<StaticFileResolution> {
+        let rewritten_path =
+            match self
+                .static_file_rules_service
+                .rewrite_path(host_option, path, query_option)
+            {
+                RewriteResult::Unchanged => None,
+                RewriteResult::Rewrite(rewritten_path) => Some(rewritten_path),
+                RewriteResult::Redirect {
+                    status_code,
+                    location,
+                } => {
+                    return Ok(StaticFileResolution {
+                        rewritten_path: None,
+                        outcome: StaticFileOutcome::Redirect {
+                            status_code,
+                            location,
+                        },
+                    });
+                }
+            };
 
-        let resolve_result = self
-            .resolver
-            .resolve_request(&client_error_page_request)
-            .await
-            .map_err(ClientErrorPageError::ResolveRequest)?;
+        let request_path = rewritten_path.as_deref().unwrap_or(path);
 
-        let response = hyper_staticfile::ResponseBuilder::new()
-            .request(&client_error_page_request)
-            .cache_headers(self.build_cache_headers(original_request, &resolve_result))
-            .build(resolve_result)
-            .map_err(ClientErrorPageError::BuildResponse)?;
+        debug!("request_path = {:?}", request_path);
 
-        let (mut parts, body) = response.into_parts();
-        parts.status = status_code;
+        let outcome = self
+            .resolve_request_path(method, headers, host_option, request_path)
+            .await?;
 
-        let boxed_body = body.map_err(|e| e.into()).boxed();
+        Ok(StaticFileResolution {
+            rewritten_path,
+            outcome,
+        })
+    }
+}
 
-        Ok(Response::from_parts(parts, boxed_body))
+struct StaticFileHandler {
+    static_file_resolver: StaticFileResolver,
+    access_checker: AccessChecker,
+    static_file_rules_service: &'static StaticFileRulesService,
+    error_page_service: &'static ErrorPageService,
+    content_hash_service_option: Option<&'static ContentHashService>,
+}
+
+impl StaticFileHandler {
+    async fn new() -> Self {
+        let static_file_configuration = &crate::config::instance().static_file_configuration;
+
+        let content_hash_service_option = if static_file_configuration.content_hash_etags {
+            Some(ContentHashService::instance().await)
+        } else {
+            None
+        };
+
+        Self {
+            static_file_resolver: StaticFileResolver::new(),
+            access_checker: AccessChecker::new(),
+            static_file_rules_service: crate::service::static_file::rules_service_instance(),
+            error_page_service: crate::service::error_page::error_page_service_instance(),
+            content_hash_service_option,
+        }
     }
 
-    fn block_dot_paths(&self, resolve_result: &ResolveResult) -> bool {
-        let str_path_option = match resolve_result {
-            ResolveResult::Found(resolved_file) => resolved_file.path.to_str(),
-            ResolveResult::IsDirectory { redirect_to } => Some(redirect_to.as_str()),
-            _ => None,
+    async fn build_content_etag(
+        &self,
+        mount_snapshot: &MountSnapshot<'_>,
+        resolve_result: &ResolveResult,
+    ) -> Result<Option<HeaderValue>, StaticFileHandlerError> {
+        let (Some(content_hash_service), ResolveResult::Found(resolved_file)) =
+            (self.content_hash_service_option, resolve_result)
+        else {
+            return Ok(None);
         };
 
-        if let Some(str_path) = str_path_option {
-            debug!("str_path = {}", str_path);
-            if str_path.starts_with('.') || str_path.contains("/.") {
-                warn!("blocking request for dot file path = {:?}", str_path);
-                return true;
-            }
+        let Some(file_contents) = mount_snapshot.file_contents(&resolved_file.path) else {
+            return Ok(None);
         };
 
-        false
+        let content_digests = content_hash_service
+            .content_digests(file_contents)
+            .await
+            .map_err(StaticFileHandlerError::ContentHash)?;
+
+        Ok(HeaderValue::try_from(content_digests.etag()).ok())
     }
 
     async fn handle_resolve_errors(
         &self,
         request: &HttpRequest,
         resolve_result: &ResolveResult,
-    ) -> Result<Option<Response<ResponseBody>>, StaticFileHandlerError> {
-        let client_error_page_status_option =
-            if matches!(resolve_result, ResolveResult::PermissionDenied)
-                || self.block_dot_paths(resolve_result)
-            {
-                Some(StatusCode::FORBIDDEN)
-            } else if matches!(resolve_result, ResolveResult::MethodNotMatched) {
-                Some(StatusCode::METHOD_NOT_ALLOWED)
-            } else if matches!(resolve_result, ResolveResult::NotFound) {
-                Some(StatusCode::NOT_FOUND)
-            } else {
-                None
-            };
+    ) -> Option<Response<ResponseBody>> {
+        let error_page_status_option = if matches!(resolve_result, ResolveResult::PermissionDenied)
+        {
+            Some(StatusCode::FORBIDDEN)
+        } else if matches!(resolve_result, ResolveResult::MethodNotMatched) {
+            Some(StatusCode::METHOD_NOT_ALLOWED)
+        } else if matches!(resolve_result, ResolveResult::NotFound) {
+            Some(StatusCode::NOT_FOUND)
+        } else {
+            None
+        };
 
-        Ok(match client_error_page_status_option {
+        match error_page_status_option {
             None => None,
-            Some(client_error_page_status) => Some(
-                self.build_client_error_page_response(request, client_error_page_status)
-                    .await
-                    .map_err(StaticFileHandlerError::ClientErrorPage)?,
+            Some(error_page_status) => Some(
+                self.error_page_service
+                    .build_error_page_response(request, error_page_status)
+                    .await,
             ),
-        })
+        }
+    }
+
+    /// The router checks the request and rewritten paths, but try_files can serve a
+    /// file at another path, so the path that is served must pass the access checks too.
+    async fn check_served_path_access(
+        &self,
+        request: &HttpRequest,
+        outcome: &StaticFileOutcome,
+    ) -> AccessCheckResult {
+        let served_path_option = match outcome {
+            StaticFileOutcome::Autoindex { request_path, .. } => Some(normalize_path(request_path)),
+            StaticFileOutcome::Resolved {
+                resolve_result: ResolveResult::Found(resolved_file),
+                ..
+            } => Some(format!("/{}", resolved_file.path.to_string_lossy())),
+            _ => None,
+        };
+
+        match served_path_option {
+            None => AccessCheckResult::Allowed {
+                authenticated: false,
+            },
+            Some(served_path) => {
+                self.access_checker
+                    .check(request, &[&served_path], None)
+                    .await
+            }
+        }
     }
 
     async fn try_handle(
@@ -184,29 +377,149 @@ impl StaticFileHandler {
     ) -> Result<Response<ResponseBody>, StaticFileHandlerError> {
         debug!("StaticFileHandler::try_handle request = {:?}", request);
 
-        let resolve_result = self
-            .resolver
-            .resolve_request(&request.hyper_request)
+        let hyper_request = &request.hyper_request;
+
+        let resolution = self
+            .static_file_resolver
+            .resolve(
+                hyper_request.method(),
+                hyper_request.headers(),
+                request_host(request),
+                hyper_request.uri().path(),
+                hyper_request.uri().query(),
+            )
             .await
             .map_err(StaticFileHandlerError::ResolveRequest)?;
 
-        debug!("resolve_result = {:?}", resolve_result);
+        match self
+            .check_served_path_access(request, &resolution.outcome)
+            .await
+        {
+            AccessCheckResult::Denied(response) => Ok(response),
+            AccessCheckResult::Allowed { authenticated } => {
+                let mut response = self.serve(request, resolution.outcome).await?;
+                if authenticated {
+                    make_cache_control_private(response.headers_mut());
+                }
+                Ok(response)
+            }
+        }
+    }
+
+    async fn serve(
+        &self,
+        request: &HttpRequest,
+        outcome: StaticFileOutcome,
+    ) -> Result<Response<ResponseBody>, StaticFileHandlerError> {
+        let hyper_request = &request.hyper_request;
+
+        let (mount_snapshot, mut resolve_result) = match outcome {
+            StaticFileOutcome::Redirect {
+                status_code,
+                location,
+            } => {
+                debug!(
+                    "redirect status_code = {} location = {:?}",
+                    status_code, location
+                );
+                return build_redirect_response(status_code, &location, CacheControl::NoCache)
+                    .map_err(StaticFileHandlerError::BuildResponse);
+            }
+            StaticFileOutcome::Blocked => {
+                return Ok(self
+                    .error_page_service
+                    .build_error_page_response(request, StatusCode::FORBIDDEN)
+                    .await);
+            }
+            StaticFileOutcome::Autoindex {
+                request_path,
+                directory,
+            } => {
+                let response_option = autoindex::build_autoindex_response(
+                    hyper_request.method(),
+                    &request_path,
+                    &directory,
+                    |path| self.static_file_rules_service.blocks_path(path),
+                )
+                .await
+                .map_err(StaticFileHandlerError::Autoindex)?;
+
+                return Ok(match response_option {
+                    Some(response) => response,
+                    None => {
+                        self.error_page_service
+                            .build_error_page_response(request, StatusCode::NOT_FOUND)
+                            .await
+                    }
+                });
+            }
+            StaticFileOutcome::Resolved {
+                mount_snapshot,
+                resolve_result,
+            } => (mount_snapshot, resolve_result),
+        };
 
-        if let Some(response) = self.handle_resolve_errors(request, &resolve_result).await? {
+        if let Some(response) = self.handle_resolve_errors(request, &resolve_result).await {
             return Ok(response);
         }
 
-        let cache_headers = self.build_cache_headers(request, &resolve_result);
+        self.static_file_rules_service
+            .apply_content_type(request, &mut resolve_result);
+
+        let cache_control = self
+            .static_file_rules_service
+            .build_cache_headers(request, &resolve_result);
+
+        debug!("cache_control = {:?}", cache_control);
+
+        let content_etag = self
+            .build_content_etag(&mount_snapshot, &resolve_result)
+            .await?;
+
+        debug!("content_etag = {:?}", content_etag);
+
+        let if_none_match_option = hyper_request
+            .headers()
+            .get(header::IF_NONE_MATCH)
+            .and_then(|v| v.to_str().ok());
+
+        let mut response_builder = hyper_staticfile::ResponseBuilder::new();
+        response_builder.request(hyper_request);
+
+        if let (Some(content_etag), Some(if_none_match)) = (&content_etag, if_none_match_option) {
+            if if_none_match_matches(if_none_match, content_etag.to_str().unwrap_or_default()) {
+                let mut response = Response::builder()
+                    .status(StatusCode::NOT_MODIFIED)
+                    .header(header::ETAG, content_etag);
+                if let Some(cache_control) = cache_control {
+                    response = response.header(header::CACHE_CONTROL, cache_control);
+                }
+                return response
+                    .body(empty_response_body())
+                    .map_err(StaticFileHandlerError::BuildResponse);
+            }
 
-        debug!("cache_headers = {:?}", cache_headers);
+            // If-None-Match takes precedence over If-Modified-Since.
+            response_builder
+                .file_response_builder
+                .if_modified_since(None);
+        }
 
-        let response = hyper_staticfile::ResponseBuilder::new()
-            .request(&request.hyper_request)
-            .cache_headers(cache_headers)
+        let response = response_builder
             .build(resolve_result)
             .map_err(StaticFileHandlerError::BuildResponse)?;
 
-        let (parts, body) = response.into_parts();
+        let (mut parts, body) = response.into_parts();
+
+        if let Some(cache_control) = cache_control {
+            parts.headers.insert(header::CACHE_CONTROL, cache_control);
+        }
+
+        if let Some(content_etag) = content_etag {
+            if parts.status.is_success() || parts.status == StatusCode::NOT_MODIFIED {
+                parts.headers.insert(header::ETAG, content_etag);
+            }
+        }
 
         let boxed_body = body.map_err(|e| e.into()).boxed();
 
@@ -221,12 +534,14 @@ impl RequestHandler for StaticFileHandler {
             Ok(response) => response,
             Err(e) => {
                 warn!("StaticFileHandler::try_handle error: {}", e);
-                build_status_code_response(StatusCode::INTERNAL_SERVER_ERROR, CacheControl::NoCache)
+                self.error_page_service
+                    .build_error_page_response(request, StatusCode::INTERNAL_SERVER_ERROR)
+                    .await
             }
         }
     }
 }
 
-pub fn create_default_route() -> Box<dyn RequestHandler> {
-    Box::new(StaticFileHandler::new())
+pub async fn create_default_route() -> Box<dyn RequestHandler> {
+    Box::new(StaticFileHandler::new().await)
 }
@@ -0,0 +1,160 @@
+use http_body_util::{BodyExt, Full};
+
+use hyper::http::{header, Method, Response, StatusCode};
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+
+use std::{fmt::Write, path::Path};
+
+use crate::{
+    handlers::ResponseBody,
+    response::{empty_response_body, CacheControl},
+};
+
+const HREF_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+fn html_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// List a directory, skipping entries whose URL path under url_path is blocked.
+async fn list_directory_entries(
+    directory: &Path,
+    url_path: &str,
+    blocks_path: impl Fn(&str) -> bool,
+) -> std::io::Result<Vec<String>> {
+    let mut entries = Vec::new();
+
+    let mut read_dir = tokio::fs::read_dir(directory).await?;
+
+    while let Some(entry) = read_dir.next_entry().await? {
+        let mut name = entry.file_name().to_string_lossy().into_owned();
+
+        if entry.file_type().await?.is_dir() {
+            name.push('/');
+        }
+
+        if blocks_path(&format!("{}{}", url_path, name)) {
+            continue;
+        }
+
+        entries.push(name);
+    }
+
+    entries.sort();
+
+    Ok(entries)
+}
+
+fn build_listing_html(url_path: &str, entries: &[String]) -> String {
+    let escaped_url_path = html_escape(url_path);
+
+    let mut html = String::new();
+
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Index of {0}</title>\n</head>\n<body>\n<h1>Index of {0}</h1>\n<ul>\n",
+        escaped_url_path,
+    );
+
+    if url_path != "/" {
+        html.push_str("<li><a href=\"../\">../</a></li>\n");
+    }
+
+    for entry in entries {
+        let _ = writeln!(
+            html,
+            "<li><a href=\"{}\">{}</a></li>",
+            html_escape(&utf8_percent_encode(entry, HREF_ENCODE_SET).to_string()),
+            html_escape(entry),
+        );
+    }
+
+    html.push_str("</ul>\n</body>\n</html>\n");
+
+    html
+}
+
+/// Build a directory listing response for a directory request without an index file.
+///
+/// Returns None if the directory no longer exists.
+/// Entries are listed only if blocks_path allows their URL path, as for requests.
+pub async fn build_autoindex_response(
+    method: &Method,
+    url_path: &str,
+    directory: &Path,
+    blocks_path: impl Fn(&str) -> bool,
+) -> std::io::Result<Option<Response<ResponseBody>>> {
+    let entries = match list_directory_entries(directory, url_path, blocks_path).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    let html = build_listing_html(url_path, &entries);
+
+    let body = if *method == Method::HEAD {
+        empty_response_body()
+    } else {
+        Full::from(html).map_err(|never| never.into()).boxed()
+    };
+
+    Ok(Some(
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+            .header(header::CACHE_CONTROL, CacheControl::NoCache.header_value())
+            .body(body)
+            .unwrap(),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_list_directory_entries_filtered() {
+        let directory =
+            std::env::temp_dir().join(format!("rhs-autoindex-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(directory.join(".well-known")).unwrap();
+        std::fs::create_dir_all(directory.join(".git")).unwrap();
+        for name in ["index.txt", "notes.txt~", ".env"] {
+            std::fs::write(directory.join(name), "x").unwrap();
+        }
+
+        let blocks_path = |path: &str| {
+            path.ends_with('~') || (path.contains("/.") && !path.starts_with("/docs/.well-known/"))
+        };
+
+        let entries = list_directory_entries(&directory, "/docs/", blocks_path)
+            .await
+            .unwrap();
+
+        let _ = std::fs::remove_dir_all(&directory);
+
+        assert_eq!(entries, vec![".well-known/", "index.txt"]);
+    }
+}
@@ -0,0 +1,270 @@
+use ahash::AHashMap;
+
+use async_trait::async_trait;
+
+use hyper::http::{Method, Response, StatusCode};
+
+use schemars::{JsonSchema, SchemaGenerator};
+
+use serde::Serialize;
+
+use tracing::warn;
+
+use std::path::PathBuf;
+
+use crate::{
+    handlers::{
+        route::RouteInfo, time_utils::current_local_date_time_string, HttpRequest, RequestHandler,
+        ResponseBody,
+    },
+    response::{build_json_response, CacheControl},
+    service::{
+        auth::AuthService,
+        error_page::ErrorPageService,
+        ip_access::IpAccessService,
+        static_file::{
+            archive::ArchiveFs,
+            content_hash::{ContentHashService, FileContents},
+            mount::{StaticFileMount, StaticFileMounts},
+            StaticFileRulesService,
+        },
+    },
+};
+
+#[derive(Debug, Serialize, JsonSchema)]
+struct StaticManifestEntry {
+    path: String,
+    size: u64,
+    sha256: String,
+    sha256_integrity: String,
+    sha384_integrity: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+struct StaticManifestResponse {
+    now: String,
+    files: Vec<StaticManifestEntry>,
+}
+
+struct StaticManifestHandler {
+    mounts: &'static StaticFileMounts,
+    content_hash_service: &'static ContentHashService,
+    static_file_rules_service: &'static StaticFileRulesService,
+    auth_service: &'static AuthService,
+    ip_access_service: &'static IpAccessService,
+    error_page_service: &'static ErrorPageService,
+}
+
+impl StaticManifestHandler {
+    async fn new() -> Self {
+        Self {
+            mounts: crate::service::static_file::mount::mounts_instance(),
+            content_hash_service: ContentHashService::instance().await,
+            static_file_rules_service: crate::service::static_file::rules_service_instance(),
+            auth_service: crate::service::auth::auth_service_instance(),
+            ip_access_service: crate::service::ip_access::ip_access_service_instance(),
+            error_page_service: crate::service::error_page::error_page_service_instance(),
+        }
+    }
+
+    async fn list_archive_files(
+        &self,
+        mount: &StaticFileMount,
+        archive_fs: &ArchiveFs,
+        files: &mut Vec<StaticManifestEntry>,
+    ) -> std::io::Result<()> {
+        for (relative_path, bytes, modified) in archive_fs.snapshot().files() {
+            let entry_url_path =
+                format!("{}/{}", mount.url_prefix(), relative_path.to_string_lossy());
+
+            // skip paths shadowed by a mount with a longer prefix.
+            let (owning_mount, _) = self.mounts.find(&entry_url_path);
+            if !std::ptr::eq(owning_mount, mount) {
+                continue;
+            }
+
+            // skip paths that are never served.
+            if self.static_file_rules_service.blocks_path(&entry_url_path) {
+                continue;
+            }
+
+            let size = bytes.len() as u64;
+
+            let content_digests = self
+                .content_hash_service
+                .content_digests(FileContents::Bytes {
+                    key: mount.root().join(&relative_path),
+                    bytes,
+                    modified,
+                })
+                .await?;
+
+            files.push(StaticManifestEntry {
+                path: entry_url_path,
+                size,
+                sha256: content_digests.sha256_hex(),
+                sha256_integrity: content_digests.sha256_integrity(),
+                sha384_integrity: content_digests.sha384_integrity(),
+            });
+        }
+
+        Ok(())
+    }
+
+    async fn list_mount_files(
+        &self,
+        mount: &StaticFileMount,
+        files: &mut Vec<StaticManifestEntry>,
+    ) -> std::io::Result<()> {
+        if let Some(archive_fs) = mount.archive_fs() {
+            return self.list_archive_files(mount, archive_fs, files).await;
+        }
+
+        // pending directories to scan, as (file system path, url path)
+        let mut directories = vec![(mount.root().to_path_buf(), mount.url_prefix().to_owned())];
+
+        while let Some((directory, url_path)) = directories.pop() {
+            let mut read_dir = tokio::fs::read_dir(&directory).await?;
+
+            while let Some(entry) = read_dir.next_entry().await? {
+                let name = entry.file_name().to_string_lossy().into_owned();
+
+                let entry_url_path = format!("{}/{}", url_path, name);
+
+                // skip paths shadowed by a mount with a longer prefix.
+                let (owning_mount, _) = self.mounts.find(&entry_url_path);
+                if !std::ptr::eq(owning_mount, mount) {
+                    continue;
+                }
+
+                let is_dir = entry.file_type().await?.is_dir();
+
+                // skip paths that are never served.
+                let check_path = if is_dir {
+                    format!("{}/", entry_url_path)
+                } else {
+                    entry_url_path.clone()
+                };
+                if self.static_file_rules_service.blocks_path(&check_path) {
+                    continue;
+                }
+
+                if is_dir {
+                    directories.push((entry.path(), entry_url_path));
+                    continue;
+                }
+
+                let metadata = tokio::fs::metadata(entry.path()).await?;
+                if !metadata.is_file() {
+                    continue;
+                }
+
+                let content_digests = self
+                    .content_hash_service
+                    .content_digests(FileContents::Path(entry.path()))
+                    .await?;
+
+                files.push(StaticManifestEntry {
+                    path: entry_url_path,
+                    size: metadata.len(),
+                    sha256: content_digests.sha256_hex(),
+                    sha256_integrity: content_digests.sha256_integrity(),
+                    sha384_integrity: content_digests.sha384_integrity(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Drop files the requesting client could not fetch because of ip access or auth rules.
+    async fn retain_accessible(&self, request: &HttpRequest, files: &mut Vec<StaticManifestEntry>) {
+        // credentials are checked once per auth rule, not once per file.
+        let mut rule_index_to_authenticated = AHashMap::new();
+
+        let mut accessible_files = Vec::with_capacity(files.len());
+
+        for file in files.drain(..) {
+            if !self
+                .ip_access_service
+                .allows_route(&file.path, request.peer_ip)
+            {
+                continue;
+            }
+
+            if let Some(rule_index) = self.auth_service.rule_index(request, &file.path) {
+                let authenticated = match rule_index_to_authenticated.get(&rule_index) {
+                    Some(authenticated) => *authenticated,
+                    None => {
+                        let authenticated = self
+                            .auth_service
+                            .authenticate_rule_index(request, rule_index)
+                            .await;
+                        rule_index_to_authenticated.insert(rule_index, authenticated);
+                        authenticated
+                    }
+                };
+
+                if !authenticated {
+                    continue;
+                }
+            }
+
+            accessible_files.push(file);
+        }
+
+        *files = accessible_files;
+    }
+
+    async fn build_manifest(
+        &self,
+        request: &HttpRequest,
+    ) -> std::io::Result<StaticManifestResponse> {
+        let mut files = Vec::new();
+
+        for mount in self.mounts.iter() {
+            self.list_mount_files(mount, &mut files).await?;
+        }
+
+        self.retain_accessible(request, &mut files).await;
+
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(StaticManifestResponse {
+            now: current_local_date_time_string(),
+            files,
+        })
+    }
+}
+
+#[async_trait]
+impl RequestHandler for StaticManifestHandler {
+    async fn handle(&self, request: &HttpRequest) -> Response<ResponseBody> {
+        match self.build_manifest(request).await {
+            Ok(manifest) => build_json_response(request, manifest, CacheControl::NoCache),
+            Err(e) => {
+                warn!("StaticManifestHandler::build_manifest error: {}", e);
+                self.error_page_service
+                    .build_error_page_response(request, StatusCode::INTERNAL_SERVER_ERROR)
+                    .await
+            }
+        }
+    }
+}
+
+pub async fn create_routes() -> Vec<RouteInfo> {
+    if !crate::config::instance()
+        .static_file_configuration
+        .static_manifest
+    {
+        return Vec::new();
+    }
+
+    vec![RouteInfo {
+        method: &Method::GET,
+        path_suffix: PathBuf::from("static_manifest"),
+        summary: "Static files with their sizes and content digests",
+        response_schema: SchemaGenerator::subschema_for::<StaticManifestResponse>,
+        handler: Box::new(StaticManifestHandler::new().await),
+    }]
+}
@@ -1,30 +1,39 @@
+use anyhow::Context;
+
 use async_trait::async_trait;
 
 use hyper::http::{Method, Response};
 
+use schemars::SchemaGenerator;
+
 use std::path::PathBuf;
 
 use crate::{
     handlers::{route::RouteInfo, HttpRequest, RequestHandler, ResponseBody},
-    response::{build_json_response, CacheControl},
-    version::get_verison_info,
+    response::{build_static_json_response, CacheControl, StaticJson},
+    version::{get_verison_info, VersionInfoMap},
 };
 
-struct VersionInfoHandler;
+struct VersionInfoHandler {
+    static_json: StaticJson,
+}
 
 #[async_trait]
 impl RequestHandler for VersionInfoHandler {
-    async fn handle(&self, _request: &HttpRequest) -> Response<ResponseBody> {
-        let version_info = get_verison_info().await;
-
-        build_json_response(version_info, CacheControl::NoCache)
+    async fn handle(&self, request: &HttpRequest) -> Response<ResponseBody> {
+        build_static_json_response(request, &self.static_json, CacheControl::NoCache)
     }
 }
 
-pub async fn create_routes() -> Vec<RouteInfo> {
-    vec![RouteInfo {
+pub async fn create_routes() -> anyhow::Result<Vec<RouteInfo>> {
+    let static_json = StaticJson::new(get_verison_info().await)
+        .context("version_info::create_routes: error serializing version info")?;
+
+    Ok(vec![RouteInfo {
         method: &Method::GET,
         path_suffix: PathBuf::from("version_info"),
-        handler: Box::new(VersionInfoHandler),
-    }]
+        summary: "Build and version information",
+        response_schema: SchemaGenerator::subschema_for::<VersionInfoMap>,
+        handler: Box::new(VersionInfoHandler { static_json }),
+    }])
 }
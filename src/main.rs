@@ -1,5 +1,6 @@
 mod config;
 mod handlers;
+mod precompress;
 mod request;
 mod response;
 mod server;
@@ -26,24 +27,71 @@ fn app_name() -> String {
 async fn try_main() -> anyhow::Result<()> {
     log_version_info().await;
 
-    let config_file = std::env::args().nth(1).with_context(|| {
+    let usage = || {
         format!(
-            "config file required as command line argument: {} <config file>",
+            "config file required as command line argument: {0} <config file> | {0} precompress <config file> | {0} openapi <config file> <output file>",
             app_name(),
         )
-    })?;
+    };
+
+    let mut args = std::env::args().skip(1);
+
+    let first_arg = args.next().with_context(usage)?;
+
+    let subcommand_option = match first_arg.as_str() {
+        "precompress" | "openapi" => Some(first_arg.clone()),
+        _ => None,
+    };
+
+    let config_file = match subcommand_option {
+        Some(_) => args.next().with_context(usage)?,
+        None => first_arg,
+    };
+
+    let openapi_output_file_option = match subcommand_option.as_deref() {
+        Some("openapi") => Some(args.next().with_context(usage)?),
+        _ => None,
+    };
 
     crate::config::read_configuration(config_file)
         .await
         .context("read_configuration error")?;
 
+    if subcommand_option.as_deref() == Some("precompress") {
+        return crate::precompress::run().await;
+    }
+
     crate::service::connection::ConnectionTrackerService::instance().await;
 
+    crate::service::forwarded_headers::create_forwarded_headers_service_instance()?;
+
+    crate::service::canonical_redirect::create_canonical_redirect_service_instance()?;
+
+    crate::service::ip_access::create_ip_access_service_instance()?;
+
+    crate::service::request_id::create_request_id_service_instance()?;
+
+    crate::service::rate_limit::create_rate_limit_service_instance()?;
+
     crate::service::static_file::create_rules_service_instance()?;
 
+    crate::service::static_file::mount::create_mounts_instance()?;
+
+    crate::service::error_page::create_error_page_service_instance()?;
+
+    crate::service::response_header::create_header_rules_service_instance()?;
+
+    crate::service::cors::create_cors_service_instance()?;
+
+    crate::service::auth::create_auth_service_instance()?;
+
+    if let Some(openapi_output_file) = openapi_output_file_option {
+        return handlers::write_openapi_document(&openapi_output_file).await;
+    }
+
     let handlers = handlers::create_handlers().await?;
 
-    let server = crate::server::Server::new(handlers).await;
+    let server = crate::server::Server::new(handlers).await?;
 
     server.run().await
 }
@@ -0,0 +1,338 @@
+use ahash::AHashSet;
+
+use anyhow::Context;
+
+use tracing::{debug, info, warn};
+
+use std::{
+    ffi::OsString,
+    fs::Metadata,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::{StaticFilePrecompressConfiguration, StaticFilePrecompressedConfiguration},
+    service::static_file::archive::ArchiveFormat,
+};
+
+#[derive(Clone, Copy, Debug)]
+enum Compression {
+    Gzip,
+    Brotli,
+    Zstd,
+}
+
+impl Compression {
+    fn file_extension(&self) -> &'static str {
+        match self {
+            Self::Gzip => "gz",
+            Self::Brotli => "br",
+            Self::Zstd => "zst",
+        }
+    }
+
+    fn compress(&self, input: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Self::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+                encoder.write_all(input)?;
+                encoder.finish()
+            }
+            Self::Brotli => {
+                let mut output = Vec::new();
+                {
+                    let mut encoder = brotli::CompressorWriter::new(&mut output, 64 * 1024, 11, 22);
+                    encoder.write_all(input)?;
+                }
+                Ok(output)
+            }
+            Self::Zstd => zstd::encode_all(input, 19),
+        }
+    }
+
+    fn sibling_path(&self, path: &Path) -> PathBuf {
+        let mut file_name = OsString::from(path.as_os_str());
+        file_name.push(".");
+        file_name.push(self.file_extension());
+        PathBuf::from(file_name)
+    }
+}
+
+#[derive(Debug, Default)]
+struct PrecompressStats {
+    files_compressed: u64,
+    files_up_to_date: u64,
+    files_not_smaller: u64,
+    original_bytes: u64,
+    compressed_bytes: u64,
+}
+
+impl PrecompressStats {
+    fn space_saved(&self) -> u64 {
+        self.original_bytes.saturating_sub(self.compressed_bytes)
+    }
+}
+
+/// Returns true if the compressed sibling was modified at or after the source file.
+fn is_up_to_date(metadata: &Metadata, sibling_path: &Path) -> bool {
+    let Ok(sibling_metadata) = std::fs::metadata(sibling_path) else {
+        return false;
+    };
+
+    match (metadata.modified(), sibling_metadata.modified()) {
+        (Ok(modified), Ok(sibling_modified)) => sibling_modified >= modified,
+        _ => false,
+    }
+}
+
+/// Write to a temporary file first so a partially written sibling is never served.
+fn write_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut temp_file_name = OsString::from(path.as_os_str());
+    temp_file_name.push(".tmp");
+    let temp_path = PathBuf::from(temp_file_name);
+
+    std::fs::write(&temp_path, contents)?;
+    std::fs::rename(&temp_path, path)
+}
+
+struct Precompressor {
+    min_size: u64,
+    extensions: AHashSet<String>,
+}
+
+impl Precompressor {
+    fn new(precompress_configuration: &StaticFilePrecompressConfiguration) -> Self {
+        Self {
+            min_size: precompress_configuration.min_size,
+            extensions: precompress_configuration
+                .extensions
+                .iter()
+                .map(|extension| extension.trim_start_matches('.').to_ascii_lowercase())
+                .collect(),
+        }
+    }
+
+    fn is_compressible(&self, path: &Path, metadata: &Metadata) -> bool {
+        metadata.len() >= self.min_size
+            && path
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| self.extensions.contains(&extension.to_ascii_lowercase()))
+    }
+
+    fn precompress_file(
+        &self,
+        path: &Path,
+        metadata: &Metadata,
+        compressions: &[Compression],
+        stats: &mut PrecompressStats,
+    ) -> std::io::Result<()> {
+        let mut input_option: Option<Vec<u8>> = None;
+
+        for compression in compressions {
+            let sibling_path = compression.sibling_path(path);
+
+            if is_up_to_date(metadata, &sibling_path) {
+                debug!("up to date {:?}", sibling_path);
+                stats.files_up_to_date += 1;
+                continue;
+            }
+
+            let input = match &input_option {
+                Some(input) => input,
+                None => input_option.insert(std::fs::read(path)?),
+            };
+
+            let output = compression.compress(input)?;
+
+            if output.len() >= input.len() {
+                debug!("not smaller {:?}", sibling_path);
+                stats.files_not_smaller += 1;
+
+                // a stale sibling would otherwise be served for the new content.
+                if sibling_path.exists() {
+                    std::fs::remove_file(&sibling_path)?;
+                }
+                continue;
+            }
+
+            write_atomically(&sibling_path, &output)?;
+
+            info!(
+                "wrote {:?} {} -> {} bytes",
+                sibling_path,
+                input.len(),
+                output.len()
+            );
+
+            stats.files_compressed += 1;
+            stats.original_bytes += input.len() as u64;
+            stats.compressed_bytes += output.len() as u64;
+        }
+
+        Ok(())
+    }
+
+    fn precompress_directory(
+        &self,
+        root: &Path,
+        compressions: &[Compression],
+        stats: &mut PrecompressStats,
+    ) -> std::io::Result<()> {
+        let mut directories = vec![root.to_path_buf()];
+
+        while let Some(directory) = directories.pop() {
+            for entry in std::fs::read_dir(&directory)? {
+                let entry = entry?;
+
+                // dot paths are not served by default.
+                if entry.file_name().to_string_lossy().starts_with('.') {
+                    continue;
+                }
+
+                let path = entry.path();
+
+                let file_type = entry.file_type()?;
+                if file_type.is_dir() {
+                    directories.push(path);
+                    continue;
+                }
+
+                let metadata = std::fs::metadata(&path)?;
+                if !metadata.is_file() || !self.is_compressible(&path, &metadata) {
+                    continue;
+                }
+
+                if let Err(e) = self.precompress_file(&path, &metadata, compressions, stats) {
+                    warn!("error precompressing {:?}: {}", path, e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn compressions(
+    precompressed: &StaticFilePrecompressedConfiguration,
+    precompress_configuration: &StaticFilePrecompressConfiguration,
+) -> Vec<Compression> {
+    let mut compressions = Vec::new();
+
+    if precompressed.gz {
+        compressions.push(Compression::Gzip);
+    }
+
+    if precompressed.br {
+        compressions.push(Compression::Brotli);
+    }
+
+    if precompress_configuration.zstd {
+        compressions.push(Compression::Zstd);
+    }
+
+    compressions
+}
+
+/// Write compressed siblings for the static root and every mount.
+pub async fn run() -> anyhow::Result<()> {
+    let static_file_configuration = &crate::config::instance().static_file_configuration;
+
+    let precompress_configuration = &static_file_configuration.precompress;
+
+    let roots = std::iter::once((
+        &static_file_configuration.root,
+        &static_file_configuration.precompressed,
+    ))
+    .chain(
+        static_file_configuration
+            .mounts
+            .iter()
+            .map(|mount| (&mount.root, &mount.precompressed)),
+    )
+    .map(|(root, precompressed)| {
+        (
+            PathBuf::from(root),
+            compressions(precompressed, precompress_configuration),
+        )
+    })
+    .collect::<Vec<_>>();
+
+    let precompressor = Precompressor::new(precompress_configuration);
+
+    let stats = tokio::task::spawn_blocking(move || -> anyhow::Result<PrecompressStats> {
+        let mut stats = PrecompressStats::default();
+
+        for (root, compressions) in roots {
+            if ArchiveFormat::from_path(&root).is_some() {
+                info!("skipping archive root {:?}", root);
+                continue;
+            }
+
+            if compressions.is_empty() {
+                info!("no compression enabled for root {:?}", root);
+                continue;
+            }
+
+            info!("precompressing root {:?} {:?}", root, compressions);
+
+            precompressor
+                .precompress_directory(&root, &compressions, &mut stats)
+                .with_context(|| format!("error precompressing root {:?}", root))?;
+        }
+
+        Ok(stats)
+    })
+    .await??;
+
+    info!(
+        "precompress complete files_compressed = {} files_up_to_date = {} files_not_smaller = {} original_bytes = {} compressed_bytes = {} space_saved = {}",
+        stats.files_compressed,
+        stats.files_up_to_date,
+        stats.files_not_smaller,
+        stats.original_bytes,
+        stats.compressed_bytes,
+        stats.space_saved(),
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sibling_path() {
+        assert_eq!(
+            Compression::Gzip.sibling_path(Path::new("/srv/www/app.js")),
+            PathBuf::from("/srv/www/app.js.gz")
+        );
+        assert_eq!(
+            Compression::Brotli.sibling_path(Path::new("index.html")),
+            PathBuf::from("index.html.br")
+        );
+    }
+
+    #[test]
+    fn test_compress_round_trip() {
+        let input = "hello world ".repeat(100);
+
+        let gzip = Compression::Gzip.compress(input.as_bytes()).unwrap();
+        let mut decoded = String::new();
+        std::io::Read::read_to_string(&mut flate2::read::GzDecoder::new(&gzip[..]), &mut decoded)
+            .unwrap();
+        assert_eq!(decoded, input);
+
+        assert!(
+            Compression::Brotli
+                .compress(input.as_bytes())
+                .unwrap()
+                .len()
+                < input.len()
+        );
+        assert!(Compression::Zstd.compress(input.as_bytes()).unwrap().len() < input.len());
+    }
+}
@@ -1,8 +1,24 @@
-use hyper::{body::Incoming, http::Request};
+use http_body_util::Limited;
 
-use std::sync::atomic::{AtomicUsize, Ordering};
+use hyper::{
+    body::Incoming,
+    http::{HeaderValue, Request},
+};
 
-use crate::service::connection::ConnectionID;
+use tokio::time::Instant;
+
+use std::{
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use crate::service::{connection::ConnectionID, request_matcher::normalize_path};
+
+/// Request body limited to request_limits.max_request_body_size.
+pub type RequestBody = Limited<Incoming>;
 
 #[derive(Clone, Copy, Debug)]
 pub struct RequestID(usize);
@@ -16,22 +32,50 @@ impl RequestID {
 #[derive(Debug)]
 pub struct HttpRequest {
     pub connection_id: ConnectionID,
+    pub listener_index: usize,
+    pub peer_ip: Option<IpAddr>,
+    pub peer_uid: Option<u32>,
     pub request_id: RequestID,
-    pub hyper_request: Request<Incoming>,
+    pub external_request_id: HeaderValue,
+    pub hyper_request: Request<()>,
+    /// Decoded and normalized request path, for matching access rules.
+    pub normalized_path: String,
+    body: Mutex<Option<RequestBody>>,
+    pub start_instant: Instant,
 }
 
 impl HttpRequest {
     pub fn new(
         connection_id: ConnectionID,
+        listener_index: usize,
+        peer_ip: Option<IpAddr>,
+        peer_uid: Option<u32>,
         request_id: RequestID,
-        hyper_request: Request<Incoming>,
+        external_request_id: HeaderValue,
+        hyper_request: Request<RequestBody>,
     ) -> Self {
+        let (parts, body) = hyper_request.into_parts();
+
+        let normalized_path = normalize_path(parts.uri.path());
+
         Self {
             connection_id,
+            listener_index,
+            peer_ip,
+            peer_uid,
             request_id,
-            hyper_request,
+            external_request_id,
+            hyper_request: Request::from_parts(parts, ()),
+            normalized_path,
+            body: Mutex::new(Some(body)),
+            start_instant: Instant::now(),
         }
     }
+
+    /// Take ownership of the request body, None if a handler already took it.
+    pub fn take_body(&self) -> Option<RequestBody> {
+        self.body.lock().unwrap().take()
+    }
 }
 
 pub struct RequestIDFactory {
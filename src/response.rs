@@ -1,3 +1,5 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+
 use bytes::Bytes;
 
 use http_body_util::{
@@ -9,10 +11,14 @@ use hyper::http::{header, HeaderValue, Response, StatusCode};
 
 use serde::Serialize;
 
+use sha2::{Digest, Sha256};
+
 use tracing::warn;
 
 use std::convert::Infallible;
 
+use crate::{request::HttpRequest, service::static_file::content_hash::if_none_match_matches};
+
 #[derive(Clone, Copy, Debug)]
 pub enum CacheControl {
     NoCache,
@@ -36,6 +42,9 @@ impl CacheControl {
 pub enum ResponseBodyError {
     #[error("io error: {0}")]
     IoError(#[from] std::io::Error),
+
+    #[error("hyper error: {0}")]
+    HyperError(#[from] hyper::Error),
 }
 
 impl From<Infallible> for ResponseBodyError {
@@ -46,58 +55,157 @@ impl From<Infallible> for ResponseBodyError {
 
 pub type ResponseBody = BoxBody<Bytes, ResponseBodyError>;
 
-pub fn build_json_body_response(
-    http_response_body: ResponseBody,
+/// Strong ETag derived from the SHA-256 digest of the body.
+pub fn build_etag(body: &[u8]) -> HeaderValue {
+    HeaderValue::try_from(format!("\"{}\"", STANDARD.encode(Sha256::digest(body))))
+        .expect("base64 etag is a valid header value")
+}
+
+/// Serialized JSON and its ETag, for handlers whose response never changes.
+#[derive(Debug)]
+pub struct StaticJson {
+    json: Bytes,
+    etag: HeaderValue,
+}
+
+impl StaticJson {
+    pub fn new(response_dto: impl Serialize) -> serde_json::Result<Self> {
+        let json = Bytes::from(serde_json::to_vec(&response_dto)?);
+        let etag = build_etag(&json);
+
+        Ok(Self { json, etag })
+    }
+}
+
+fn build_json_etag_response(
+    request: &HttpRequest,
+    json: Bytes,
+    etag: &HeaderValue,
     cache_control: CacheControl,
 ) -> Response<ResponseBody> {
-    Response::builder()
+    let if_none_match_option = request
+        .hyper_request
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok());
+
+    let builder = Response::builder()
+        .header(header::ETAG, etag)
+        .header(header::CACHE_CONTROL, cache_control.header_value());
+
+    if if_none_match_option.is_some_and(|if_none_match| {
+        if_none_match_matches(if_none_match, etag.to_str().unwrap_or_default())
+    }) {
+        return builder
+            .status(StatusCode::NOT_MODIFIED)
+            .body(empty_response_body())
+            .unwrap();
+    }
+
+    builder
         .status(StatusCode::OK)
         .header(header::CONTENT_TYPE, "application/json")
-        .header(header::CACHE_CONTROL, cache_control.header_value())
-        .body(http_response_body)
+        .body(Full::from(json).map_err(|never| never.into()).boxed())
         .unwrap()
 }
 
+/// JSON response with an ETag computed from the body, answering a matching If-None-Match with 304.
+pub fn build_json_body_response(
+    request: &HttpRequest,
+    json: Bytes,
+    cache_control: CacheControl,
+) -> Response<ResponseBody> {
+    let etag = build_etag(&json);
+
+    build_json_etag_response(request, json, &etag, cache_control)
+}
+
 pub fn build_json_response(
+    request: &HttpRequest,
     response_dto: impl Serialize,
     cache_control: CacheControl,
 ) -> Response<ResponseBody> {
-    let json_result = serde_json::to_string(&response_dto);
+    let json_result = serde_json::to_vec(&response_dto);
 
     match json_result {
         Err(e) => {
             warn!("build_json_response serialization error {}", e);
 
-            Response::builder()
-                .status(StatusCode::INTERNAL_SERVER_ERROR)
-                .header(header::CACHE_CONTROL, CacheControl::NoCache.header_value())
-                .body(empty_response_body())
-                .unwrap()
+            build_status_code_response(StatusCode::INTERNAL_SERVER_ERROR, CacheControl::NoCache)
         }
-        Ok(json_string) => build_json_body_response(
-            Full::from(json_string)
-                .map_err(|never| never.into())
-                .boxed(),
-            cache_control,
-        ),
+        Ok(json) => build_json_body_response(request, Bytes::from(json), cache_control),
     }
 }
 
+/// JSON response using the precomputed ETag.
+pub fn build_static_json_response(
+    request: &HttpRequest,
+    static_json: &StaticJson,
+    cache_control: CacheControl,
+) -> Response<ResponseBody> {
+    build_json_etag_response(
+        request,
+        static_json.json.clone(),
+        &static_json.etag,
+        cache_control,
+    )
+}
+
+fn status_code_allows_body(status_code: StatusCode) -> bool {
+    !(status_code.is_informational()
+        || status_code == StatusCode::NO_CONTENT
+        || status_code == StatusCode::NOT_MODIFIED)
+}
+
+fn build_status_code_page(status_code: StatusCode) -> String {
+    let status = format!(
+        "{} {}",
+        status_code.as_u16(),
+        status_code.canonical_reason().unwrap_or_default()
+    );
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n</head>\n<body>\n<h1>{0}</h1>\n</body>\n</html>\n",
+        status.trim_end()
+    )
+}
+
+/// Build a response with a minimal built-in page for the status code.
 pub fn build_status_code_response(
     status_code: StatusCode,
     cache_control: CacheControl,
 ) -> Response<ResponseBody> {
+    let builder = Response::builder()
+        .status(status_code)
+        .header(header::CACHE_CONTROL, cache_control.header_value());
+
+    if !status_code_allows_body(status_code) {
+        return builder.body(empty_response_body()).unwrap();
+    }
+
+    builder
+        .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+        .body(
+            Full::from(build_status_code_page(status_code))
+                .map_err(|never| never.into())
+                .boxed(),
+        )
+        .unwrap()
+}
+
+/// Build a redirect response with an empty body.
+pub fn build_redirect_response(
+    status_code: StatusCode,
+    location: &str,
+    cache_control: CacheControl,
+) -> Result<Response<ResponseBody>, hyper::http::Error> {
     Response::builder()
         .status(status_code)
+        .header(header::LOCATION, location)
         .header(header::CACHE_CONTROL, cache_control.header_value())
         .body(empty_response_body())
-        .unwrap()
 }
 
 pub fn empty_response_body() -> ResponseBody {
     Empty::new().map_err(|never| never.into()).boxed()
 }
-
-pub fn static_string_response_body(s: &'static str) -> ResponseBody {
-    Full::from(s).map_err(|e| e.into()).boxed()
-}
@@ -21,26 +21,43 @@ pub struct Server {
 }
 
 impl Server {
-    pub async fn new(handlers: Box<dyn RequestHandler>) -> Self {
+    pub async fn new(handlers: Box<dyn RequestHandler>) -> anyhow::Result<Self> {
         let request_id_factory = RequestIDFactory::new();
-        let connection_handler = ConnectionHandler::new(handlers, request_id_factory);
+        let connection_handler = ConnectionHandler::new(handlers, request_id_factory).await?;
 
         let mut join_set = JoinSet::new();
 
         let configuration = crate::config::instance();
 
-        for listener_configuration in &configuration.server_configuration.listeners {
+        let ip_access_service = crate::service::ip_access::ip_access_service_instance();
+
+        for (listener_index, listener_configuration) in configuration
+            .server_configuration
+            .listeners
+            .iter()
+            .enumerate()
+        {
             let connection_handler_clone = Arc::clone(&connection_handler);
+            let ip_access_rules = ip_access_service.listener_rules(listener_index);
             join_set.spawn(async move {
                 match listener_configuration.socket_type {
                     ServerSocketType::Tcp => {
-                        let server =
-                            TCPServer::new(connection_handler_clone, listener_configuration).await;
+                        let server = TCPServer::new(
+                            connection_handler_clone,
+                            listener_configuration,
+                            listener_index,
+                            ip_access_rules,
+                        )
+                        .await;
                         server.run().await?;
                     }
                     ServerSocketType::Unix => {
-                        let server =
-                            UnixServer::new(connection_handler_clone, listener_configuration).await;
+                        let server = UnixServer::new(
+                            connection_handler_clone,
+                            listener_configuration,
+                            listener_index,
+                        )
+                        .await;
                         server.run().await?;
                     }
                 };
@@ -48,7 +65,7 @@ impl Server {
             });
         }
 
-        Self { join_set }
+        Ok(Self { join_set })
     }
 
     pub async fn run(mut self) -> anyhow::Result<()> {
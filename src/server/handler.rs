@@ -1,3 +1,5 @@
+use http_body_util::Limited;
+
 use hyper::{
     http::{Request, Response},
     service::service_fn,
@@ -14,44 +16,82 @@ use tokio::{
 
 use tracing::{debug, info, instrument, warn, Instrument};
 
-use std::{convert::Infallible, sync::Arc};
+use std::{convert::Infallible, net::IpAddr, sync::Arc};
 
 use crate::{
+    config::ServerRequestLimitsConfiguration,
     handlers::RequestHandler,
     request::{HttpRequest, RequestID, RequestIDFactory},
     response::ResponseBody,
     server::HyperReadWrite,
-    service::connection::{ConnectionGuard, ConnectionID},
+    service::{
+        connection::{
+            ConnectionGuard, ConnectionID, ConnectionTrackerService, RequestLimitViolation,
+        },
+        request_id::RequestIdService,
+    },
 };
 
+/// hyper panics on a smaller http1 max_buf_size.
+const MIN_HTTP1_MAX_HEADER_SIZE: usize = 8192;
+
+fn request_limit_violation(
+    error: &(dyn std::error::Error + Send + Sync + 'static),
+) -> Option<RequestLimitViolation> {
+    let hyper_error = error.downcast_ref::<hyper::Error>()?;
+
+    if hyper_error.is_parse_too_large() {
+        Some(RequestLimitViolation::HeaderLimit)
+    } else {
+        None
+    }
+}
+
 pub struct ConnectionHandler {
     request_handler: Box<dyn RequestHandler>,
     request_id_factory: RequestIDFactory,
-    connection_timeout_durations: Vec<Duration>,
+    request_id_service: &'static RequestIdService,
+    connection_tracker: &'static ConnectionTrackerService,
+    request_limits: &'static ServerRequestLimitsConfiguration,
+    max_lifetime: Duration,
+    graceful_shutdown_timeout: Duration,
 }
 
 impl ConnectionHandler {
-    pub fn new(
+    pub async fn new(
         request_handler: Box<dyn RequestHandler>,
         request_id_factory: RequestIDFactory,
-    ) -> Arc<Self> {
+    ) -> anyhow::Result<Arc<Self>> {
         let server_configuration = &crate::config::instance().server_configuration;
 
-        let connection_timeout_durations = vec![
-            server_configuration.connection.max_lifetime,
-            server_configuration.connection.graceful_shutdown_timeout,
-        ];
+        let request_limits = &server_configuration.request_limits;
+
+        if request_limits.http1_max_header_size < MIN_HTTP1_MAX_HEADER_SIZE {
+            anyhow::bail!(
+                "ConnectionHandler::new: request_limits.http1_max_header_size must be at least {}",
+                MIN_HTTP1_MAX_HEADER_SIZE
+            );
+        }
+
+        debug!("request_limits = {:?}", request_limits);
+
+        let connection_configuration = &server_configuration.connection;
 
         debug!(
-            "connection_timeout_durations = {:?}",
-            connection_timeout_durations
+            "max_lifetime = {:?} graceful_shutdown_timeout = {:?}",
+            connection_configuration.max_lifetime,
+            connection_configuration.graceful_shutdown_timeout
         );
 
-        Arc::new(Self {
+        Ok(Arc::new(Self {
             request_handler,
             request_id_factory,
-            connection_timeout_durations,
-        })
+            request_id_service: crate::service::request_id::request_id_service_instance(),
+            connection_tracker: ConnectionTrackerService::instance().await,
+            request_limits,
+            max_lifetime: connection_configuration.max_lifetime,
+            graceful_shutdown_timeout: connection_configuration.graceful_shutdown_timeout,
+        }))
     }
 
     #[instrument(
@@ -61,39 +101,50 @@ impl ConnectionHandler {
             id = request_id.as_usize(),
             method = %hyper_request.method(),
             uri = %hyper_request.uri(),
+            request_id,
             micros,
             status,
+            user,
         )
     )]
     async fn handle_request(
         self: Arc<Self>,
         connection_id: ConnectionID,
+        listener_index: usize,
+        peer_ip: Option<IpAddr>,
+        peer_uid: Option<u32>,
         request_id: RequestID,
         hyper_request: Request<hyper::body::Incoming>,
     ) -> Result<Response<ResponseBody>, Infallible> {
-        let start_time = Instant::now();
-
-        let http_request = HttpRequest::new(connection_id, request_id, hyper_request);
+        let external_request_id = self
+            .request_id_service
+            .request_id(hyper_request.headers(), peer_ip);
 
-        let result = self.request_handler.handle(&http_request).await;
+        tracing::Span::current().record(
+            "request_id",
+            external_request_id.to_str().unwrap_or_default(),
+        );
 
-        let duration = Instant::now() - start_time;
+        let max_request_body_size = self.request_limits.max_request_body_size;
 
-        let status = result.status();
+        let http_request = HttpRequest::new(
+            connection_id,
+            listener_index,
+            peer_ip,
+            peer_uid,
+            request_id,
+            external_request_id,
+            hyper_request.map(|body| Limited::new(body, max_request_body_size)),
+        );
 
-        tracing::Span::current()
-            .record("micros", duration.as_micros())
-            .record("status", status.as_u16());
+        let mut response = self.request_handler.handle(&http_request).await;
 
-        if status.is_informational() || status.is_success() || status.is_redirection() {
-            debug!("request complete");
-        } else if status.is_client_error() {
-            info!("request complete");
-        } else {
-            warn!("request complete");
-        };
+        response.headers_mut().insert(
+            self.request_id_service.header_name().clone(),
+            http_request.external_request_id.clone(),
+        );
 
-        Ok(result)
+        Ok(response)
     }
 
     #[instrument(
@@ -108,6 +159,7 @@ impl ConnectionHandler {
         self: Arc<Self>,
         stream: impl HyperReadWrite,
         connection: ConnectionGuard,
+        listener_index: usize,
     ) {
         debug!("begin handle_connection");
 
@@ -117,31 +169,87 @@ impl ConnectionHandler {
             let request_id = self.request_id_factory.new_request_id();
 
             Arc::clone(&self)
-                .handle_request(connection.id, request_id, hyper_request)
+                .handle_request(
+                    connection.id,
+                    listener_index,
+                    connection.peer_ip,
+                    connection.peer_uid,
+                    request_id,
+                    hyper_request,
+                )
                 .in_current_span()
         });
 
         let mut builder = HyperConnAutoBuilder::new(TokioExecutor::new());
 
-        builder.http1().timer(TokioTimer::new());
-        builder.http2().timer(TokioTimer::new());
+        builder
+            .http1()
+            .timer(TokioTimer::new())
+            .header_read_timeout(self.request_limits.http1_header_read_timeout)
+            .max_buf_size(self.request_limits.http1_max_header_size)
+            .max_headers(self.request_limits.http1_max_headers);
+        builder
+            .http2()
+            .timer(TokioTimer::new())
+            .max_header_list_size(self.request_limits.http2_max_header_list_size);
 
         let hyper_conn = builder.serve_connection(stream, service);
         pin!(hyper_conn);
 
-        for (iter, sleep_duration) in self.connection_timeout_durations.iter().enumerate() {
-            debug!("iter = {} sleep_duration = {:?}", iter, sleep_duration);
+        // HTTP version detection and HTTP/2 have no header read timeout, so bound the wait for the first request.
+        let first_request_timeout = tokio::time::sleep(self.request_limits.first_request_timeout);
+        pin!(first_request_timeout);
+        let mut first_request_timeout_pending = true;
+
+        // max_lifetime, then graceful_shutdown_timeout once graceful shutdown has started.
+        let deadline = tokio::time::sleep(self.max_lifetime);
+        pin!(deadline);
+        let mut graceful_shutdown_started = false;
+
+        loop {
             tokio::select! {
                 res = hyper_conn.as_mut() => {
                     match res {
                         Ok(()) => debug!("after polling conn, no error"),
-                        Err(e) =>  warn!("error serving connection: {:?}", e),
+                        Err(e) => match request_limit_violation(e.as_ref()) {
+                            Some(violation) => {
+                                self.connection_tracker
+                                    .add_request_limit_violation(connection.id, violation)
+                                    .await;
+                            }
+                            None => warn!("error serving connection: {:?}", e),
+                        },
                     };
                     break;
                 }
-                _ = tokio::time::sleep(*sleep_duration) => {
-                    info!("iter = {} got timeout_interval, calling conn.graceful_shutdown", iter);
+                _ = &mut first_request_timeout, if first_request_timeout_pending => {
+                    first_request_timeout_pending = false;
+                    if connection.num_requests() == 0 && !graceful_shutdown_started {
+                        self.connection_tracker
+                            .add_request_limit_violation(
+                                connection.id,
+                                RequestLimitViolation::HeaderReadTimeout,
+                            )
+                            .await;
+                        info!("no request before first_request_timeout, calling conn.graceful_shutdown");
+                        hyper_conn.as_mut().graceful_shutdown();
+                        graceful_shutdown_started = true;
+                        deadline
+                            .as_mut()
+                            .reset(Instant::now() + self.graceful_shutdown_timeout);
+                    }
+                }
+                _ = &mut deadline => {
+                    if graceful_shutdown_started {
+                        info!("graceful_shutdown_timeout expired, closing connection");
+                        break;
+                    }
+                    info!("max_lifetime expired, calling conn.graceful_shutdown");
                     hyper_conn.as_mut().graceful_shutdown();
+                    graceful_shutdown_started = true;
+                    deadline
+                        .as_mut()
+                        .reset(Instant::now() + self.graceful_shutdown_timeout);
                 }
             }
         }
@@ -156,7 +264,8 @@ impl ConnectionHandler {
         self: &Arc<Self>,
         stream: impl HyperReadWrite,
         connection: ConnectionGuard,
+        listener_index: usize,
     ) {
-        tokio::spawn(Arc::clone(self).handle_connection(stream, connection));
+        tokio::spawn(Arc::clone(self).handle_connection(stream, connection, listener_index));
     }
 }
@@ -2,32 +2,39 @@ use anyhow::Context;
 
 use hyper_util::rt::TokioIo;
 
-use tracing::{info, warn};
+use tracing::{debug, info, warn};
 
 use tokio::net::TcpListener;
 
 use std::sync::Arc;
 
 use crate::{
-    config::ServerSocketType, server::handler::ConnectionHandler,
-    service::connection::ConnectionTrackerService,
+    config::ServerSocketType,
+    server::handler::ConnectionHandler,
+    service::{connection::ConnectionTrackerService, ip_access::IpAccessRules},
 };
 
 pub struct TCPServer {
     connection_handler: Arc<ConnectionHandler>,
     connection_tracker: &'static ConnectionTrackerService,
     listener_configuration: &'static crate::config::ServerListenerConfiguration,
+    listener_index: usize,
+    ip_access_rules: &'static IpAccessRules,
 }
 
 impl TCPServer {
     pub async fn new(
         connection_handler: Arc<ConnectionHandler>,
         listener_configuration: &'static crate::config::ServerListenerConfiguration,
+        listener_index: usize,
+        ip_access_rules: &'static IpAccessRules,
     ) -> Self {
         Self {
             connection_handler,
             connection_tracker: ConnectionTrackerService::instance().await,
             listener_configuration,
+            listener_index,
+            ip_access_rules,
         }
     }
 
@@ -45,7 +52,13 @@ impl TCPServer {
         info!("listening on tcp {:?}", local_addr);
 
         loop {
-            let (tcp_stream, _remote_addr) = tcp_listener.accept().await?;
+            let (tcp_stream, remote_addr) = tcp_listener.accept().await?;
+
+            // dropping the stream closes the socket before hyper sees it.
+            if !self.ip_access_rules.allows(remote_addr.ip()) {
+                debug!("ip access denied remote_addr = {:?}", remote_addr);
+                continue;
+            }
 
             if let Err(e) = tcp_stream.set_nodelay(true) {
                 warn!("error setting tcp no delay {:?}", e);
@@ -54,11 +67,14 @@ impl TCPServer {
 
             if let Some(connection) = self
                 .connection_tracker
-                .add_connection(ServerSocketType::Tcp)
+                .add_connection(ServerSocketType::Tcp, Some(remote_addr.ip()), None)
                 .await
             {
-                self.connection_handler
-                    .start_connection_handler(TokioIo::new(tcp_stream), connection);
+                self.connection_handler.start_connection_handler(
+                    TokioIo::new(tcp_stream),
+                    connection,
+                    self.listener_index,
+                );
             }
         }
     }
@@ -17,17 +17,20 @@ pub struct UnixServer {
     connection_handler: Arc<ConnectionHandler>,
     connection_tracker: &'static ConnectionTrackerService,
     listener_configuration: &'static crate::config::ServerListenerConfiguration,
+    listener_index: usize,
 }
 
 impl UnixServer {
     pub async fn new(
         connection_handler: Arc<ConnectionHandler>,
         listener_configuration: &'static crate::config::ServerListenerConfiguration,
+        listener_index: usize,
     ) -> Self {
         Self {
             connection_handler,
             connection_tracker: ConnectionTrackerService::instance().await,
             listener_configuration,
+            listener_index,
         }
     }
 
@@ -50,13 +53,18 @@ impl UnixServer {
         loop {
             let (unix_stream, _remote_addr) = unix_listener.accept().await?;
 
+            let peer_uid = unix_stream.peer_cred().ok().map(|cred| cred.uid());
+
             if let Some(connection) = self
                 .connection_tracker
-                .add_connection(ServerSocketType::Unix)
+                .add_connection(ServerSocketType::Unix, None, peer_uid)
                 .await
             {
-                self.connection_handler
-                    .start_connection_handler(TokioIo::new(unix_stream), connection);
+                self.connection_handler.start_connection_handler(
+                    TokioIo::new(unix_stream),
+                    connection,
+                    self.listener_index,
+                );
             }
         }
     }
@@ -1,2 +1,12 @@
+pub mod auth;
+pub mod canonical_redirect;
 pub mod connection;
+pub mod cors;
+pub mod error_page;
+pub mod forwarded_headers;
+pub mod ip_access;
+pub mod rate_limit;
+pub mod request_id;
+pub mod request_matcher;
+pub mod response_header;
 pub mod static_file;
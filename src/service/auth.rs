@@ -0,0 +1,634 @@
+use ahash::{AHashMap, AHashSet};
+
+use anyhow::Context;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use hyper::http::{header, HeaderMap, HeaderValue};
+
+use sha2::{Digest, Sha256};
+
+use tokio::sync::OnceCell;
+
+use tracing::{debug, warn};
+
+use std::sync::{Arc, RwLock};
+
+use crate::{
+    request::HttpRequest,
+    service::request_matcher::{request_host, RequestMatchData, RequestMatcher},
+};
+
+const DEFAULT_REALM: &str = "rhs";
+
+/// Successful password verifications are cached so bcrypt and argon2
+/// are not run on every request, up to this many entries.
+const MAX_VERIFIED_CREDENTIALS: usize = 1024;
+
+#[derive(Debug)]
+enum PasswordHash {
+    Bcrypt(String),
+    Argon2(String),
+}
+
+impl PasswordHash {
+    fn new(hash: &str) -> anyhow::Result<Self> {
+        if ["$2a$", "$2b$", "$2x$", "$2y$"]
+            .iter()
+            .any(|prefix| hash.starts_with(prefix))
+        {
+            Ok(Self::Bcrypt(hash.to_owned()))
+        } else if hash.starts_with("$argon2") {
+            argon2::PasswordHash::new(hash)
+                .map_err(|e| anyhow::anyhow!("invalid argon2 hash: {}", e))?;
+            Ok(Self::Argon2(hash.to_owned()))
+        } else {
+            anyhow::bail!("unsupported password hash, use bcrypt or argon2")
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        match self {
+            Self::Bcrypt(hash) | Self::Argon2(hash) => hash,
+        }
+    }
+
+    fn verify(&self, password: &str) -> bool {
+        match self {
+            Self::Bcrypt(hash) => bcrypt::verify(password, hash).unwrap_or(false),
+            Self::Argon2(hash) => {
+                use argon2::PasswordVerifier;
+
+                argon2::PasswordHash::new(hash).is_ok_and(|hash| {
+                    argon2::Argon2::default()
+                        .verify_password(password.as_bytes(), &hash)
+                        .is_ok()
+                })
+            }
+        }
+    }
+}
+
+/// Parse htpasswd file contents, one `user:hash` per line.
+fn parse_htpasswd(contents: &str) -> anyhow::Result<Vec<(String, PasswordHash)>> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let (username, hash) = line
+                .split_once(':')
+                .with_context(|| format!("invalid htpasswd line {:?}", line))?;
+
+            let hash = PasswordHash::new(hash)
+                .with_context(|| format!("invalid hash for user {:?}", username))?;
+
+            Ok((username.to_owned(), hash))
+        })
+        .collect()
+}
+
+enum Credentials {
+    Basic { username: String, password: String },
+    Bearer(String),
+}
+
+impl Credentials {
+    fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+
+        let (scheme, parameters) = value.split_once(' ')?;
+        let parameters = parameters.trim();
+
+        if scheme.eq_ignore_ascii_case("basic") {
+            let decoded = String::from_utf8(STANDARD.decode(parameters).ok()?).ok()?;
+            let (username, password) = decoded.split_once(':')?;
+
+            Some(Self::Basic {
+                username: username.to_owned(),
+                password: password.to_owned(),
+            })
+        } else if scheme.eq_ignore_ascii_case("bearer") {
+            Some(Self::Bearer(parameters.to_owned()))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug)]
+struct AuthRule {
+    request_matcher: RequestMatcher,
+    route_ids: Vec<String>,
+    realm: String,
+    users: AHashMap<String, Arc<PasswordHash>>,
+    // sha256 of token to token name
+    bearer_tokens: AHashMap<[u8; 32], String>,
+    challenges: Vec<HeaderValue>,
+}
+
+impl AuthRule {
+    fn new(rule_configuration: &crate::config::AuthRule) -> anyhow::Result<Self> {
+        let request_matcher = RequestMatcher::new(
+            &rule_configuration.host_regex,
+            &rule_configuration.path_regex,
+        )?;
+
+        let mut users = AHashMap::new();
+
+        for (username, hash) in &rule_configuration.users {
+            let hash = PasswordHash::new(hash)
+                .with_context(|| format!("AuthRule::new: invalid hash for user {:?}", username))?;
+
+            users.insert(username.clone(), Arc::new(hash));
+        }
+
+        if let Some(htpasswd_file) = &rule_configuration.htpasswd_file {
+            let contents = std::fs::read_to_string(htpasswd_file)
+                .with_context(|| format!("AuthRule::new: error reading {:?}", htpasswd_file))?;
+
+            let entries = parse_htpasswd(&contents)
+                .with_context(|| format!("AuthRule::new: error parsing {:?}", htpasswd_file))?;
+
+            for (username, hash) in entries {
+                if users.insert(username.clone(), Arc::new(hash)).is_some() {
+                    anyhow::bail!("AuthRule::new: duplicate user {:?}", username);
+                }
+            }
+        }
+
+        let bearer_tokens: AHashMap<[u8; 32], String> = rule_configuration
+            .bearer_tokens
+            .iter()
+            .map(|(name, token)| (Sha256::digest(token).into(), name.clone()))
+            .collect();
+
+        if users.is_empty() && bearer_tokens.is_empty() {
+            anyhow::bail!("AuthRule::new: users, htpasswd_file or bearer_tokens must be set");
+        }
+
+        let realm = rule_configuration.realm.as_deref().unwrap_or(DEFAULT_REALM);
+
+        if realm.contains(['"', '\\']) {
+            anyhow::bail!("AuthRule::new: invalid realm {:?}", realm);
+        }
+
+        let mut challenges = Vec::new();
+
+        if !users.is_empty() {
+            challenges.push(
+                HeaderValue::try_from(format!("Basic realm=\"{}\", charset=\"UTF-8\"", realm))
+                    .context("AuthRule::new: invalid realm")?,
+            );
+        }
+
+        if !bearer_tokens.is_empty() {
+            challenges.push(
+                HeaderValue::try_from(format!("Bearer realm=\"{}\"", realm))
+                    .context("AuthRule::new: invalid realm")?,
+            );
+        }
+
+        Ok(Self {
+            request_matcher,
+            route_ids: rule_configuration.route_ids.clone(),
+            realm: realm.to_owned(),
+            users,
+            bearer_tokens,
+            challenges,
+        })
+    }
+
+    /// A route id matches itself and routes below it, so `commands` matches `commands/uptime`.
+    fn matches_route_id(&self, route_id_option: Option<&str>) -> bool {
+        if self.route_ids.is_empty() {
+            return true;
+        }
+
+        route_id_option.is_some_and(|route_id| {
+            self.route_ids.iter().any(|rule_route_id| {
+                route_id
+                    .strip_prefix(rule_route_id.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+        })
+    }
+}
+
+/// An auth rule that can apply to a route.
+#[derive(Debug)]
+pub struct AuthRuleSummary {
+    pub index: usize,
+    pub realm: String,
+    pub host_regex: Option<String>,
+    pub path_regex: Option<String>,
+}
+
+/// Outcome of checking a request against the auth rules.
+pub enum AuthResult<'a> {
+    NotRequired,
+    Authenticated(String),
+    Unauthorized(&'a [HeaderValue]),
+}
+
+#[derive(Debug)]
+pub struct AuthService {
+    rules: Vec<AuthRule>,
+    verified_credentials: RwLock<AHashSet<[u8; 32]>>,
+}
+
+impl AuthService {
+    fn new() -> anyhow::Result<Self> {
+        let rules = crate::config::instance()
+            .auth_rules
+            .iter()
+            .map(AuthRule::new)
+            .collect::<anyhow::Result<Vec<_>>>()
+            .context("AuthService::new: error in auth_rules")?;
+
+        debug!("auth rules = {}", rules.len());
+
+        Ok(Self {
+            rules,
+            verified_credentials: RwLock::new(AHashSet::new()),
+        })
+    }
+
+    async fn verify_password(&self, hash: &Arc<PasswordHash>, password: String) -> bool {
+        let cache_key: [u8; 32] = Sha256::new()
+            .chain_update(hash.as_str())
+            .chain_update([0])
+            .chain_update(&password)
+            .finalize()
+            .into();
+
+        if self
+            .verified_credentials
+            .read()
+            .unwrap()
+            .contains(&cache_key)
+        {
+            return true;
+        }
+
+        let hash = Arc::clone(hash);
+        let verified = tokio::task::spawn_blocking(move || hash.verify(&password))
+            .await
+            .unwrap_or_else(|e| {
+                warn!("verify_password spawn_blocking error: {}", e);
+                false
+            });
+
+        if verified {
+            let mut verified_credentials = self.verified_credentials.write().unwrap();
+            if verified_credentials.len() >= MAX_VERIFIED_CREDENTIALS {
+                verified_credentials.clear();
+            }
+            verified_credentials.insert(cache_key);
+        }
+
+        verified
+    }
+
+    /// Hash the password of an unknown user against a hash of the rule, so unknown
+    /// usernames take as long to reject as wrong passwords.
+    async fn verify_unknown_user_password(&self, rule: &AuthRule, password: String) {
+        let Some(hash) = rule.users.values().next() else {
+            return;
+        };
+
+        let hash = Arc::clone(hash);
+        if let Err(e) = tokio::task::spawn_blocking(move || hash.verify(&password)).await {
+            warn!("verify_unknown_user_password spawn_blocking error: {}", e);
+        }
+    }
+
+    async fn authenticate_rule<'a>(
+        &'a self,
+        rule: &'a AuthRule,
+        headers: &HeaderMap,
+    ) -> AuthResult<'a> {
+        let credentials = Credentials::from_headers(headers);
+
+        let username_option = match credentials {
+            None => None,
+            Some(Credentials::Basic { username, password }) => {
+                let verified = match rule.users.get(&username) {
+                    Some(hash) => self.verify_password(hash, password).await,
+                    None => {
+                        self.verify_unknown_user_password(rule, password).await;
+                        false
+                    }
+                };
+
+                if verified {
+                    Some(username)
+                } else {
+                    debug!("basic auth failed username = {:?}", username);
+                    None
+                }
+            }
+            Some(Credentials::Bearer(token)) => {
+                let digest: [u8; 32] = Sha256::digest(token).into();
+                let name_option = rule.bearer_tokens.get(&digest).cloned();
+                if name_option.is_none() {
+                    debug!("bearer auth failed");
+                }
+                name_option
+            }
+        };
+
+        match username_option {
+            Some(username) => AuthResult::Authenticated(username),
+            None => AuthResult::Unauthorized(&rule.challenges),
+        }
+    }
+
+    /// Rules that can apply to requests for path, in the order they are checked.
+    /// Rules with a host_regex are included since the host is only known per request.
+    pub fn route_rules(&self, path: &str, route_id_option: Option<&str>) -> Vec<AuthRuleSummary> {
+        self.rules
+            .iter()
+            .enumerate()
+            .filter(|(_, rule)| {
+                rule.request_matcher.matches_path(path) && rule.matches_route_id(route_id_option)
+            })
+            .map(|(index, rule)| AuthRuleSummary {
+                index,
+                realm: rule.realm.clone(),
+                host_regex: rule.request_matcher.host_regex().map(str::to_owned),
+                path_regex: rule.request_matcher.path_regex().map(str::to_owned),
+            })
+            .collect()
+    }
+
+    /// The first rule matching the host and path or route id decides which credentials are accepted.
+    ///
+    /// path is a normalized path, see [`crate::service::request_matcher::normalize_path`].
+    pub async fn authenticate(
+        &self,
+        request: &HttpRequest,
+        path: &str,
+        route_id_option: Option<&str>,
+    ) -> AuthResult<'_> {
+        let request_match_data = RequestMatchData {
+            host_option: request_host(request),
+            path_option: Some(path),
+        };
+
+        self.authenticate_match(
+            &request_match_data,
+            request.hyper_request.headers(),
+            route_id_option,
+        )
+        .await
+    }
+
+    async fn authenticate_match(
+        &self,
+        request_match_data: &RequestMatchData<'_>,
+        headers: &HeaderMap,
+        route_id_option: Option<&str>,
+    ) -> AuthResult<'_> {
+        match self.find_rule_index(request_match_data, route_id_option) {
+            None => AuthResult::NotRequired,
+            Some(rule_index) => {
+                self.authenticate_rule(&self.rules[rule_index], headers)
+                    .await
+            }
+        }
+    }
+
+    fn find_rule_index(
+        &self,
+        request_match_data: &RequestMatchData<'_>,
+        route_id_option: Option<&str>,
+    ) -> Option<usize> {
+        self.rules.iter().position(|rule| {
+            rule.request_matcher.matches(request_match_data)
+                && rule.matches_route_id(route_id_option)
+        })
+    }
+
+    /// Index of the rule that decides for a normalized path, None if no credentials are required.
+    /// Callers checking many paths can authenticate once per rule with authenticate_rule_index.
+    pub fn rule_index(&self, request: &HttpRequest, path: &str) -> Option<usize> {
+        self.find_rule_index(
+            &RequestMatchData {
+                host_option: request_host(request),
+                path_option: Some(path),
+            },
+            None,
+        )
+    }
+
+    /// Returns true if the request credentials are accepted by the rule at rule_index.
+    pub async fn authenticate_rule_index(&self, request: &HttpRequest, rule_index: usize) -> bool {
+        matches!(
+            self.authenticate_rule(&self.rules[rule_index], request.hyper_request.headers())
+                .await,
+            AuthResult::Authenticated(_)
+        )
+    }
+}
+
+/// Shared caches must not store responses that required credentials.
+pub fn make_cache_control_private(headers: &mut HeaderMap) {
+    let Some(cache_control) = headers
+        .get(header::CACHE_CONTROL)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return;
+    };
+
+    let directives = cache_control
+        .split(',')
+        .map(str::trim)
+        .filter(|directive| {
+            !directive.eq_ignore_ascii_case("public") && !directive.eq_ignore_ascii_case("private")
+        })
+        .collect::<Vec<_>>();
+
+    if directives
+        .iter()
+        .any(|directive| directive.eq_ignore_ascii_case("no-store"))
+    {
+        return;
+    }
+
+    let cache_control = std::iter::once("private")
+        .chain(directives)
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    if let Ok(value) = HeaderValue::try_from(cache_control) {
+        headers.insert(header::CACHE_CONTROL, value);
+    }
+}
+
+static AUTH_SERVICE_INSTANCE: OnceCell<AuthService> = OnceCell::const_new();
+
+pub fn create_auth_service_instance() -> anyhow::Result<()> {
+    let auth_service = AuthService::new()?;
+
+    AUTH_SERVICE_INSTANCE
+        .set(auth_service)
+        .context("AUTH_SERVICE_INSTANCE.set error")?;
+
+    Ok(())
+}
+
+pub fn auth_service_instance() -> &'static AuthService {
+    AUTH_SERVICE_INSTANCE.get().unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_password_hash() {
+        let bcrypt_hash = bcrypt::hash("secret", 4).unwrap();
+
+        let entries = parse_htpasswd(&format!("# comment\n\nalice:{}\n", bcrypt_hash)).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].0, "alice");
+        assert!(entries[0].1.verify("secret"));
+        assert!(!entries[0].1.verify("wrong"));
+
+        let argon2_hash = {
+            use argon2::password_hash::{PasswordHasher, SaltString};
+
+            let argon2 = argon2::Argon2::new(
+                argon2::Algorithm::Argon2id,
+                argon2::Version::V0x13,
+                argon2::Params::new(16, 2, 1, None).unwrap(),
+            );
+            let salt = SaltString::encode_b64(b"0123456789abcdef").unwrap();
+            let hash = argon2.hash_password(b"password", &salt).unwrap();
+
+            PasswordHash::new(&hash.to_string()).unwrap()
+        };
+        assert!(argon2_hash.verify("password"));
+        assert!(!argon2_hash.verify("wrong"));
+
+        assert!(PasswordHash::new("$apr1$salt$hash").is_err());
+        assert!(parse_htpasswd("alice").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_normalized_path() {
+        use crate::service::request_matcher::normalize_path;
+
+        let auth_service = AuthService {
+            rules: vec![AuthRule::new(&crate::config::AuthRule {
+                host_regex: None,
+                path_regex: Some("^/private/".to_owned()),
+                route_ids: Vec::new(),
+                realm: None,
+                users: [("alice".to_owned(), bcrypt::hash("secret", 4).unwrap())].into(),
+                htpasswd_file: None,
+                bearer_tokens: Default::default(),
+            })
+            .unwrap()],
+            verified_credentials: RwLock::new(AHashSet::new()),
+        };
+
+        let basic_auth_headers = |credentials: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                header::AUTHORIZATION,
+                HeaderValue::try_from(format!("Basic {}", STANDARD.encode(credentials))).unwrap(),
+            );
+            headers
+        };
+
+        let authenticate = |path: &'static str, headers: HeaderMap| {
+            let auth_service = &auth_service;
+            async move {
+                let path = normalize_path(path);
+                let request_match_data = RequestMatchData {
+                    host_option: None,
+                    path_option: Some(&path),
+                };
+                match auth_service
+                    .authenticate_match(&request_match_data, &headers, None)
+                    .await
+                {
+                    AuthResult::NotRequired => "not required".to_owned(),
+                    AuthResult::Authenticated(username) => username,
+                    AuthResult::Unauthorized(_) => "unauthorized".to_owned(),
+                }
+            }
+        };
+
+        for path in [
+            "/private/x",
+            "/%70rivate/x",
+            "//private/x",
+            "/./private/x",
+            "/public/../private/x",
+        ] {
+            assert_eq!(
+                authenticate(path, HeaderMap::new()).await,
+                "unauthorized",
+                "path = {:?}",
+                path
+            );
+        }
+
+        assert_eq!(
+            authenticate("/%70rivate/x", basic_auth_headers("alice:secret")).await,
+            "alice"
+        );
+        assert_eq!(
+            authenticate("/private/x", basic_auth_headers("alice:wrong")).await,
+            "unauthorized"
+        );
+        assert_eq!(
+            authenticate("/private/x", basic_auth_headers("mallory:secret")).await,
+            "unauthorized"
+        );
+        assert_eq!(
+            authenticate("/public/x", HeaderMap::new()).await,
+            "not required"
+        );
+    }
+
+    #[test]
+    fn test_auth_rule_configuration_debug_redacted() {
+        let rule_configuration = crate::config::AuthRule {
+            host_regex: None,
+            path_regex: None,
+            route_ids: Vec::new(),
+            realm: None,
+            users: [("alice".to_owned(), "$2b$04$secrethash".to_owned())].into(),
+            htpasswd_file: None,
+            bearer_tokens: [("ci".to_owned(), "secret-token".to_owned())].into(),
+        };
+
+        let debug = format!("{:?}", rule_configuration);
+        assert!(debug.contains("alice"));
+        assert!(debug.contains("ci"));
+        assert!(!debug.contains("secrethash"));
+        assert!(!debug.contains("secret-token"));
+    }
+
+    #[test]
+    fn test_make_cache_control_private() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static("public, max-age=60"),
+        );
+        make_cache_control_private(&mut headers);
+        assert_eq!(headers[header::CACHE_CONTROL], "private, max-age=60");
+
+        make_cache_control_private(&mut headers);
+        assert_eq!(headers[header::CACHE_CONTROL], "private, max-age=60");
+
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+        make_cache_control_private(&mut headers);
+        assert_eq!(headers[header::CACHE_CONTROL], "no-store");
+    }
+}
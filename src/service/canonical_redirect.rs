@@ -0,0 +1,287 @@
+use anyhow::Context;
+
+use hyper::http::{Response, StatusCode};
+
+use tokio::sync::OnceCell;
+
+use tracing::{debug, warn};
+
+use crate::{
+    request::HttpRequest,
+    response::{build_redirect_response, CacheControl, ResponseBody},
+    service::request_matcher::{request_host, RequestMatchData, RequestMatcher},
+};
+
+/// Requests under this path are never redirected so certificates can be renewed.
+const ACME_CHALLENGE_PATH_PREFIX: &str = "/.well-known/acme-challenge/";
+
+#[derive(Debug)]
+struct CanonicalRedirectRule {
+    host_matcher: RequestMatcher,
+    scheme_option: Option<String>,
+    host_option: Option<String>,
+    status_code: StatusCode,
+}
+
+impl CanonicalRedirectRule {
+    fn new(rule_configuration: &crate::config::CanonicalRedirectRule) -> anyhow::Result<Self> {
+        let host_matcher = RequestMatcher::new(&rule_configuration.host_regex, &None)?;
+
+        if rule_configuration.scheme.is_none() && rule_configuration.host.is_none() {
+            anyhow::bail!("CanonicalRedirectRule::new: scheme or host must be set");
+        }
+
+        if let Some(scheme) = &rule_configuration.scheme {
+            if scheme != "http" && scheme != "https" {
+                anyhow::bail!(
+                    "CanonicalRedirectRule::new: scheme must be http or https scheme = {:?}",
+                    scheme
+                );
+            }
+        }
+
+        let status_code = rule_configuration.status_code.unwrap_or(301);
+        if !matches!(status_code, 301 | 308) {
+            anyhow::bail!(
+                "CanonicalRedirectRule::new: status_code must be 301 or 308 status_code = {}",
+                status_code
+            );
+        }
+
+        Ok(Self {
+            host_matcher,
+            scheme_option: rule_configuration.scheme.clone(),
+            host_option: rule_configuration.host.clone(),
+            status_code: StatusCode::from_u16(status_code)?,
+        })
+    }
+
+    /// Returns the redirect location, or None if the request already has the canonical scheme and host.
+    fn location(
+        &self,
+        request_scheme: &str,
+        request_host_option: Option<&str>,
+        path_and_query: &str,
+    ) -> Option<String> {
+        let scheme = self.scheme_option.as_deref().unwrap_or(request_scheme);
+
+        let host = self.host_option.as_deref().or(request_host_option)?;
+
+        if scheme == request_scheme && Some(host) == request_host_option {
+            return None;
+        }
+
+        Some(format!("{}://{}{}", scheme, host, path_and_query))
+    }
+}
+
+/// Canonical redirect rules for one listener, or the server-wide rules if the listener has none.
+#[derive(Debug)]
+pub struct CanonicalRedirectRules(Vec<CanonicalRedirectRule>);
+
+impl CanonicalRedirectRules {
+    fn new(
+        rule_configurations: impl Iterator<Item = &'static crate::config::CanonicalRedirectRule>,
+    ) -> anyhow::Result<Self> {
+        let rules = rule_configurations
+            .map(CanonicalRedirectRule::new)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(Self(rules))
+    }
+
+    /// The first rule matching the request host decides the redirect.
+    fn find_redirect(
+        &self,
+        request_scheme: &str,
+        request_host_option: Option<&str>,
+        path_and_query: &str,
+    ) -> Option<(StatusCode, String)> {
+        if path_and_query.starts_with(ACME_CHALLENGE_PATH_PREFIX) {
+            return None;
+        }
+
+        let request_match_data = RequestMatchData {
+            host_option: request_host_option,
+            path_option: None,
+        };
+
+        let rule = self
+            .0
+            .iter()
+            .find(|rule| rule.host_matcher.matches(&request_match_data))?;
+
+        rule.location(request_scheme, request_host_option, path_and_query)
+            .map(|location| (rule.status_code, location))
+    }
+
+    /// Build a permanent redirect response if the request is not already canonical.
+    pub fn redirect(
+        &self,
+        request: &HttpRequest,
+        request_scheme: &str,
+    ) -> Option<Response<ResponseBody>> {
+        if self.0.is_empty() {
+            return None;
+        }
+
+        let uri = request.hyper_request.uri();
+
+        let path_and_query = uri.path_and_query().map_or("/", |p| p.as_str());
+
+        let (status_code, location) =
+            self.find_redirect(request_scheme, request_host(request), path_and_query)?;
+
+        debug!(
+            "canonical redirect status_code = {} location = {:?}",
+            status_code, location
+        );
+
+        match build_redirect_response(status_code, &location, CacheControl::NoCache) {
+            Ok(response) => Some(response),
+            Err(e) => {
+                warn!("canonical redirect build_redirect_response error: {}", e);
+                None
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CanonicalRedirectService {
+    listener_rules: Vec<CanonicalRedirectRules>,
+}
+
+impl CanonicalRedirectService {
+    fn new() -> anyhow::Result<Self> {
+        let server_configuration = &crate::config::instance().server_configuration;
+
+        let listener_rules = server_configuration
+            .listeners
+            .iter()
+            .map(|listener_configuration| {
+                let rule_configurations =
+                    if listener_configuration.canonical_redirect_rules.is_empty() {
+                        &server_configuration.canonical_redirect_rules
+                    } else {
+                        &listener_configuration.canonical_redirect_rules
+                    };
+
+                CanonicalRedirectRules::new(rule_configurations.iter()).with_context(|| {
+                    format!(
+                        "CanonicalRedirectService::new: error in listener bind_address = {:?}",
+                        listener_configuration.bind_address
+                    )
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        debug!("listener_rules = {:?}", listener_rules);
+
+        Ok(Self { listener_rules })
+    }
+
+    /// Rules for the listener at listener_index in the server configuration.
+    pub fn listener_rules(&self, listener_index: usize) -> &CanonicalRedirectRules {
+        &self.listener_rules[listener_index]
+    }
+}
+
+static CANONICAL_REDIRECT_SERVICE_INSTANCE: OnceCell<CanonicalRedirectService> =
+    OnceCell::const_new();
+
+pub fn create_canonical_redirect_service_instance() -> anyhow::Result<()> {
+    let canonical_redirect_service = CanonicalRedirectService::new()?;
+
+    CANONICAL_REDIRECT_SERVICE_INSTANCE
+        .set(canonical_redirect_service)
+        .context("CANONICAL_REDIRECT_SERVICE_INSTANCE.set error")?;
+
+    Ok(())
+}
+
+pub fn canonical_redirect_service_instance() -> &'static CanonicalRedirectService {
+    CANONICAL_REDIRECT_SERVICE_INSTANCE.get().unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rule(
+        host_regex: Option<&str>,
+        scheme: Option<&str>,
+        host: Option<&str>,
+    ) -> CanonicalRedirectRule {
+        CanonicalRedirectRule::new(&crate::config::CanonicalRedirectRule {
+            host_regex: host_regex.map(str::to_owned),
+            scheme: scheme.map(str::to_owned),
+            host: host.map(str::to_owned),
+            status_code: None,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_find_redirect() {
+        let rules = CanonicalRedirectRules(vec![
+            rule(
+                Some(r"^www\.aaronr\.digital$"),
+                Some("https"),
+                Some("aaronr.digital"),
+            ),
+            rule(None, Some("https"), None),
+        ]);
+
+        assert_eq!(
+            rules.find_redirect("http", Some("www.aaronr.digital"), "/a?b=c"),
+            Some((
+                StatusCode::MOVED_PERMANENTLY,
+                "https://aaronr.digital/a?b=c".to_owned()
+            ))
+        );
+        assert_eq!(
+            rules.find_redirect("http", Some("aaronr.digital"), "/"),
+            Some((
+                StatusCode::MOVED_PERMANENTLY,
+                "https://aaronr.digital/".to_owned()
+            ))
+        );
+        assert_eq!(
+            rules.find_redirect("https", Some("aaronr.digital"), "/"),
+            None
+        );
+        assert_eq!(rules.find_redirect("http", None, "/"), None);
+        assert_eq!(
+            rules.find_redirect(
+                "http",
+                Some("www.aaronr.digital"),
+                "/.well-known/acme-challenge/token"
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_invalid_rule() {
+        assert!(
+            CanonicalRedirectRule::new(&crate::config::CanonicalRedirectRule {
+                host_regex: None,
+                scheme: None,
+                host: None,
+                status_code: None,
+            })
+            .is_err()
+        );
+
+        assert!(
+            CanonicalRedirectRule::new(&crate::config::CanonicalRedirectRule {
+                host_regex: None,
+                scheme: Some("https".to_owned()),
+                host: None,
+                status_code: Some(302),
+            })
+            .is_err()
+        );
+    }
+}
@@ -6,6 +6,7 @@ use tokio::{
 };
 
 use std::{
+    net::IpAddr,
     sync::{
         atomic::{AtomicUsize, Ordering},
         Arc,
@@ -15,6 +16,8 @@ use std::{
 
 use crate::config::ServerSocketType;
 
+const TOP_PER_CLIENT_LIMIT_OFFENDERS: usize = 10;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Ord, PartialOrd)]
 pub struct ConnectionID(usize);
 
@@ -30,16 +33,22 @@ pub struct ConnectionInfo {
     pub creation_time: SystemTime,
     pub creation_instant: Instant,
     pub server_socket_type: ServerSocketType,
+    pub peer_ip: Option<IpAddr>,
     num_requests: Arc<AtomicUsize>,
 }
 
 impl ConnectionInfo {
-    fn new(id: ConnectionID, server_socket_type: ServerSocketType) -> Self {
+    fn new(
+        id: ConnectionID,
+        server_socket_type: ServerSocketType,
+        peer_ip: Option<IpAddr>,
+    ) -> Self {
         Self {
             id,
             creation_time: SystemTime::now(),
             creation_instant: Instant::now(),
             server_socket_type,
+            peer_ip,
             num_requests: Arc::new(AtomicUsize::new(0)),
         }
     }
@@ -53,9 +62,19 @@ impl ConnectionInfo {
     }
 }
 
+/// Client requests that exceeded server_configuration.request_limits.
+#[derive(Clone, Copy, Debug)]
+pub enum RequestLimitViolation {
+    HeaderReadTimeout,
+    HeaderLimit,
+    BodyLimit,
+}
+
 pub struct ConnectionGuard {
     pub id: ConnectionID,
     pub server_socket_type: ServerSocketType,
+    pub peer_ip: Option<IpAddr>,
+    pub peer_uid: Option<u32>,
     num_requests: Arc<AtomicUsize>,
 }
 
@@ -63,11 +82,15 @@ impl ConnectionGuard {
     fn new(
         id: ConnectionID,
         server_socket_type: ServerSocketType,
+        peer_ip: Option<IpAddr>,
+        peer_uid: Option<u32>,
         num_requests: Arc<AtomicUsize>,
     ) -> Self {
         Self {
             id,
             server_socket_type,
+            peer_ip,
+            peer_uid,
             num_requests,
         }
     }
@@ -108,10 +131,12 @@ impl ConnectionTrackerService {
     pub async fn add_connection(
         &self,
         server_socket_type: ServerSocketType,
+        peer_ip: Option<IpAddr>,
+        peer_uid: Option<u32>,
     ) -> Option<ConnectionGuard> {
         let mut state = self.state.write().await;
 
-        state.add_connection(server_socket_type)
+        state.add_connection(server_socket_type, peer_ip, peer_uid)
     }
 
     async fn remove_connection(&self, connection_id: ConnectionID) {
@@ -120,12 +145,28 @@ impl ConnectionTrackerService {
         state.remove_connection(connection_id);
     }
 
+    pub async fn add_request_limit_violation(
+        &self,
+        connection_id: ConnectionID,
+        violation: RequestLimitViolation,
+    ) {
+        let mut state = self.state.write().await;
+
+        state.add_request_limit_violation(connection_id, violation);
+    }
+
     pub async fn connection_tracker_state_snapshot(&self) -> ConnectionTrackerStateSnapshot {
         let state = self.state.read().await;
 
         ConnectionTrackerStateSnapshot {
             max_open_connections: state.max_open_connections(),
             connection_limit_hits: state.connection_limit_hits(),
+            per_client_limit_hits: state.per_client_limit_hits(),
+            top_per_client_limit_offenders: state
+                .top_per_client_limit_offenders(TOP_PER_CLIENT_LIMIT_OFFENDERS),
+            header_read_timeouts: state.header_read_timeouts(),
+            header_limit_violations: state.header_limit_violations(),
+            body_limit_violations: state.body_limit_violations(),
             min_connection_lifetime: state.min_connection_lifetime(),
             max_connection_lifetime: state.max_connection_lifetime(),
             max_requests_per_connection: state.max_requests_per_connection(),
@@ -143,6 +184,11 @@ impl ConnectionTrackerService {
 pub struct ConnectionTrackerStateSnapshot {
     pub max_open_connections: usize,
     pub connection_limit_hits: usize,
+    pub per_client_limit_hits: usize,
+    pub top_per_client_limit_offenders: Vec<(IpAddr, usize)>,
+    pub header_read_timeouts: usize,
+    pub header_limit_violations: usize,
+    pub body_limit_violations: usize,
     pub min_connection_lifetime: Duration,
     pub max_connection_lifetime: Duration,
     pub max_requests_per_connection: usize,
@@ -4,16 +4,24 @@ use tokio::time::{Duration, Instant};
 
 use tracing::{debug, warn};
 
-use std::{cmp, sync::Arc};
+use std::{cmp, net::IpAddr, sync::Arc};
 
 use crate::config::ServerSocketType;
 
-use super::{ConnectionGuard, ConnectionID, ConnectionInfo};
+use super::{ConnectionGuard, ConnectionID, ConnectionInfo, RequestLimitViolation};
+
+/// Bound on distinct addresses remembered for per_client_limit hits.
+const MAX_PER_CLIENT_LIMIT_OFFENDERS: usize = 1024;
 
 #[derive(Default)]
 struct ConnectionTrackerMetrics {
     max_open_connections: usize,
     connection_limit_hits: usize,
+    per_client_limit_hits: usize,
+    ip_to_per_client_limit_hits: AHashMap<IpAddr, usize>,
+    header_read_timeouts: usize,
+    header_limit_violations: usize,
+    body_limit_violations: usize,
     past_min_connection_age: Option<Duration>,
     past_max_connection_age: Duration,
     past_max_requests_per_connection: usize,
@@ -44,25 +52,53 @@ impl ConnectionTrackerMetrics {
     fn increment_connection_limit_hits(&mut self) {
         self.connection_limit_hits += 1;
     }
+
+    fn increment_per_client_limit_hits(&mut self, peer_ip: IpAddr) {
+        self.per_client_limit_hits += 1;
+
+        if !self.ip_to_per_client_limit_hits.contains_key(&peer_ip)
+            && self.ip_to_per_client_limit_hits.len() >= MAX_PER_CLIENT_LIMIT_OFFENDERS
+        {
+            if let Some(least_hits_ip) = self
+                .ip_to_per_client_limit_hits
+                .iter()
+                .min_by_key(|(_, hits)| **hits)
+                .map(|(ip, _)| *ip)
+            {
+                self.ip_to_per_client_limit_hits.remove(&least_hits_ip);
+            }
+        }
+
+        *self.ip_to_per_client_limit_hits.entry(peer_ip).or_default() += 1;
+    }
+
+    fn increment_request_limit_violations(&mut self, violation: RequestLimitViolation) {
+        match violation {
+            RequestLimitViolation::HeaderReadTimeout => self.header_read_timeouts += 1,
+            RequestLimitViolation::HeaderLimit => self.header_limit_violations += 1,
+            RequestLimitViolation::BodyLimit => self.body_limit_violations += 1,
+        }
+    }
 }
 
 #[derive(Default)]
 pub struct ConnectionTrackerState {
     next_connection_id: usize,
     connection_limit: usize,
+    per_client_limit: Option<usize>,
     id_to_connection_info: AHashMap<ConnectionID, Arc<ConnectionInfo>>,
+    ip_to_num_connections: AHashMap<IpAddr, usize>,
     metrics: ConnectionTrackerMetrics,
 }
 
 impl ConnectionTrackerState {
     pub fn new() -> Self {
-        let connection_limit = crate::config::instance()
-            .server_configuration
-            .connection
-            .limit;
+        let connection_configuration = &crate::config::instance().server_configuration.connection;
+        let connection_limit = connection_configuration.limit;
         Self {
             next_connection_id: 1,
             connection_limit,
+            per_client_limit: connection_configuration.per_client_limit,
             id_to_connection_info: AHashMap::with_capacity(connection_limit),
             ..Default::default()
         }
@@ -78,9 +114,23 @@ impl ConnectionTrackerState {
         (self.id_to_connection_info.len() + 1) > self.connection_limit
     }
 
+    fn new_connection_exceeds_per_client_limit(&self, peer_ip: IpAddr) -> bool {
+        self.per_client_limit.is_some_and(|per_client_limit| {
+            (self
+                .ip_to_num_connections
+                .get(&peer_ip)
+                .copied()
+                .unwrap_or_default()
+                + 1)
+                > per_client_limit
+        })
+    }
+
     pub fn add_connection(
         &mut self,
         server_socket_type: ServerSocketType,
+        peer_ip: Option<IpAddr>,
+        peer_uid: Option<u32>,
     ) -> Option<ConnectionGuard> {
         if self.new_connection_exceeds_connection_limit() {
             warn!(
@@ -91,9 +141,28 @@ impl ConnectionTrackerState {
             return None;
         }
 
+        let peer_ip = peer_ip.map(|peer_ip| peer_ip.to_canonical());
+
+        if let Some(peer_ip) = peer_ip {
+            if self.new_connection_exceeds_per_client_limit(peer_ip) {
+                warn!(
+                    "add_connection hit per_client_limit = {:?} peer_ip = {}",
+                    self.per_client_limit, peer_ip
+                );
+                self.metrics.increment_per_client_limit_hits(peer_ip);
+                return None;
+            }
+
+            *self.ip_to_num_connections.entry(peer_ip).or_default() += 1;
+        }
+
         let connection_id = self.next_connection_id();
 
-        let connection_info = Arc::new(ConnectionInfo::new(connection_id, server_socket_type));
+        let connection_info = Arc::new(ConnectionInfo::new(
+            connection_id,
+            server_socket_type,
+            peer_ip,
+        ));
 
         let num_requests = Arc::clone(&connection_info.num_requests);
 
@@ -112,6 +181,8 @@ impl ConnectionTrackerState {
         Some(ConnectionGuard::new(
             connection_id,
             server_socket_type,
+            peer_ip,
+            peer_uid,
             num_requests,
         ))
     }
@@ -119,6 +190,15 @@ impl ConnectionTrackerState {
     pub fn remove_connection(&mut self, connection_id: ConnectionID) {
         if let Some(connection_info) = self.id_to_connection_info.remove(&connection_id) {
             self.metrics.update_for_removed_connection(&connection_info);
+
+            if let Some(peer_ip) = connection_info.peer_ip {
+                if let Some(num_connections) = self.ip_to_num_connections.get_mut(&peer_ip) {
+                    *num_connections -= 1;
+                    if *num_connections == 0 {
+                        self.ip_to_num_connections.remove(&peer_ip);
+                    }
+                }
+            }
         }
 
         debug!(
@@ -127,6 +207,19 @@ impl ConnectionTrackerState {
         );
     }
 
+    pub fn add_request_limit_violation(
+        &mut self,
+        connection_id: ConnectionID,
+        violation: RequestLimitViolation,
+    ) {
+        warn!(
+            "request limit violation connection_id = {} violation = {:?}",
+            connection_id.as_usize(),
+            violation
+        );
+        self.metrics.increment_request_limit_violations(violation);
+    }
+
     pub fn max_open_connections(&self) -> usize {
         self.metrics.max_open_connections
     }
@@ -135,6 +228,37 @@ impl ConnectionTrackerState {
         self.metrics.connection_limit_hits
     }
 
+    pub fn per_client_limit_hits(&self) -> usize {
+        self.metrics.per_client_limit_hits
+    }
+
+    /// Addresses with the most per_client_limit hits, most hits first.
+    pub fn top_per_client_limit_offenders(&self, limit: usize) -> Vec<(IpAddr, usize)> {
+        let mut offenders: Vec<(IpAddr, usize)> = self
+            .metrics
+            .ip_to_per_client_limit_hits
+            .iter()
+            .map(|(ip, hits)| (*ip, *hits))
+            .collect();
+
+        offenders.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        offenders.truncate(limit);
+
+        offenders
+    }
+
+    pub fn header_read_timeouts(&self) -> usize {
+        self.metrics.header_read_timeouts
+    }
+
+    pub fn header_limit_violations(&self) -> usize {
+        self.metrics.header_limit_violations
+    }
+
+    pub fn body_limit_violations(&self) -> usize {
+        self.metrics.body_limit_violations
+    }
+
     pub fn min_connection_lifetime(&self) -> Duration {
         match self.metrics.past_min_connection_age {
             Some(past_min_connection_age) => past_min_connection_age,
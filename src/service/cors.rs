@@ -0,0 +1,369 @@
+use anyhow::Context;
+
+use hyper::http::{header, HeaderMap, HeaderName, HeaderValue, Method, Response, StatusCode};
+
+use tokio::sync::OnceCell;
+
+use tracing::debug;
+
+use std::time::Duration;
+
+use crate::{
+    request::HttpRequest,
+    response::{build_status_code_response, CacheControl, ResponseBody},
+};
+
+const ANY_ORIGIN: &str = "*";
+
+fn join_header_value(values: &[&str]) -> anyhow::Result<Option<HeaderValue>> {
+    if values.is_empty() {
+        return Ok(None);
+    }
+
+    let value = values.join(", ");
+
+    Ok(Some(HeaderValue::try_from(value.as_str()).with_context(
+        || format!("invalid header value {:?}", value),
+    )?))
+}
+
+/// The method a preflight request asks about, None if the request is not a preflight.
+pub fn preflight_request_method(request: &HttpRequest) -> Option<Method> {
+    let headers = request.hyper_request.headers();
+
+    if request.hyper_request.method() != Method::OPTIONS || !headers.contains_key(header::ORIGIN) {
+        return None;
+    }
+
+    Method::from_bytes(
+        headers
+            .get(header::ACCESS_CONTROL_REQUEST_METHOD)?
+            .as_bytes(),
+    )
+    .ok()
+}
+
+#[derive(Debug)]
+struct CorsRules {
+    allow_any_origin: bool,
+    allowed_origins: Vec<String>,
+    allowed_origin_regexes: Vec<regex::Regex>,
+    allowed_methods: Vec<Method>,
+    allowed_headers: Vec<HeaderName>,
+    allow_credentials: bool,
+    max_age: Option<Duration>,
+    allow_methods_value: Option<HeaderValue>,
+    allow_headers_value: Option<HeaderValue>,
+}
+
+impl CorsRules {
+    fn new(cors_configuration: &crate::config::CorsConfiguration) -> anyhow::Result<Self> {
+        let allow_any_origin = cors_configuration
+            .allowed_origins
+            .iter()
+            .any(|origin| origin == ANY_ORIGIN);
+
+        if allow_any_origin && cors_configuration.allow_credentials {
+            anyhow::bail!(
+                "CorsRules::new: allowed_origins {:?} cannot be used with allow_credentials",
+                ANY_ORIGIN
+            );
+        }
+
+        let allowed_origin_regexes = cors_configuration
+            .allowed_origin_regexes
+            .iter()
+            .map(|origin_regex| {
+                regex::Regex::new(origin_regex)
+                    .with_context(|| format!("invalid origin regex {:?}", origin_regex))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()
+            .context("CorsRules::new: error parsing allowed_origin_regexes")?;
+
+        let allowed_methods = cors_configuration
+            .allowed_methods
+            .iter()
+            .map(|method| {
+                Method::from_bytes(method.as_bytes())
+                    .with_context(|| format!("invalid method {:?}", method))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()
+            .context("CorsRules::new: error parsing allowed_methods")?;
+
+        let allowed_headers = cors_configuration
+            .allowed_headers
+            .iter()
+            .map(|name| {
+                HeaderName::try_from(name.as_str())
+                    .with_context(|| format!("invalid header name {:?}", name))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()
+            .context("CorsRules::new: error parsing allowed_headers")?;
+
+        let allow_methods_value = join_header_value(
+            &allowed_methods
+                .iter()
+                .map(Method::as_str)
+                .collect::<Vec<_>>(),
+        )?;
+
+        let allow_headers_value = join_header_value(
+            &allowed_headers
+                .iter()
+                .map(HeaderName::as_str)
+                .collect::<Vec<_>>(),
+        )?;
+
+        Ok(Self {
+            allow_any_origin,
+            allowed_origins: cors_configuration.allowed_origins.clone(),
+            allowed_origin_regexes,
+            allowed_methods,
+            allowed_headers,
+            allow_credentials: cors_configuration.allow_credentials,
+            max_age: cors_configuration.max_age,
+            allow_methods_value,
+            allow_headers_value,
+        })
+    }
+
+    fn allowed_origin<'a>(&self, headers: &'a HeaderMap) -> Option<&'a HeaderValue> {
+        let origin = headers.get(header::ORIGIN)?;
+
+        let origin_str = origin.to_str().ok()?;
+
+        if self.allow_any_origin
+            || self.allowed_origins.iter().any(|o| o == origin_str)
+            || self
+                .allowed_origin_regexes
+                .iter()
+                .any(|origin_regex| origin_regex.is_match(origin_str))
+        {
+            Some(origin)
+        } else {
+            None
+        }
+    }
+
+    fn add_origin_headers(&self, origin: &HeaderValue, headers: &mut HeaderMap) {
+        if self.allow_any_origin {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_ORIGIN,
+                HeaderValue::from_static(ANY_ORIGIN),
+            );
+            return;
+        }
+
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+        headers.append(header::VARY, HeaderValue::from_static("origin"));
+
+        if self.allow_credentials {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+    }
+
+    fn requested_headers_allowed(&self, headers: &HeaderMap) -> bool {
+        headers
+            .get_all(header::ACCESS_CONTROL_REQUEST_HEADERS)
+            .iter()
+            .all(|value| {
+                value.to_str().is_ok_and(|value| {
+                    value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|name| !name.is_empty())
+                        .all(|name| {
+                            HeaderName::try_from(name)
+                                .is_ok_and(|name| self.allowed_headers.contains(&name))
+                        })
+                })
+            })
+    }
+
+    fn preflight_response(
+        &self,
+        headers: &HeaderMap,
+        requested_method: &Method,
+    ) -> Option<Response<ResponseBody>> {
+        let Some(origin) = self.allowed_origin(headers) else {
+            debug!("preflight origin not allowed");
+            return None;
+        };
+
+        if !self.allowed_methods.contains(requested_method) {
+            debug!("preflight method not allowed {:?}", requested_method);
+            return None;
+        }
+
+        if !self.requested_headers_allowed(headers) {
+            debug!("preflight headers not allowed");
+            return None;
+        }
+
+        let mut response =
+            build_status_code_response(StatusCode::NO_CONTENT, CacheControl::NoCache);
+
+        let response_headers = response.headers_mut();
+
+        self.add_origin_headers(origin, response_headers);
+
+        if let Some(allow_methods_value) = &self.allow_methods_value {
+            response_headers.insert(
+                header::ACCESS_CONTROL_ALLOW_METHODS,
+                allow_methods_value.clone(),
+            );
+        }
+
+        if let Some(allow_headers_value) = &self.allow_headers_value {
+            response_headers.insert(
+                header::ACCESS_CONTROL_ALLOW_HEADERS,
+                allow_headers_value.clone(),
+            );
+        }
+
+        if let Some(max_age) = self.max_age {
+            response_headers.insert(
+                header::ACCESS_CONTROL_MAX_AGE,
+                HeaderValue::from(max_age.as_secs()),
+            );
+        }
+
+        response_headers.append(
+            header::VARY,
+            HeaderValue::from_static(
+                "access-control-request-method, access-control-request-headers",
+            ),
+        );
+
+        Some(response)
+    }
+}
+
+/// CORS for dynamic routes, configured by context_configuration.cors.
+#[derive(Debug)]
+pub struct CorsService {
+    rules_option: Option<CorsRules>,
+}
+
+impl CorsService {
+    fn new() -> anyhow::Result<Self> {
+        let rules_option = match &crate::config::instance().context_configuration.cors {
+            None => None,
+            Some(cors_configuration) => Some(
+                CorsRules::new(cors_configuration)
+                    .context("CorsService::new: error in context_configuration.cors")?,
+            ),
+        };
+
+        debug!("rules_option = {:?}", rules_option);
+
+        Ok(Self { rules_option })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.rules_option.is_some()
+    }
+
+    /// Answer a preflight request for a dynamic route, None if the preflight is not allowed.
+    pub fn preflight_response(
+        &self,
+        request: &HttpRequest,
+        requested_method: &Method,
+    ) -> Option<Response<ResponseBody>> {
+        self.rules_option
+            .as_ref()?
+            .preflight_response(request.hyper_request.headers(), requested_method)
+    }
+
+    /// Add CORS headers to a dynamic route response when the request origin is allowed.
+    pub fn apply(&self, request: &HttpRequest, headers: &mut HeaderMap) {
+        let Some(rules) = &self.rules_option else {
+            return;
+        };
+
+        if let Some(origin) = rules.allowed_origin(request.hyper_request.headers()) {
+            rules.add_origin_headers(origin, headers);
+        }
+    }
+}
+
+static CORS_SERVICE_INSTANCE: OnceCell<CorsService> = OnceCell::const_new();
+
+pub fn create_cors_service_instance() -> anyhow::Result<()> {
+    let cors_service = CorsService::new()?;
+
+    CORS_SERVICE_INSTANCE
+        .set(cors_service)
+        .context("CORS_SERVICE_INSTANCE.set error")?;
+
+    Ok(())
+}
+
+pub fn cors_service_instance() -> &'static CorsService {
+    CORS_SERVICE_INSTANCE.get().unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cors_preflight() {
+        let rules = CorsRules::new(&crate::config::CorsConfiguration {
+            allowed_origins: vec!["https://dashboard.example.com".to_owned()],
+            allowed_origin_regexes: vec![r"^https://[a-z]+\.internal\.example\.com$".to_owned()],
+            allowed_methods: vec!["GET".to_owned(), "POST".to_owned()],
+            allowed_headers: vec!["Content-Type".to_owned()],
+            allow_credentials: true,
+            max_age: Some(Duration::from_secs(600)),
+        })
+        .unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::ORIGIN,
+            HeaderValue::from_static("https://ops.internal.example.com"),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_REQUEST_HEADERS,
+            HeaderValue::from_static("content-type"),
+        );
+
+        let response = rules.preflight_response(&headers, &Method::POST).unwrap();
+        let response_headers = response.headers();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            response_headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://ops.internal.example.com"
+        );
+        assert_eq!(
+            response_headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS],
+            "true"
+        );
+        assert_eq!(
+            response_headers[header::ACCESS_CONTROL_ALLOW_METHODS],
+            "GET, POST"
+        );
+        assert_eq!(response_headers[header::ACCESS_CONTROL_MAX_AGE], "600");
+
+        assert!(rules
+            .preflight_response(&headers, &Method::DELETE)
+            .is_none());
+
+        headers.insert(
+            header::ACCESS_CONTROL_REQUEST_HEADERS,
+            HeaderValue::from_static("content-type, x-other"),
+        );
+        assert!(rules.preflight_response(&headers, &Method::POST).is_none());
+
+        headers.remove(header::ACCESS_CONTROL_REQUEST_HEADERS);
+        headers.insert(
+            header::ORIGIN,
+            HeaderValue::from_static("https://evil.example.com"),
+        );
+        assert!(rules.preflight_response(&headers, &Method::GET).is_none());
+    }
+}
@@ -0,0 +1,252 @@
+use ahash::AHashMap;
+
+use anyhow::Context;
+
+use http_body_util::BodyExt;
+
+use hyper::http::{header, Request as HyperHttpRequest, Response, StatusCode};
+
+use tokio::sync::OnceCell;
+
+use tracing::{debug, warn};
+
+use crate::{
+    request::HttpRequest,
+    response::{build_status_code_response, CacheControl, ResponseBody},
+    service::static_file::{
+        mount::{mounts_instance, StaticFileMounts},
+        rules_service_instance,
+        vfs::ResolveResult,
+        StaticFileRulesService,
+    },
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum ErrorPageKey {
+    StatusCode(u16),
+    StatusClass(u16),
+}
+
+impl TryFrom<&str> for ErrorPageKey {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let key = if let Some(class) = value
+            .strip_suffix("xx")
+            .or_else(|| value.strip_suffix("XX"))
+        {
+            let class: u16 = class
+                .parse()
+                .with_context(|| format!("invalid status class {:?}", value))?;
+            ErrorPageKey::StatusClass(class)
+        } else {
+            let status_code: u16 = value
+                .parse()
+                .with_context(|| format!("invalid status code {:?}", value))?;
+            ErrorPageKey::StatusCode(status_code)
+        };
+
+        let valid = match key {
+            ErrorPageKey::StatusCode(status_code) => (400..=599).contains(&status_code),
+            ErrorPageKey::StatusClass(class) => (4..=5).contains(&class),
+        };
+
+        if !valid {
+            anyhow::bail!("error page key {:?} is not a 4xx or 5xx status", value);
+        }
+
+        Ok(key)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+enum ErrorPageError {
+    #[error("build request error: {0}")]
+    BuildRequest(hyper::http::Error),
+
+    #[error("resolve request error: {0}")]
+    ResolveRequest(std::io::Error),
+
+    #[error("error page not found: {0:?}")]
+    NotFound(ResolveResult),
+
+    #[error("build response error: {0}")]
+    BuildResponse(hyper::http::Error),
+}
+
+#[derive(Debug)]
+pub struct ErrorPageService {
+    key_to_path: AHashMap<ErrorPageKey, String>,
+    mounts: &'static StaticFileMounts,
+    static_file_rules_service: &'static StaticFileRulesService,
+}
+
+impl ErrorPageService {
+    fn new() -> anyhow::Result<Self> {
+        let static_file_configuration = &crate::config::instance().static_file_configuration;
+
+        let mut key_to_path = AHashMap::with_capacity(static_file_configuration.error_pages.len());
+
+        for (key, path) in &static_file_configuration.error_pages {
+            let key = ErrorPageKey::try_from(key.as_str())
+                .context("ErrorPageService::new: error parsing error_pages key")?;
+
+            key_to_path.insert(key, path.clone());
+        }
+
+        // client_error_page_path is the error page for 4xx status codes
+        // unless error_pages has a more specific entry.
+        if let Some(client_error_page_path) = &static_file_configuration.client_error_page_path {
+            key_to_path
+                .entry(ErrorPageKey::StatusClass(4))
+                .or_insert_with(|| client_error_page_path.clone());
+        }
+
+        debug!("key_to_path = {:?}", key_to_path);
+
+        Ok(Self {
+            key_to_path,
+            mounts: mounts_instance(),
+            static_file_rules_service: rules_service_instance(),
+        })
+    }
+
+    fn error_page_path(&self, status_code: StatusCode) -> Option<&str> {
+        let status_code = status_code.as_u16();
+
+        self.key_to_path
+            .get(&ErrorPageKey::StatusCode(status_code))
+            .or_else(|| {
+                self.key_to_path
+                    .get(&ErrorPageKey::StatusClass(status_code / 100))
+            })
+            .map(|path| path.as_str())
+    }
+
+    async fn try_build_error_page_response(
+        &self,
+        original_request: &HttpRequest,
+        error_page_path: &str,
+        status_code: StatusCode,
+    ) -> Result<Response<ResponseBody>, ErrorPageError> {
+        let mut error_page_request = HyperHttpRequest::get(error_page_path);
+
+        // copy ACCEPT_ENCODING header from original request
+        // so we can try to use gz/bz error page if possible.
+        if let Some(accept_encoding_header_value) = original_request
+            .hyper_request
+            .headers()
+            .get(header::ACCEPT_ENCODING)
+        {
+            error_page_request =
+                error_page_request.header(header::ACCEPT_ENCODING, accept_encoding_header_value);
+        }
+
+        let error_page_request = error_page_request
+            .body(())
+            .map_err(ErrorPageError::BuildRequest)?;
+
+        let (mount, mount_path) = self.mounts.find(error_page_path);
+
+        let resolve_result = mount
+            .snapshot()
+            .resolve(
+                error_page_request.method(),
+                error_page_request.headers(),
+                mount_path,
+            )
+            .await
+            .map_err(ErrorPageError::ResolveRequest)?;
+
+        if !matches!(resolve_result, ResolveResult::Found(_)) {
+            return Err(ErrorPageError::NotFound(resolve_result));
+        }
+
+        let cache_control = self
+            .static_file_rules_service
+            .build_cache_headers(original_request, &resolve_result);
+
+        let response = hyper_staticfile::ResponseBuilder::new()
+            .request(&error_page_request)
+            .build(resolve_result)
+            .map_err(ErrorPageError::BuildResponse)?;
+
+        let (mut parts, body) = response.into_parts();
+        parts.status = status_code;
+
+        if let Some(cache_control) = cache_control {
+            parts.headers.insert(header::CACHE_CONTROL, cache_control);
+        }
+
+        let boxed_body = body.map_err(|e| e.into()).boxed();
+
+        Ok(Response::from_parts(parts, boxed_body))
+    }
+
+    /// Build a response for an error status code using the configured error page,
+    /// falling back to a built-in page if no error page is configured or it can't be resolved.
+    pub async fn build_error_page_response(
+        &self,
+        original_request: &HttpRequest,
+        status_code: StatusCode,
+    ) -> Response<ResponseBody> {
+        if let Some(error_page_path) = self.error_page_path(status_code) {
+            match self
+                .try_build_error_page_response(original_request, error_page_path, status_code)
+                .await
+            {
+                Ok(response) => return response,
+                Err(e) => {
+                    warn!(
+                        "error building error page response error_page_path = {:?}: {}",
+                        error_page_path, e
+                    );
+                }
+            }
+        }
+
+        build_status_code_response(status_code, CacheControl::NoCache)
+    }
+}
+
+static ERROR_PAGE_SERVICE_INSTANCE: OnceCell<ErrorPageService> = OnceCell::const_new();
+
+pub fn create_error_page_service_instance() -> anyhow::Result<()> {
+    let error_page_service = ErrorPageService::new()?;
+
+    ERROR_PAGE_SERVICE_INSTANCE
+        .set(error_page_service)
+        .context("ERROR_PAGE_SERVICE_INSTANCE.set error")?;
+
+    Ok(())
+}
+
+pub fn error_page_service_instance() -> &'static ErrorPageService {
+    ERROR_PAGE_SERVICE_INSTANCE.get().unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_error_page_key() {
+        assert_eq!(
+            ErrorPageKey::try_from("404").unwrap(),
+            ErrorPageKey::StatusCode(404)
+        );
+        assert_eq!(
+            ErrorPageKey::try_from("5xx").unwrap(),
+            ErrorPageKey::StatusClass(5)
+        );
+        assert_eq!(
+            ErrorPageKey::try_from("4XX").unwrap(),
+            ErrorPageKey::StatusClass(4)
+        );
+
+        assert!(ErrorPageKey::try_from("200").is_err());
+        assert!(ErrorPageKey::try_from("3xx").is_err());
+        assert!(ErrorPageKey::try_from("abc").is_err());
+        assert!(ErrorPageKey::try_from("xx").is_err());
+    }
+}
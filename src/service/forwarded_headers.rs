@@ -0,0 +1,138 @@
+use anyhow::Context;
+
+use hyper::http::HeaderMap;
+
+use tokio::sync::OnceCell;
+
+use tracing::debug;
+
+use std::net::IpAddr;
+
+use crate::service::ip_access::IpCidr;
+
+/// Decides which peers are trusted proxies whose `X-Forwarded-*` headers are honored.
+#[derive(Debug)]
+pub struct ForwardedHeadersService {
+    trusted_cidrs: Vec<IpCidr>,
+    trust_unix_peers: bool,
+}
+
+impl ForwardedHeadersService {
+    fn new() -> anyhow::Result<Self> {
+        let forwarded_headers_configuration = &crate::config::instance()
+            .server_configuration
+            .forwarded_headers;
+
+        let trusted_cidrs = forwarded_headers_configuration
+            .trusted_cidrs
+            .iter()
+            .map(|cidr| IpCidr::new(cidr))
+            .collect::<anyhow::Result<Vec<_>>>()
+            .context("ForwardedHeadersService::new: error parsing trusted_cidrs")?;
+
+        let forwarded_headers_service = Self {
+            trusted_cidrs,
+            trust_unix_peers: forwarded_headers_configuration.trust_unix_peers,
+        };
+
+        debug!(
+            "forwarded_headers_service = {:?}",
+            forwarded_headers_service
+        );
+
+        Ok(forwarded_headers_service)
+    }
+
+    /// Requests over UNIX sockets have no peer address.
+    pub fn is_trusted(&self, peer_ip_option: Option<IpAddr>) -> bool {
+        match peer_ip_option {
+            None => self.trust_unix_peers,
+            Some(peer_ip) => self.trusted_cidrs.iter().any(|cidr| cidr.contains(peer_ip)),
+        }
+    }
+
+    /// Listeners only accept plain HTTP connections, so the scheme is http unless a
+    /// trusted proxy sent `X-Forwarded-Proto: https`.
+    pub fn request_scheme(
+        &self,
+        headers: &HeaderMap,
+        peer_ip_option: Option<IpAddr>,
+    ) -> &'static str {
+        if !self.is_trusted(peer_ip_option) {
+            return "http";
+        }
+
+        // a proxy chain appends, the first entry is the client facing proxy.
+        let forwarded_proto_option = headers
+            .get("x-forwarded-proto")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(',').next())
+            .map(str::trim);
+
+        match forwarded_proto_option {
+            Some(forwarded_proto) if forwarded_proto.eq_ignore_ascii_case("https") => "https",
+            _ => "http",
+        }
+    }
+}
+
+static FORWARDED_HEADERS_SERVICE_INSTANCE: OnceCell<ForwardedHeadersService> =
+    OnceCell::const_new();
+
+pub fn create_forwarded_headers_service_instance() -> anyhow::Result<()> {
+    let forwarded_headers_service = ForwardedHeadersService::new()?;
+
+    FORWARDED_HEADERS_SERVICE_INSTANCE
+        .set(forwarded_headers_service)
+        .context("FORWARDED_HEADERS_SERVICE_INSTANCE.set error")?;
+
+    Ok(())
+}
+
+pub fn forwarded_headers_service_instance() -> &'static ForwardedHeadersService {
+    FORWARDED_HEADERS_SERVICE_INSTANCE.get().unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use hyper::http::HeaderValue;
+
+    #[test]
+    fn test_request_scheme() {
+        let forwarded_headers_service = ForwardedHeadersService {
+            trusted_cidrs: vec![IpCidr::new("10.0.0.0/8").unwrap()],
+            trust_unix_peers: true,
+        };
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            forwarded_headers_service.request_scheme(&headers, None),
+            "http"
+        );
+
+        headers.insert("x-forwarded-proto", HeaderValue::from_static("https, http"));
+        assert_eq!(
+            forwarded_headers_service.request_scheme(&headers, None),
+            "https"
+        );
+        assert_eq!(
+            forwarded_headers_service.request_scheme(&headers, Some("10.1.2.3".parse().unwrap())),
+            "https"
+        );
+
+        // untrusted peers can not claim https.
+        assert_eq!(
+            forwarded_headers_service
+                .request_scheme(&headers, Some("192.168.1.1".parse().unwrap())),
+            "http"
+        );
+
+        headers.insert("x-forwarded-proto", HeaderValue::from_static("gopher"));
+        assert_eq!(
+            forwarded_headers_service.request_scheme(&headers, None),
+            "http"
+        );
+    }
+}
@@ -0,0 +1,362 @@
+use anyhow::Context;
+
+use tokio::sync::OnceCell;
+
+use tracing::debug;
+
+use std::net::IpAddr;
+
+use crate::{
+    config::{IpAccessAction, ServerSocketType},
+    service::request_matcher::path_has_prefix,
+};
+
+/// An IPv4 or IPv6 network such as `192.168.0.0/16` or `fd00::/8`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IpCidr {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpCidr {
+    pub fn new(value: &str) -> anyhow::Result<Self> {
+        let (address, prefix_len_option) = match value.split_once('/') {
+            Some((address, prefix_len)) => (address, Some(prefix_len)),
+            None => (value, None),
+        };
+
+        let network: IpAddr = address
+            .parse()
+            .with_context(|| format!("invalid cidr address {:?}", value))?;
+
+        let max_prefix_len = if network.is_ipv4() { 32 } else { 128 };
+
+        let prefix_len = match prefix_len_option {
+            None => max_prefix_len,
+            Some(prefix_len) => prefix_len
+                .parse()
+                .with_context(|| format!("invalid cidr prefix length {:?}", value))?,
+        };
+
+        if prefix_len > max_prefix_len {
+            anyhow::bail!("cidr prefix length too large {:?}", value);
+        }
+
+        // contains compares canonical peer addresses, so IPv4-mapped networks become IPv4.
+        let (network, prefix_len) = match network {
+            IpAddr::V6(network_v6) => match network_v6.to_ipv4_mapped() {
+                Some(network_v4) if prefix_len >= 96 => (IpAddr::V4(network_v4), prefix_len - 96),
+                Some(_) => anyhow::bail!(
+                    "cidr prefix length too small for an IPv4-mapped network {:?}",
+                    value
+                ),
+                None => (network, prefix_len),
+            },
+            IpAddr::V4(_) => (network, prefix_len),
+        };
+
+        Ok(Self {
+            network,
+            prefix_len,
+        })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // IPv4 clients of a dual stack listener are seen as IPv4-mapped IPv6 addresses.
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                prefix_matches(&network.octets(), &ip.octets(), self.prefix_len)
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                prefix_matches(&network.octets(), &ip.octets(), self.prefix_len)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn prefix_matches(network: &[u8], ip: &[u8], prefix_len: u8) -> bool {
+    let full_bytes = usize::from(prefix_len / 8);
+    let remaining_bits = prefix_len % 8;
+
+    if network[..full_bytes] != ip[..full_bytes] {
+        return false;
+    }
+
+    if remaining_bits == 0 {
+        return true;
+    }
+
+    let mask = u8::MAX << (8 - remaining_bits);
+    (network[full_bytes] & mask) == (ip[full_bytes] & mask)
+}
+
+#[derive(Debug)]
+struct IpAccessRule {
+    action: IpAccessAction,
+    cidrs: Vec<IpCidr>,
+}
+
+impl IpAccessRule {
+    fn new(rule_configuration: &crate::config::IpAccessRule) -> anyhow::Result<Self> {
+        let cidrs = rule_configuration
+            .cidrs
+            .iter()
+            .map(|cidr| IpCidr::new(cidr))
+            .collect::<anyhow::Result<Vec<_>>>()
+            .context("IpAccessRule::new: error parsing cidrs")?;
+
+        Ok(Self {
+            action: rule_configuration.action,
+            cidrs,
+        })
+    }
+}
+
+/// Ordered allow and deny rules, the first rule containing the address decides.
+#[derive(Debug)]
+pub struct IpAccessRules(Vec<IpAccessRule>);
+
+impl IpAccessRules {
+    fn new(rule_configurations: &[crate::config::IpAccessRule]) -> anyhow::Result<Self> {
+        let rules = rule_configurations
+            .iter()
+            .map(IpAccessRule::new)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(Self(rules))
+    }
+
+    /// Addresses not matching any rule are allowed.
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        self.0
+            .iter()
+            .find(|rule| rule.cidrs.iter().any(|cidr| cidr.contains(ip)))
+            .is_none_or(|rule| rule.action == IpAccessAction::Allow)
+    }
+}
+
+#[derive(Debug)]
+struct RouteIpAccessRule {
+    path_prefix: String,
+    rules: IpAccessRules,
+}
+
+#[derive(Debug)]
+pub struct IpAccessService {
+    listener_rules: Vec<IpAccessRules>,
+    route_rules: Vec<RouteIpAccessRule>,
+}
+
+impl IpAccessService {
+    fn new() -> anyhow::Result<Self> {
+        let server_configuration = &crate::config::instance().server_configuration;
+
+        let listener_rules = server_configuration
+            .listeners
+            .iter()
+            .map(|listener_configuration| {
+                let error_context = || {
+                    format!(
+                        "IpAccessService::new: error in listener bind_address = {:?}",
+                        listener_configuration.bind_address
+                    )
+                };
+
+                // UNIX socket peers have no address, use file permissions instead.
+                if matches!(listener_configuration.socket_type, ServerSocketType::Unix)
+                    && !listener_configuration.ip_access_rules.is_empty()
+                {
+                    return Err(anyhow::anyhow!(
+                        "ip_access_rules are not supported on UNIX listeners"
+                    ))
+                    .with_context(error_context);
+                }
+
+                IpAccessRules::new(&listener_configuration.ip_access_rules)
+                    .with_context(error_context)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let route_rules = server_configuration
+            .route_ip_access_rules
+            .iter()
+            .map(|route_rule_configuration| {
+                let rules = IpAccessRules::new(&route_rule_configuration.rules).with_context(
+                    || {
+                        format!(
+                            "IpAccessService::new: error in route_ip_access_rules path_prefix = {:?}",
+                            route_rule_configuration.path_prefix
+                        )
+                    },
+                )?;
+
+                Ok(RouteIpAccessRule {
+                    path_prefix: route_rule_configuration.path_prefix.clone(),
+                    rules,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        debug!(
+            "listener_rules = {:?} route_rules = {:?}",
+            listener_rules, route_rules
+        );
+
+        Ok(Self {
+            listener_rules,
+            route_rules,
+        })
+    }
+
+    /// Rules for the listener at listener_index in the server configuration.
+    pub fn listener_rules(&self, listener_index: usize) -> &IpAccessRules {
+        &self.listener_rules[listener_index]
+    }
+
+    /// path_prefix of the route rule that decides for path, if any.
+    ///
+    /// path is a normalized path, see [`crate::service::request_matcher::normalize_path`].
+    pub fn route_rule_path_prefix(&self, path: &str) -> Option<&str> {
+        self.route_rules
+            .iter()
+            .find(|route_rule| path_has_prefix(path, &route_rule.path_prefix))
+            .map(|route_rule| route_rule.path_prefix.as_str())
+    }
+
+    /// The first route rule whose path_prefix matches the normalized path decides.
+    /// Requests without a peer address (UNIX sockets) are always allowed.
+    pub fn allows_route(&self, path: &str, peer_ip_option: Option<IpAddr>) -> bool {
+        let Some(peer_ip) = peer_ip_option else {
+            return true;
+        };
+
+        self.route_rules
+            .iter()
+            .find(|route_rule| path_has_prefix(path, &route_rule.path_prefix))
+            .is_none_or(|route_rule| route_rule.rules.allows(peer_ip))
+    }
+}
+
+static IP_ACCESS_SERVICE_INSTANCE: OnceCell<IpAccessService> = OnceCell::const_new();
+
+pub fn create_ip_access_service_instance() -> anyhow::Result<()> {
+    let ip_access_service = IpAccessService::new()?;
+
+    IP_ACCESS_SERVICE_INSTANCE
+        .set(ip_access_service)
+        .context("IP_ACCESS_SERVICE_INSTANCE.set error")?;
+
+    Ok(())
+}
+
+pub fn ip_access_service_instance() -> &'static IpAccessService {
+    IP_ACCESS_SERVICE_INSTANCE.get().unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ip_cidr_contains() {
+        let cidr = IpCidr::new("192.168.0.0/16").unwrap();
+        assert!(cidr.contains("192.168.1.2".parse().unwrap()));
+        assert!(cidr.contains("::ffff:192.168.1.2".parse().unwrap()));
+        assert!(!cidr.contains("192.169.0.1".parse().unwrap()));
+        assert!(!cidr.contains("fd00::1".parse().unwrap()));
+
+        let cidr = IpCidr::new("10.0.0.0/9").unwrap();
+        assert!(cidr.contains("10.127.0.1".parse().unwrap()));
+        assert!(!cidr.contains("10.128.0.1".parse().unwrap()));
+
+        let cidr = IpCidr::new("fd00::/8").unwrap();
+        assert!(cidr.contains("fd12::1".parse().unwrap()));
+        assert!(!cidr.contains("fe80::1".parse().unwrap()));
+
+        assert!(IpCidr::new("127.0.0.1")
+            .unwrap()
+            .contains("127.0.0.1".parse().unwrap()));
+        assert!(IpCidr::new("0.0.0.0/0")
+            .unwrap()
+            .contains("8.8.8.8".parse().unwrap()));
+
+        assert!(IpCidr::new("10.0.0.0/33").is_err());
+        assert!(IpCidr::new("10.0.0/8").is_err());
+    }
+
+    #[test]
+    fn test_ip_cidr_ipv4_mapped_network() {
+        let cidr = IpCidr::new("::ffff:10.0.0.0/104").unwrap();
+        assert_eq!(cidr, IpCidr::new("10.0.0.0/8").unwrap());
+        assert!(cidr.contains("10.1.2.3".parse().unwrap()));
+        assert!(cidr.contains("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(!cidr.contains("11.1.2.3".parse().unwrap()));
+
+        assert!(IpCidr::new("::ffff:127.0.0.1")
+            .unwrap()
+            .contains("127.0.0.1".parse().unwrap()));
+
+        assert!(IpCidr::new("::ffff:0.0.0.0/95").is_err());
+    }
+
+    #[test]
+    fn test_ip_access_rules_allows() {
+        let rules = IpAccessRules::new(&[
+            crate::config::IpAccessRule {
+                action: IpAccessAction::Allow,
+                cidrs: vec!["192.168.0.0/16".to_owned(), "::1".to_owned()],
+            },
+            crate::config::IpAccessRule {
+                action: IpAccessAction::Deny,
+                cidrs: vec!["0.0.0.0/0".to_owned(), "::/0".to_owned()],
+            },
+        ])
+        .unwrap();
+
+        assert!(rules.allows("192.168.1.1".parse().unwrap()));
+        assert!(rules.allows("::1".parse().unwrap()));
+        assert!(!rules.allows("8.8.8.8".parse().unwrap()));
+        assert!(!rules.allows("2001:db8::1".parse().unwrap()));
+
+        assert!(IpAccessRules::new(&[])
+            .unwrap()
+            .allows("8.8.8.8".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_allows_route_normalized_path() {
+        use crate::service::request_matcher::normalize_path;
+
+        let ip_access_service = IpAccessService {
+            listener_rules: Vec::new(),
+            route_rules: vec![RouteIpAccessRule {
+                path_prefix: "/admin".to_owned(),
+                rules: IpAccessRules::new(&[crate::config::IpAccessRule {
+                    action: IpAccessAction::Deny,
+                    cidrs: vec!["0.0.0.0/0".to_owned()],
+                }])
+                .unwrap(),
+            }],
+        };
+
+        let peer_ip = Some("8.8.8.8".parse().unwrap());
+
+        for path in [
+            "/admin/x",
+            "/%61dmin/x",
+            "//admin/x",
+            "/./admin/x",
+            "/public/../admin/x",
+        ] {
+            assert!(
+                !ip_access_service.allows_route(&normalize_path(path), peer_ip),
+                "path = {:?}",
+                path
+            );
+        }
+
+        assert!(ip_access_service.allows_route(&normalize_path("/adminx"), peer_ip));
+        assert!(ip_access_service.allows_route(&normalize_path("/admin/x"), None));
+    }
+}
@@ -0,0 +1,312 @@
+use ahash::AHashMap;
+
+use anyhow::Context;
+
+use tokio::{
+    sync::OnceCell,
+    time::{Duration, Instant},
+};
+
+use tracing::debug;
+
+use std::{
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use crate::{request::HttpRequest, service::request_matcher::path_has_prefix};
+
+const EVICTION_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Clients are identified by IP address, or by peer uid on UNIX sockets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum RateLimitKey {
+    Ip(IpAddr),
+    UnixUid(u32),
+}
+
+impl RateLimitKey {
+    /// None if the client cannot be identified.
+    fn new(request: &HttpRequest) -> Option<Self> {
+        match (request.peer_ip, request.peer_uid) {
+            (Some(peer_ip), _) => Some(Self::Ip(peer_ip.to_canonical())),
+            (None, Some(peer_uid)) => Some(Self::UnixUid(peer_uid)),
+            (None, None) => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+#[derive(Debug)]
+struct RateLimitRule {
+    path_prefix: String,
+    burst: u32,
+    refill_per_second: f64,
+    rejections: AtomicUsize,
+}
+
+impl RateLimitRule {
+    fn new(rule_configuration: &crate::config::RateLimitRule) -> anyhow::Result<Self> {
+        if rule_configuration.burst == 0 {
+            anyhow::bail!("RateLimitRule::new: burst must be at least 1");
+        }
+
+        if !(rule_configuration.refill_per_second.is_finite()
+            && rule_configuration.refill_per_second > 0.0)
+        {
+            anyhow::bail!("RateLimitRule::new: refill_per_second must be positive");
+        }
+
+        Ok(Self {
+            path_prefix: rule_configuration.path_prefix.clone(),
+            burst: rule_configuration.burst,
+            refill_per_second: rule_configuration.refill_per_second,
+            rejections: AtomicUsize::new(0),
+        })
+    }
+
+    fn refilled_tokens(&self, bucket: &TokenBucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.last_refill);
+
+        (bucket.tokens + elapsed.as_secs_f64() * self.refill_per_second).min(self.burst.into())
+    }
+
+    fn seconds_until(&self, tokens: f64, target_tokens: f64) -> Duration {
+        Duration::from_secs(((target_tokens - tokens) / self.refill_per_second).ceil() as u64)
+    }
+}
+
+/// Values for the 429 response headers.
+#[derive(Debug, PartialEq)]
+pub struct RateLimitRejection {
+    pub limit: u32,
+    pub retry_after: Duration,
+    pub reset: Duration,
+}
+
+#[derive(Debug)]
+pub struct RateLimitRuleSnapshot {
+    pub path_prefix: String,
+    pub burst: u32,
+    pub refill_per_second: f64,
+    pub rejections: usize,
+}
+
+#[derive(Debug)]
+pub struct RateLimitSnapshot {
+    pub active_buckets: usize,
+    pub rules: Vec<RateLimitRuleSnapshot>,
+}
+
+/// Token buckets per rule and client, the first rule whose path_prefix matches applies.
+#[derive(Debug)]
+pub struct RateLimitService {
+    rules: Vec<RateLimitRule>,
+    buckets: Mutex<AHashMap<(usize, RateLimitKey), TokenBucket>>,
+}
+
+impl RateLimitService {
+    fn new(rule_configurations: &[crate::config::RateLimitRule]) -> anyhow::Result<Self> {
+        let rules = rule_configurations
+            .iter()
+            .map(RateLimitRule::new)
+            .collect::<anyhow::Result<Vec<_>>>()
+            .context("RateLimitService::new: error in rate_limit_rules")?;
+
+        debug!("rules = {:?}", rules);
+
+        Ok(Self {
+            rules,
+            buckets: Mutex::new(AHashMap::new()),
+        })
+    }
+
+    fn take_token(
+        &self,
+        path: &str,
+        key: RateLimitKey,
+        now: Instant,
+    ) -> Result<(), RateLimitRejection> {
+        let Some((rule_index, rule)) = self
+            .rules
+            .iter()
+            .enumerate()
+            .find(|(_, rule)| path_has_prefix(path, &rule.path_prefix))
+        else {
+            return Ok(());
+        };
+
+        let mut buckets = self.buckets.lock().unwrap();
+
+        let bucket = buckets
+            .entry((rule_index, key))
+            .or_insert_with(|| TokenBucket {
+                tokens: rule.burst.into(),
+                last_refill: now,
+            });
+
+        bucket.tokens = rule.refilled_tokens(bucket, now);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+
+        rule.rejections.fetch_add(1, Ordering::Relaxed);
+
+        Err(RateLimitRejection {
+            limit: rule.burst,
+            retry_after: rule.seconds_until(bucket.tokens, 1.0),
+            reset: rule.seconds_until(bucket.tokens, rule.burst.into()),
+        })
+    }
+
+    /// Take a token for the request, or return why it was rejected.
+    ///
+    /// Requests from clients that cannot be identified are not limited,
+    /// since one shared bucket would let any of them starve the others.
+    pub fn check(&self, request: &HttpRequest) -> Result<(), RateLimitRejection> {
+        if self.rules.is_empty() {
+            return Ok(());
+        }
+
+        let Some(key) = RateLimitKey::new(request) else {
+            return Ok(());
+        };
+
+        self.take_token(&request.normalized_path, key, Instant::now())
+    }
+
+    /// A full bucket behaves the same as a missing one, so full buckets are removed.
+    fn evict_full_buckets(&self, now: Instant) {
+        let mut buckets = self.buckets.lock().unwrap();
+
+        let before = buckets.len();
+
+        buckets.retain(|(rule_index, _), bucket| {
+            let rule = &self.rules[*rule_index];
+            rule.refilled_tokens(bucket, now) < f64::from(rule.burst)
+        });
+
+        debug!(
+            "evicted {} rate limit buckets remaining = {}",
+            before - buckets.len(),
+            buckets.len()
+        );
+    }
+
+    fn start_eviction_task(&'static self) {
+        if self.rules.is_empty() {
+            return;
+        }
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(EVICTION_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                self.evict_full_buckets(Instant::now());
+            }
+        });
+    }
+
+    pub fn snapshot(&self) -> RateLimitSnapshot {
+        RateLimitSnapshot {
+            active_buckets: self.buckets.lock().unwrap().len(),
+            rules: self
+                .rules
+                .iter()
+                .map(|rule| RateLimitRuleSnapshot {
+                    path_prefix: rule.path_prefix.clone(),
+                    burst: rule.burst,
+                    refill_per_second: rule.refill_per_second,
+                    rejections: rule.rejections.load(Ordering::Relaxed),
+                })
+                .collect(),
+        }
+    }
+}
+
+static RATE_LIMIT_SERVICE_INSTANCE: OnceCell<RateLimitService> = OnceCell::const_new();
+
+pub fn create_rate_limit_service_instance() -> anyhow::Result<()> {
+    let rate_limit_service = RateLimitService::new(&crate::config::instance().rate_limit_rules)?;
+
+    RATE_LIMIT_SERVICE_INSTANCE
+        .set(rate_limit_service)
+        .context("RATE_LIMIT_SERVICE_INSTANCE.set error")?;
+
+    rate_limit_service_instance().start_eviction_task();
+
+    Ok(())
+}
+
+pub fn rate_limit_service_instance() -> &'static RateLimitService {
+    RATE_LIMIT_SERVICE_INSTANCE.get().unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::service::request_matcher::normalize_path;
+
+    #[test]
+    fn test_token_bucket() {
+        let rate_limit_service = RateLimitService::new(&[crate::config::RateLimitRule {
+            path_prefix: "/api".to_owned(),
+            burst: 2,
+            refill_per_second: 0.5,
+        }])
+        .unwrap();
+
+        let key = RateLimitKey::Ip("10.0.0.1".parse().unwrap());
+        let other_key = RateLimitKey::UnixUid(1000);
+        let now = Instant::now();
+
+        assert!(rate_limit_service.take_token("/api/x", key, now).is_ok());
+        assert!(rate_limit_service.take_token("/api/x", key, now).is_ok());
+        assert_eq!(
+            rate_limit_service.take_token("/api/x", key, now),
+            Err(RateLimitRejection {
+                limit: 2,
+                retry_after: Duration::from_secs(2),
+                reset: Duration::from_secs(4),
+            })
+        );
+
+        assert!(rate_limit_service
+            .take_token("/api/x", other_key, now)
+            .is_ok());
+        assert!(rate_limit_service.take_token("/static", key, now).is_ok());
+
+        // bypass forms of the path are normalized before prefix matching.
+        for path in ["/%61pi/x", "//api/x", "/static/../api/x"] {
+            assert!(
+                rate_limit_service
+                    .take_token(&normalize_path(path), key, now)
+                    .is_err(),
+                "path = {:?}",
+                path
+            );
+        }
+
+        let later = now + Duration::from_secs(2);
+        assert!(rate_limit_service.take_token("/api/x", key, later).is_ok());
+        assert!(rate_limit_service.take_token("/api/x", key, later).is_err());
+
+        rate_limit_service.evict_full_buckets(now + Duration::from_secs(10));
+        assert_eq!(rate_limit_service.snapshot().active_buckets, 0);
+        assert_eq!(rate_limit_service.snapshot().rules[0].rejections, 5);
+    }
+}
@@ -0,0 +1,153 @@
+use anyhow::Context;
+
+use hyper::http::{HeaderMap, HeaderName, HeaderValue};
+
+use tokio::sync::OnceCell;
+
+use tracing::debug;
+
+use std::net::IpAddr;
+
+use crate::{config::RequestIdFormat, service::ip_access::IpCidr};
+
+/// Assigns each request an ID that can be correlated across proxies, browsers and logs.
+#[derive(Debug)]
+pub struct RequestIdService {
+    header_name: HeaderName,
+    format: RequestIdFormat,
+    max_length: usize,
+    trusted_cidrs: Vec<IpCidr>,
+    trust_unix_peers: bool,
+}
+
+impl RequestIdService {
+    fn new() -> anyhow::Result<Self> {
+        let request_id_configuration = &crate::config::instance().request_id_configuration;
+
+        let header_name = HeaderName::try_from(request_id_configuration.header_name.as_str())
+            .with_context(|| {
+                format!(
+                    "RequestIdService::new: invalid header_name {:?}",
+                    request_id_configuration.header_name
+                )
+            })?;
+
+        let trusted_cidrs = request_id_configuration
+            .trusted_cidrs
+            .iter()
+            .map(|cidr| IpCidr::new(cidr))
+            .collect::<anyhow::Result<Vec<_>>>()
+            .context("RequestIdService::new: error parsing trusted_cidrs")?;
+
+        let request_id_service = Self {
+            header_name,
+            format: request_id_configuration.format,
+            max_length: request_id_configuration.max_length,
+            trusted_cidrs,
+            trust_unix_peers: request_id_configuration.trust_unix_peers,
+        };
+
+        debug!("request_id_service = {:?}", request_id_service);
+
+        Ok(request_id_service)
+    }
+
+    pub fn header_name(&self) -> &HeaderName {
+        &self.header_name
+    }
+
+    /// Requests over UNIX sockets have no peer address.
+    fn is_trusted(&self, peer_ip_option: Option<IpAddr>) -> bool {
+        match peer_ip_option {
+            None => self.trust_unix_peers,
+            Some(peer_ip) => self.trusted_cidrs.iter().any(|cidr| cidr.contains(peer_ip)),
+        }
+    }
+
+    fn is_valid(&self, request_id: &str) -> bool {
+        !request_id.is_empty()
+            && request_id.len() <= self.max_length
+            && request_id
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b':'))
+    }
+
+    fn generate(&self) -> String {
+        match self.format {
+            RequestIdFormat::Uuid => uuid::Uuid::new_v4().to_string(),
+            RequestIdFormat::Ulid => ulid::Ulid::new().to_string(),
+        }
+    }
+
+    /// Use a valid inbound request ID from a trusted peer, otherwise generate one.
+    pub fn request_id(&self, headers: &HeaderMap, peer_ip_option: Option<IpAddr>) -> HeaderValue {
+        if self.is_trusted(peer_ip_option) {
+            if let Some(value) = headers.get(&self.header_name) {
+                if value.to_str().is_ok_and(|value| self.is_valid(value)) {
+                    return value.clone();
+                }
+                debug!("ignoring invalid inbound request id {:?}", value);
+            }
+        }
+
+        HeaderValue::try_from(self.generate()).expect("generated request id is a valid header")
+    }
+}
+
+static REQUEST_ID_SERVICE_INSTANCE: OnceCell<RequestIdService> = OnceCell::const_new();
+
+pub fn create_request_id_service_instance() -> anyhow::Result<()> {
+    let request_id_service = RequestIdService::new()?;
+
+    REQUEST_ID_SERVICE_INSTANCE
+        .set(request_id_service)
+        .context("REQUEST_ID_SERVICE_INSTANCE.set error")?;
+
+    Ok(())
+}
+
+pub fn request_id_service_instance() -> &'static RequestIdService {
+    REQUEST_ID_SERVICE_INSTANCE.get().unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_request_id() {
+        let request_id_service = RequestIdService {
+            header_name: HeaderName::from_static("x-request-id"),
+            format: RequestIdFormat::Ulid,
+            max_length: 16,
+            trusted_cidrs: vec![IpCidr::new("10.0.0.0/8").unwrap()],
+            trust_unix_peers: true,
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-request-id", HeaderValue::from_static("abc-123"));
+
+        assert_eq!(request_id_service.request_id(&headers, None), "abc-123");
+        assert_eq!(
+            request_id_service.request_id(&headers, Some("10.1.2.3".parse().unwrap())),
+            "abc-123"
+        );
+
+        // untrusted peers get a generated ULID.
+        let generated =
+            request_id_service.request_id(&headers, Some("192.168.1.1".parse().unwrap()));
+        assert_eq!(generated.len(), 26);
+
+        headers.insert("x-request-id", HeaderValue::from_static("a b"));
+        assert_ne!(request_id_service.request_id(&headers, None), "a b");
+
+        headers.insert(
+            "x-request-id",
+            HeaderValue::from_static("0123456789abcdefg"),
+        );
+        assert_ne!(
+            request_id_service.request_id(&headers, None),
+            "0123456789abcdefg"
+        );
+    }
+}
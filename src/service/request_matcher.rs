@@ -0,0 +1,164 @@
+use anyhow::Context;
+
+use hyper::http::header;
+
+use crate::request::HttpRequest;
+
+pub struct RequestMatchData<'a> {
+    pub host_option: Option<&'a str>,
+    pub path_option: Option<&'a str>,
+}
+
+#[derive(Debug)]
+pub struct RequestMatcher {
+    host_regex: Option<regex::Regex>,
+    path_regex: Option<regex::Regex>,
+}
+
+impl RequestMatcher {
+    pub fn new(host_regex: &Option<String>, path_regex: &Option<String>) -> anyhow::Result<Self> {
+        let host_regex = match host_regex {
+            None => None,
+            Some(host_regex) => Some(
+                regex::Regex::new(host_regex)
+                    .context("RequestMatcher::new: error parsing host_regex")?,
+            ),
+        };
+
+        let path_regex = match path_regex {
+            None => None,
+            Some(path_regex) => Some(
+                regex::Regex::new(path_regex)
+                    .context("RequestMatcher::new: error parsing path_regex")?,
+            ),
+        };
+
+        Ok(Self {
+            host_regex,
+            path_regex,
+        })
+    }
+
+    pub fn host_regex(&self) -> Option<&str> {
+        self.host_regex.as_ref().map(regex::Regex::as_str)
+    }
+
+    pub fn path_regex(&self) -> Option<&str> {
+        self.path_regex.as_ref().map(regex::Regex::as_str)
+    }
+
+    /// Returns true if path_regex is unset or matches path, ignoring host_regex.
+    pub fn matches_path(&self, path: &str) -> bool {
+        self.path_regex
+            .as_ref()
+            .is_none_or(|path_regex| path_regex.is_match(path))
+    }
+
+    pub fn matches(&self, request_match_data: &RequestMatchData) -> bool {
+        self.mismatch_reason(request_match_data).is_none()
+    }
+
+    /// Returns why the request does not match, or None if it matches.
+    pub fn mismatch_reason(&self, request_match_data: &RequestMatchData) -> Option<&'static str> {
+        if let Some(host_regex) = &self.host_regex {
+            match request_match_data.host_option {
+                None => return Some("no host to match host_regex"),
+                Some(host) if !host_regex.is_match(host) => {
+                    return Some("host_regex did not match host")
+                }
+                Some(_) => {}
+            }
+        }
+
+        if let Some(path_regex) = &self.path_regex {
+            match request_match_data.path_option {
+                None => return Some("no path to match path_regex"),
+                Some(path) if !path_regex.is_match(path) => {
+                    return Some("path_regex did not match path")
+                }
+                Some(_) => {}
+            }
+        }
+
+        None
+    }
+}
+
+/// Returns the Host header, or the URI authority for HTTP/2 requests.
+pub fn request_host(request: &HttpRequest) -> Option<&str> {
+    let hyper_request = &request.hyper_request;
+
+    hyper_request
+        .headers()
+        .get(header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .or_else(|| hyper_request.uri().authority().map(|a| a.as_str()))
+}
+
+/// Percent-decode a request path and resolve `.`, `..` and empty segments the way
+/// the static file resolver does, so rules match the path that is actually served.
+/// A trailing slash is kept since it marks a directory request.
+pub fn normalize_path(path: &str) -> String {
+    let decoded = percent_encoding::percent_decode_str(path).decode_utf8_lossy();
+
+    let mut segments = Vec::new();
+
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+
+    let mut normalized = format!("/{}", segments.join("/"));
+
+    if path.ends_with('/') && !segments.is_empty() {
+        normalized.push('/');
+    }
+
+    normalized
+}
+
+/// Returns true if path is prefix or below it, so `/api` matches `/api/x` but not `/apix`.
+pub fn path_has_prefix(path: &str, prefix: &str) -> bool {
+    path.strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/') || prefix.ends_with('/'))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_normalize_path() {
+        for path in [
+            "/private/x",
+            "/%70rivate/x",
+            "//private/x",
+            "/./private/x",
+            "/public/../private/x",
+            "/public/%2e%2e/private/x",
+            "/public%2f..%2fprivate/x",
+        ] {
+            assert_eq!(normalize_path(path), "/private/x", "path = {:?}", path);
+        }
+
+        assert_eq!(normalize_path("/private//"), "/private/");
+        assert_eq!(normalize_path("/../"), "/");
+        assert_eq!(normalize_path(""), "/");
+        assert_eq!(normalize_path("/a%20b"), "/a b");
+    }
+
+    #[test]
+    fn test_path_has_prefix() {
+        assert!(path_has_prefix("/api/v1/commands", "/api/v1/commands"));
+        assert!(path_has_prefix("/api/v1/commands/echo", "/api/v1/commands"));
+        assert!(path_has_prefix("/downloads/a.txt", "/downloads/"));
+        assert!(path_has_prefix("/anything", "/"));
+        assert!(!path_has_prefix("/api/v1/commandsx", "/api/v1/commands"));
+        assert!(!path_has_prefix("/api", "/api/v1"));
+    }
+}
@@ -0,0 +1,251 @@
+use anyhow::Context;
+
+use hyper::http::{HeaderMap, HeaderName, HeaderValue};
+
+use tokio::sync::OnceCell;
+
+use tracing::debug;
+
+use std::collections::BTreeMap;
+
+use crate::{
+    request::HttpRequest,
+    service::request_matcher::{request_host, RequestMatchData, RequestMatcher},
+};
+
+fn parse_headers(
+    headers: &BTreeMap<String, String>,
+) -> anyhow::Result<Vec<(HeaderName, HeaderValue)>> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let name = HeaderName::try_from(name.as_str())
+                .with_context(|| format!("invalid header name {:?}", name))?;
+
+            let value = HeaderValue::try_from(value.as_str())
+                .with_context(|| format!("invalid header value {:?}", value))?;
+
+            Ok((name, value))
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+struct ResponseHeaderRule {
+    request_matcher: RequestMatcher,
+    add: Vec<(HeaderName, HeaderValue)>,
+    set: Vec<(HeaderName, HeaderValue)>,
+    remove: Vec<HeaderName>,
+}
+
+impl ResponseHeaderRule {
+    fn new(rule_configuration: &crate::config::ResponseHeaderRule) -> anyhow::Result<Self> {
+        let request_matcher = RequestMatcher::new(
+            &rule_configuration.host_regex,
+            &rule_configuration.path_regex,
+        )?;
+
+        let add = parse_headers(&rule_configuration.add)
+            .context("ResponseHeaderRule::new: error parsing add")?;
+
+        let set = parse_headers(&rule_configuration.set)
+            .context("ResponseHeaderRule::new: error parsing set")?;
+
+        let remove = rule_configuration
+            .remove
+            .iter()
+            .map(|name| {
+                HeaderName::try_from(name.as_str())
+                    .with_context(|| format!("invalid header name {:?}", name))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()
+            .context("ResponseHeaderRule::new: error parsing remove")?;
+
+        Ok(Self {
+            request_matcher,
+            add,
+            set,
+            remove,
+        })
+    }
+
+    fn apply(&self, headers: &mut HeaderMap) {
+        for name in &self.remove {
+            headers.remove(name);
+        }
+
+        for (name, value) in &self.set {
+            headers.insert(name.clone(), value.clone());
+        }
+
+        for (name, value) in &self.add {
+            headers.append(name.clone(), value.clone());
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ResponseHeaderRules(Vec<ResponseHeaderRule>);
+
+impl ResponseHeaderRules {
+    fn new(rule_configurations: &[crate::config::ResponseHeaderRule]) -> anyhow::Result<Self> {
+        let rules = rule_configurations
+            .iter()
+            .map(ResponseHeaderRule::new)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(Self(rules))
+    }
+
+    /// Every matching rule is applied in configuration order.
+    fn apply(&self, request: &HttpRequest, headers: &mut HeaderMap) {
+        if self.0.is_empty() {
+            return;
+        }
+
+        let request_match_data = RequestMatchData {
+            host_option: request_host(request),
+            path_option: Some(&request.normalized_path),
+        };
+
+        self.apply_match(&request_match_data, headers);
+    }
+
+    fn apply_match(&self, request_match_data: &RequestMatchData, headers: &mut HeaderMap) {
+        for rule in &self.0 {
+            if rule.request_matcher.matches(request_match_data) {
+                rule.apply(headers);
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ResponseHeaderRulesService {
+    static_file_rules: ResponseHeaderRules,
+    dynamic_route_rules: ResponseHeaderRules,
+}
+
+impl ResponseHeaderRulesService {
+    fn new() -> anyhow::Result<Self> {
+        let configuration = crate::config::instance();
+
+        let static_file_rules =
+            ResponseHeaderRules::new(&configuration.static_file_configuration.header_rules)
+                .context("ResponseHeaderRulesService::new: error in static_file_configuration")?;
+
+        let dynamic_route_rules =
+            ResponseHeaderRules::new(&configuration.context_configuration.header_rules)
+                .context("ResponseHeaderRulesService::new: error in context_configuration")?;
+
+        debug!(
+            "static_file_rules = {:?} dynamic_route_rules = {:?}",
+            static_file_rules, dynamic_route_rules
+        );
+
+        Ok(Self {
+            static_file_rules,
+            dynamic_route_rules,
+        })
+    }
+
+    pub fn apply_static_file_rules(&self, request: &HttpRequest, headers: &mut HeaderMap) {
+        self.static_file_rules.apply(request, headers);
+    }
+
+    pub fn apply_dynamic_route_rules(&self, request: &HttpRequest, headers: &mut HeaderMap) {
+        self.dynamic_route_rules.apply(request, headers);
+    }
+}
+
+static HEADER_RULES_SERVICE_INSTANCE: OnceCell<ResponseHeaderRulesService> = OnceCell::const_new();
+
+pub fn create_header_rules_service_instance() -> anyhow::Result<()> {
+    let header_rules_service = ResponseHeaderRulesService::new()?;
+
+    HEADER_RULES_SERVICE_INSTANCE
+        .set(header_rules_service)
+        .context("HEADER_RULES_SERVICE_INSTANCE.set error")?;
+
+    Ok(())
+}
+
+pub fn header_rules_service_instance() -> &'static ResponseHeaderRulesService {
+    HEADER_RULES_SERVICE_INSTANCE.get().unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_response_header_rule_apply() {
+        let rule = ResponseHeaderRule::new(&crate::config::ResponseHeaderRule {
+            host_regex: None,
+            path_regex: None,
+            add: BTreeMap::from([("vary".to_owned(), "origin".to_owned())]),
+            set: BTreeMap::from([("x-content-type-options".to_owned(), "nosniff".to_owned())]),
+            remove: vec!["server".to_owned()],
+        })
+        .unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("server", HeaderValue::from_static("rhs"));
+        headers.insert("vary", HeaderValue::from_static("accept-encoding"));
+        headers.insert("x-content-type-options", HeaderValue::from_static("old"));
+
+        rule.apply(&mut headers);
+
+        assert!(headers.get("server").is_none());
+        assert_eq!(headers.get_all("vary").iter().count(), 2);
+        assert_eq!(headers.get("x-content-type-options").unwrap(), "nosniff");
+    }
+
+    #[test]
+    fn test_response_header_rules_normalized_path() {
+        use crate::service::request_matcher::normalize_path;
+
+        let rules = ResponseHeaderRules::new(&[crate::config::ResponseHeaderRule {
+            host_regex: None,
+            path_regex: Some("^/app/".to_owned()),
+            add: BTreeMap::new(),
+            set: BTreeMap::from([(
+                "content-security-policy".to_owned(),
+                "default-src 'self'".to_owned(),
+            )]),
+            remove: vec![],
+        }])
+        .unwrap();
+
+        for path in ["/app/index.html", "/%61pp/index.html", "//app/index.html"] {
+            let normalized_path = normalize_path(path);
+            let mut headers = HeaderMap::new();
+
+            rules.apply_match(
+                &RequestMatchData {
+                    host_option: None,
+                    path_option: Some(&normalized_path),
+                },
+                &mut headers,
+            );
+
+            assert!(
+                headers.contains_key("content-security-policy"),
+                "path = {:?}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn test_response_header_rule_invalid_header() {
+        assert!(ResponseHeaderRule::new(&crate::config::ResponseHeaderRule {
+            host_regex: None,
+            path_regex: None,
+            add: BTreeMap::from([("bad header".to_owned(), "value".to_owned())]),
+            set: BTreeMap::new(),
+            remove: vec![],
+        })
+        .is_err());
+    }
+}
@@ -1,117 +1,136 @@
+pub mod archive;
+pub mod content_hash;
+pub mod content_type;
+pub mod dot_path;
+pub mod mount;
+pub mod rewrite;
+pub mod vfs;
+
 use anyhow::Context;
 
+use hyper::http::HeaderValue;
+
+use schemars::JsonSchema;
+
+use serde::Serialize;
+
 use tokio::{sync::OnceCell, time::Duration};
 
 use tracing::debug;
 
-use std::{fmt::Debug, time::SystemTime};
+use std::{fmt::Debug, fmt::Write, time::SystemTime};
 
-use crate::config::StaticFileCacheRuleType;
+use crate::{
+    config::StaticFileCacheRuleType,
+    request::HttpRequest,
+    service::request_matcher::{request_host, RequestMatchData, RequestMatcher},
+};
 
-struct RequestMatchData<'a> {
-    host_option: Option<&'a str>,
-    resolved_path_option: Option<&'a str>,
-}
+use self::{
+    content_type::ContentTypeRules,
+    dot_path::DotPathRules,
+    rewrite::{RewriteResult, RewriteRules},
+    vfs::{ResolveResult, ResolvedFile},
+};
 
+/// Cache-Control directives appended after max-age.
 #[derive(Debug)]
-struct RequestMatcher {
-    host_regex: Option<regex::Regex>,
-    path_regex: Option<regex::Regex>,
-}
-
-impl RequestMatcher {
-    fn new(cache_rule_config: &crate::config::StaticFileCacheRule) -> anyhow::Result<Self> {
-        let host_regex = match &cache_rule_config.host_regex {
-            None => None,
-            Some(host_regex) => Some(
-                regex::Regex::new(host_regex)
-                    .context("StaticFileRulesService::new: error parsing host_regex")?,
-            ),
-        };
-
-        let path_regex = match &cache_rule_config.path_regex {
-            None => None,
-            Some(path_regex) => Some(
-                regex::Regex::new(path_regex)
-                    .context("StaticFileRulesService::new: error parsing path_regex")?,
-            ),
-        };
+struct CacheControlDirectives {
+    private: bool,
+    immutable: bool,
+    stale_while_revalidate: Option<Duration>,
+    stale_if_error: Option<Duration>,
+}
 
-        Ok(Self {
-            host_regex,
-            path_regex,
-        })
+impl CacheControlDirectives {
+    fn new(cache_rule: &crate::config::StaticFileCacheRule) -> Self {
+        Self {
+            private: matches!(cache_rule.rule_type, StaticFileCacheRuleType::Private),
+            immutable: matches!(cache_rule.rule_type, StaticFileCacheRuleType::Immutable),
+            stale_while_revalidate: cache_rule.stale_while_revalidate,
+            stale_if_error: cache_rule.stale_if_error,
+        }
     }
 
-    fn matches(&self, request_match_data: &RequestMatchData) -> bool {
-        let matches = match &self.host_regex {
-            None => true,
-            Some(host_regex) => match request_match_data.host_option {
-                None => false,
-                Some(host) => host_regex.is_match(host),
-            },
-        };
+    fn build_header_value(&self, max_age: Duration) -> HeaderValue {
+        let mut value = format!(
+            "{}, max-age={}",
+            if self.private { "private" } else { "public" },
+            max_age.as_secs()
+        );
+
+        if self.immutable {
+            value.push_str(", immutable");
+        }
 
-        if !matches {
-            return false;
+        if let Some(stale_while_revalidate) = self.stale_while_revalidate {
+            let _ = write!(
+                value,
+                ", stale-while-revalidate={}",
+                stale_while_revalidate.as_secs()
+            );
         }
 
-        match &self.path_regex {
-            None => true,
-            Some(path_regex) => match request_match_data.resolved_path_option {
-                None => false,
-                Some(resolved_path) => path_regex.is_match(resolved_path),
-            },
+        if let Some(stale_if_error) = self.stale_if_error {
+            let _ = write!(value, ", stale-if-error={}", stale_if_error.as_secs());
         }
+
+        HeaderValue::try_from(value).unwrap()
     }
 }
 
 trait CacheRule: Send + Sync + Debug {
-    fn build_cache_header(
-        &self,
-        resolved_file: &hyper_staticfile::ResolvedFile,
-    ) -> Option<Duration>;
+    /// Computed max-age, or None if the rule does not allow caching.
+    fn max_age(&self, resolved_file: &ResolvedFile) -> Option<Duration>;
+
+    fn build_cache_header(&self, resolved_file: &ResolvedFile) -> Option<HeaderValue>;
 }
 
 #[derive(Debug)]
 struct FixedTimeCacheHeaderRule {
     file_cache_duration: Duration,
+    directives: CacheControlDirectives,
 }
 
 impl FixedTimeCacheHeaderRule {
-    fn new(file_cache_duration: Duration) -> Self {
+    fn new(file_cache_duration: Duration, directives: CacheControlDirectives) -> Self {
         Self {
             file_cache_duration,
+            directives,
         }
     }
 }
 
 impl CacheRule for FixedTimeCacheHeaderRule {
-    fn build_cache_header(&self, _: &hyper_staticfile::ResolvedFile) -> Option<Duration> {
+    fn max_age(&self, _: &ResolvedFile) -> Option<Duration> {
         Some(self.file_cache_duration)
     }
+
+    fn build_cache_header(&self, resolved_file: &ResolvedFile) -> Option<HeaderValue> {
+        self.max_age(resolved_file)
+            .map(|max_age| self.directives.build_header_value(max_age))
+    }
 }
 
 #[derive(Debug)]
 struct ModificationTimePlusDeltaCacheHeaderRule {
     file_cache_duration: Duration,
+    directives: CacheControlDirectives,
 }
 
 impl ModificationTimePlusDeltaCacheHeaderRule {
-    fn new(file_cache_duration: Duration) -> Self {
+    fn new(file_cache_duration: Duration, directives: CacheControlDirectives) -> Self {
         Self {
             file_cache_duration,
+            directives,
         }
     }
 }
 
 impl CacheRule for ModificationTimePlusDeltaCacheHeaderRule {
-    fn build_cache_header(
-        &self,
-        resolved_file: &hyper_staticfile::ResolvedFile,
-    ) -> Option<Duration> {
-        match resolved_file.modified {
-            None => Some(Duration::from_secs(0)),
+    fn max_age(&self, resolved_file: &ResolvedFile) -> Option<Duration> {
+        let request_cache_duration = match resolved_file.modified {
+            None => Duration::from_secs(0),
             Some(modified) => {
                 let now = SystemTime::now();
 
@@ -125,66 +144,319 @@ impl CacheRule for ModificationTimePlusDeltaCacheHeaderRule {
                     file_expiration, request_cache_duration
                 );
 
-                Some(request_cache_duration)
+                request_cache_duration
             }
+        };
+
+        Some(request_cache_duration)
+    }
+
+    fn build_cache_header(&self, resolved_file: &ResolvedFile) -> Option<HeaderValue> {
+        self.max_age(resolved_file)
+            .map(|max_age| self.directives.build_header_value(max_age))
+    }
+}
+
+#[derive(Debug)]
+struct NoStoreCacheHeaderRule;
+
+impl CacheRule for NoStoreCacheHeaderRule {
+    fn max_age(&self, _: &ResolvedFile) -> Option<Duration> {
+        None
+    }
+
+    fn build_cache_header(&self, _: &ResolvedFile) -> Option<HeaderValue> {
+        static NO_STORE_VALUE: HeaderValue = HeaderValue::from_static("no-store");
+
+        Some(NO_STORE_VALUE.clone())
+    }
+}
+
+fn build_cache_rule(
+    cache_rule: &crate::config::StaticFileCacheRule,
+) -> anyhow::Result<Box<dyn CacheRule>> {
+    let directives = CacheControlDirectives::new(cache_rule);
+
+    // only NO_STORE rules may omit duration.
+    let duration = || {
+        cache_rule.duration.with_context(|| {
+            format!(
+                "StaticFileRulesService::new: {:?} cache rule requires duration",
+                cache_rule.rule_type
+            )
+        })
+    };
+
+    Ok(match cache_rule.rule_type {
+        StaticFileCacheRuleType::FixedTime
+        | StaticFileCacheRuleType::Private
+        | StaticFileCacheRuleType::Immutable => {
+            Box::new(FixedTimeCacheHeaderRule::new(duration()?, directives))
+        }
+        StaticFileCacheRuleType::ModTimePlusDelta => Box::new(
+            ModificationTimePlusDeltaCacheHeaderRule::new(duration()?, directives),
+        ),
+        StaticFileCacheRuleType::NoStore => {
+            if cache_rule.stale_while_revalidate.is_some() || cache_rule.stale_if_error.is_some() {
+                anyhow::bail!(
+                    "StaticFileRulesService::new: NO_STORE cache rule can not set stale_while_revalidate or stale_if_error"
+                );
+            }
+            Box::new(NoStoreCacheHeaderRule)
+        }
+    })
+}
+
+#[derive(Debug)]
+struct CacheRuleEntry {
+    request_matcher: RequestMatcher,
+    rule_type: StaticFileCacheRuleType,
+    rule: Box<dyn CacheRule>,
+}
+
+impl CacheRuleEntry {
+    fn description(&self, index: usize) -> CacheRuleDescription {
+        CacheRuleDescription {
+            index,
+            host_regex: self.request_matcher.host_regex().map(str::to_owned),
+            path_regex: self.request_matcher.path_regex().map(str::to_owned),
+            rule_type: self.rule_type,
         }
     }
 }
 
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct CacheRuleDescription {
+    pub index: usize,
+    pub host_regex: Option<String>,
+    pub path_regex: Option<String>,
+    pub rule_type: StaticFileCacheRuleType,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct MatchedCacheRule {
+    #[serde(flatten)]
+    pub rule: CacheRuleDescription,
+    pub max_age_seconds: Option<u64>,
+    pub cache_control: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SkippedCacheRule {
+    #[serde(flatten)]
+    pub rule: CacheRuleDescription,
+    pub reason: &'static str,
+}
+
+/// Which cache rule applies to a resolved file, and why earlier rules did not.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct CacheRulesExplanation {
+    pub matched_rule: Option<MatchedCacheRule>,
+    pub skipped_rules: Vec<SkippedCacheRule>,
+}
+
+#[derive(Debug)]
+struct TryFilesRule {
+    request_matcher: RequestMatcher,
+    try_files: Vec<String>,
+}
+
+/// Expand a try_files entry for a request path, replacing `$uri` with the request path.
+pub fn expand_try_file(try_file: &str, request_path: &str) -> String {
+    try_file.replace("$uri", request_path)
+}
+
 #[derive(Debug)]
 pub struct StaticFileRulesService {
-    cache_rules: Vec<(RequestMatcher, Box<dyn CacheRule>)>,
+    cache_rules: Vec<CacheRuleEntry>,
+    content_type_rules: ContentTypeRules,
+    dot_path_rules: DotPathRules,
+    rewrite_rules: RewriteRules,
+    try_files_rules: Vec<TryFilesRule>,
 }
 
 impl StaticFileRulesService {
     fn new() -> anyhow::Result<Self> {
         let static_file_configuration = &crate::config::instance().static_file_configuration;
 
-        let mut cache_rules: Vec<(RequestMatcher, Box<dyn CacheRule>)> =
-            Vec::with_capacity(static_file_configuration.cache_rules.len());
+        let mut cache_rules = Vec::with_capacity(static_file_configuration.cache_rules.len());
 
         for cache_rule in &static_file_configuration.cache_rules {
-            let request_matcher = RequestMatcher::new(cache_rule)?;
-
-            match cache_rule.rule_type {
-                StaticFileCacheRuleType::FixedTime => {
-                    cache_rules.push((
-                        request_matcher,
-                        Box::new(FixedTimeCacheHeaderRule::new(cache_rule.duration)),
-                    ));
-                }
-                StaticFileCacheRuleType::ModTimePlusDelta => {
-                    cache_rules.push((
-                        request_matcher,
-                        Box::new(ModificationTimePlusDeltaCacheHeaderRule::new(
-                            cache_rule.duration,
-                        )),
-                    ));
-                }
-            }
+            let request_matcher =
+                RequestMatcher::new(&cache_rule.host_regex, &cache_rule.path_regex)?;
+
+            cache_rules.push(CacheRuleEntry {
+                request_matcher,
+                rule_type: cache_rule.rule_type,
+                rule: build_cache_rule(cache_rule)?,
+            });
         }
 
         debug!("cache_rules = {:?}", cache_rules,);
 
-        Ok(Self { cache_rules })
+        let content_type_rules = ContentTypeRules::new(static_file_configuration)
+            .context("StaticFileRulesService::new: error in content type configuration")?;
+
+        let dot_path_rules = DotPathRules::new(&static_file_configuration.dot_paths)
+            .context("StaticFileRulesService::new: error in dot_paths")?;
+
+        let rewrite_rules = RewriteRules::new(&static_file_configuration.rewrite_rules)
+            .context("StaticFileRulesService::new: error in rewrite_rules")?;
+
+        let mut try_files_rules =
+            Vec::with_capacity(static_file_configuration.try_files_rules.len());
+
+        for try_files_rule in &static_file_configuration.try_files_rules {
+            let request_matcher =
+                RequestMatcher::new(&try_files_rule.host_regex, &try_files_rule.path_regex)?;
+
+            if let Some(try_file) = try_files_rule
+                .try_files
+                .iter()
+                .find(|try_file| !try_file.starts_with("$uri") && !try_file.starts_with('/'))
+            {
+                anyhow::bail!(
+                    "StaticFileRulesService::new: try_files entry must start with '$uri' or '/' try_file = {:?}",
+                    try_file
+                );
+            }
+
+            try_files_rules.push(TryFilesRule {
+                request_matcher,
+                try_files: try_files_rule.try_files.clone(),
+            });
+        }
+
+        debug!("try_files_rules = {:?}", try_files_rules);
+
+        Ok(Self {
+            cache_rules,
+            content_type_rules,
+            dot_path_rules,
+            rewrite_rules,
+            try_files_rules,
+        })
     }
 
     pub fn build_cache_header(
         &self,
         host_option: Option<&str>,
-        resolved_file: &hyper_staticfile::ResolvedFile,
-    ) -> Option<Duration> {
-        let resolved_path_option = resolved_file.path.to_str();
-
+        resolved_file: &ResolvedFile,
+    ) -> Option<HeaderValue> {
         let request_match_data = RequestMatchData {
             host_option,
-            resolved_path_option,
+            path_option: resolved_file.path.to_str(),
         };
 
         self.cache_rules
             .iter()
-            .find(|(matcher, _)| matcher.matches(&request_match_data))
-            .and_then(|(_, rule)| rule.build_cache_header(resolved_file))
+            .find(|entry| entry.request_matcher.matches(&request_match_data))
+            .and_then(|entry| entry.rule.build_cache_header(resolved_file))
+    }
+
+    /// Evaluate cache rules in order as build_cache_header does, recording skipped rules.
+    pub fn explain_cache_rules(
+        &self,
+        host_option: Option<&str>,
+        resolved_file: &ResolvedFile,
+    ) -> CacheRulesExplanation {
+        let request_match_data = RequestMatchData {
+            host_option,
+            path_option: resolved_file.path.to_str(),
+        };
+
+        let mut skipped_rules = Vec::new();
+
+        for (index, entry) in self.cache_rules.iter().enumerate() {
+            match entry.request_matcher.mismatch_reason(&request_match_data) {
+                Some(reason) => skipped_rules.push(SkippedCacheRule {
+                    rule: entry.description(index),
+                    reason,
+                }),
+                None => {
+                    return CacheRulesExplanation {
+                        matched_rule: Some(MatchedCacheRule {
+                            rule: entry.description(index),
+                            max_age_seconds: entry
+                                .rule
+                                .max_age(resolved_file)
+                                .map(|max_age| max_age.as_secs()),
+                            cache_control: entry
+                                .rule
+                                .build_cache_header(resolved_file)
+                                .and_then(|value| value.to_str().ok().map(str::to_owned)),
+                        }),
+                        skipped_rules,
+                    };
+                }
+            }
+        }
+
+        CacheRulesExplanation {
+            matched_rule: None,
+            skipped_rules,
+        }
+    }
+
+    pub fn build_cache_headers(
+        &self,
+        original_request: &HttpRequest,
+        resolve_result: &ResolveResult,
+    ) -> Option<HeaderValue> {
+        let host_option = request_host(original_request);
+
+        match resolve_result {
+            ResolveResult::Found(resolved_file) => {
+                self.build_cache_header(host_option, resolved_file)
+            }
+            _ => None,
+        }
+    }
+
+    /// Apply content type overrides and the default charset to a found file.
+    pub fn apply_content_type(
+        &self,
+        original_request: &HttpRequest,
+        resolve_result: &mut ResolveResult,
+    ) {
+        if let ResolveResult::Found(resolved_file) = resolve_result {
+            self.content_type_rules
+                .apply(request_host(original_request), resolved_file);
+        }
+    }
+
+    /// Returns true if the URL path is a blocked dot path or matches a deny regex.
+    pub fn blocks_path(&self, path: &str) -> bool {
+        self.dot_path_rules.blocks_path(path)
+    }
+
+    /// Apply rewrite and redirect rules to the request host and path.
+    pub fn rewrite(&self, request: &HttpRequest) -> RewriteResult {
+        let uri = request.hyper_request.uri();
+
+        self.rewrite_path(request_host(request), uri.path(), uri.query())
+    }
+
+    pub fn rewrite_path(
+        &self,
+        host_option: Option<&str>,
+        path: &str,
+        query_option: Option<&str>,
+    ) -> RewriteResult {
+        self.rewrite_rules.apply(host_option, path, query_option)
+    }
+
+    /// Returns the try_files list of the first rule matching the host and request path.
+    pub fn try_files(&self, host_option: Option<&str>, request_path: &str) -> Option<&[String]> {
+        let request_match_data = RequestMatchData {
+            host_option,
+            path_option: Some(request_path),
+        };
+
+        self.try_files_rules
+            .iter()
+            .find(|rule| rule.request_matcher.matches(&request_match_data))
+            .map(|rule| rule.try_files.as_slice())
     }
 }
 
@@ -203,3 +475,76 @@ pub fn create_rules_service_instance() -> anyhow::Result<()> {
 pub fn rules_service_instance() -> &'static StaticFileRulesService {
     RULES_SERVICE_INSTANCE.get().unwrap()
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cache_control_directives() {
+        let directives =
+            |private, immutable, stale_while_revalidate, stale_if_error| CacheControlDirectives {
+                private,
+                immutable,
+                stale_while_revalidate,
+                stale_if_error,
+            };
+
+        assert_eq!(
+            directives(false, false, None, None).build_header_value(Duration::from_secs(60)),
+            "public, max-age=60"
+        );
+        assert_eq!(
+            directives(true, false, None, None).build_header_value(Duration::from_secs(60)),
+            "private, max-age=60"
+        );
+        assert_eq!(
+            directives(false, true, None, None).build_header_value(Duration::from_secs(31536000)),
+            "public, max-age=31536000, immutable"
+        );
+        assert_eq!(
+            directives(
+                false,
+                false,
+                Some(Duration::from_secs(30)),
+                Some(Duration::from_secs(86400))
+            )
+            .build_header_value(Duration::from_secs(60)),
+            "public, max-age=60, stale-while-revalidate=30, stale-if-error=86400"
+        );
+    }
+
+    #[test]
+    fn test_cache_rule_requires_duration() {
+        let cache_rule = |rule_type, duration| crate::config::StaticFileCacheRule {
+            host_regex: None,
+            path_regex: None,
+            rule_type,
+            duration,
+            stale_while_revalidate: None,
+            stale_if_error: None,
+        };
+
+        assert!(build_cache_rule(&cache_rule(StaticFileCacheRuleType::FixedTime, None)).is_err());
+        assert!(
+            build_cache_rule(&cache_rule(StaticFileCacheRuleType::ModTimePlusDelta, None)).is_err()
+        );
+        assert!(build_cache_rule(&cache_rule(StaticFileCacheRuleType::NoStore, None)).is_ok());
+        assert!(build_cache_rule(&cache_rule(
+            StaticFileCacheRuleType::Immutable,
+            Some(Duration::from_secs(60))
+        ))
+        .is_ok());
+    }
+
+    #[test]
+    fn test_expand_try_file() {
+        assert_eq!(expand_try_file("$uri", "/about"), "/about");
+        assert_eq!(expand_try_file("$uri.html", "/about"), "/about.html");
+        assert_eq!(
+            expand_try_file("$uri/index.html", "/about"),
+            "/about/index.html"
+        );
+        assert_eq!(expand_try_file("/index.html", "/app/route"), "/index.html");
+    }
+}
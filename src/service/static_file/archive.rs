@@ -0,0 +1,446 @@
+use ahash::AHashMap;
+
+use bytes::Bytes;
+
+use hyper_staticfile::vfs::FileWithMetadata;
+
+use tokio::time::Duration;
+
+use tracing::{debug, info, warn};
+
+use std::{
+    io::{Cursor, Read},
+    path::{Component, Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Detect an archive root by file name, or None for a directory root.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let file_name = path.file_name()?.to_string_lossy().to_ascii_lowercase();
+
+        if file_name.ends_with(".tar") {
+            Some(Self::Tar)
+        } else if file_name.ends_with(".tar.gz") || file_name.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else if file_name.ends_with(".zip") {
+            Some(Self::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct ArchiveVersion {
+    modified: Option<SystemTime>,
+    size: u64,
+}
+
+impl ArchiveVersion {
+    fn read(path: &Path) -> std::io::Result<Self> {
+        let metadata = std::fs::metadata(path)?;
+
+        Ok(Self {
+            modified: metadata.modified().ok(),
+            size: metadata.len(),
+        })
+    }
+}
+
+#[derive(Debug)]
+struct ArchiveEntry {
+    data: Bytes,
+    modified: Option<SystemTime>,
+    is_dir: bool,
+}
+
+impl ArchiveEntry {
+    fn directory() -> Self {
+        Self {
+            data: Bytes::new(),
+            modified: None,
+            is_dir: true,
+        }
+    }
+}
+
+/// Returns the relative path of an archive entry, or None if it escapes the archive root.
+fn normalize_entry_path(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::CurDir | Component::RootDir => {}
+            Component::ParentDir | Component::Prefix(_) => return None,
+        }
+    }
+
+    Some(normalized)
+}
+
+/// In-memory index of every file in an archive, keyed by relative path.
+pub struct ArchiveIndex {
+    version: ArchiveVersion,
+    entries: AHashMap<PathBuf, ArchiveEntry>,
+}
+
+impl ArchiveIndex {
+    fn new(version: ArchiveVersion) -> Self {
+        let mut entries = AHashMap::new();
+        entries.insert(PathBuf::new(), ArchiveEntry::directory());
+
+        Self { version, entries }
+    }
+
+    fn add_directory(&mut self, path: &Path) {
+        let Some(path) = normalize_entry_path(path) else {
+            return;
+        };
+
+        let mut directory = PathBuf::new();
+        for component in path.components() {
+            directory.push(component);
+            self.entries
+                .entry(directory.clone())
+                .or_insert_with(ArchiveEntry::directory);
+        }
+    }
+
+    fn add_file(&mut self, path: &Path, data: Vec<u8>, modified: Option<SystemTime>) {
+        let Some(path) = normalize_entry_path(path) else {
+            warn!("skipping archive entry outside root path = {:?}", path);
+            return;
+        };
+
+        if let Some(parent) = path.parent() {
+            self.add_directory(parent);
+        }
+
+        self.entries.insert(
+            path,
+            ArchiveEntry {
+                data: Bytes::from(data),
+                modified,
+                is_dir: false,
+            },
+        );
+    }
+
+    fn read_tar(&mut self, reader: impl Read) -> std::io::Result<()> {
+        let mut archive = tar::Archive::new(reader);
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+
+            let path = entry.path()?.into_owned();
+
+            let entry_type = entry.header().entry_type();
+
+            if entry_type.is_dir() {
+                self.add_directory(&path);
+            } else if entry_type.is_file() {
+                let modified = entry
+                    .header()
+                    .mtime()
+                    .ok()
+                    .map(|mtime| UNIX_EPOCH + Duration::from_secs(mtime));
+
+                let mut data = Vec::with_capacity(entry.size() as usize);
+                entry.read_to_end(&mut data)?;
+
+                self.add_file(&path, data, modified);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_zip(&mut self, file: std::fs::File) -> std::io::Result<()> {
+        let mut archive = zip::ZipArchive::new(file).map_err(std::io::Error::other)?;
+
+        for i in 0..archive.len() {
+            let mut zip_file = archive.by_index(i).map_err(std::io::Error::other)?;
+
+            let Some(path) = zip_file.enclosed_name() else {
+                warn!(
+                    "skipping archive entry outside root name = {:?}",
+                    zip_file.name()
+                );
+                continue;
+            };
+
+            if zip_file.is_dir() {
+                self.add_directory(&path);
+            } else if zip_file.is_file() {
+                // zip timestamps have no time zone, treat them as UTC.
+                let modified = zip_file.last_modified().and_then(|date_time| {
+                    chrono::NaiveDate::from_ymd_opt(
+                        date_time.year().into(),
+                        date_time.month().into(),
+                        date_time.day().into(),
+                    )?
+                    .and_hms_opt(
+                        date_time.hour().into(),
+                        date_time.minute().into(),
+                        date_time.second().into(),
+                    )
+                    .map(|naive_date_time| SystemTime::from(naive_date_time.and_utc()))
+                });
+
+                let mut data = Vec::with_capacity(zip_file.size() as usize);
+                zip_file.read_to_end(&mut data)?;
+
+                self.add_file(&path, data, modified);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn open(&self, path: &Path) -> std::io::Result<FileWithMetadata<Cursor<Bytes>>> {
+        self.entries
+            .get(path)
+            .map(|entry| FileWithMetadata {
+                handle: Cursor::new(entry.data.clone()),
+                size: entry.data.len() as u64,
+                modified: entry.modified,
+                is_dir: entry.is_dir,
+            })
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound))
+    }
+
+    /// Contents and modification time of a file in the archive, relative to the archive root.
+    pub fn file(&self, path: &Path) -> Option<(Bytes, Option<SystemTime>)> {
+        self.entries
+            .get(path)
+            .filter(|entry| !entry.is_dir)
+            .map(|entry| (entry.data.clone(), entry.modified))
+    }
+
+    /// Every file in the archive as (relative path, contents, modified).
+    pub fn files(&self) -> Vec<(PathBuf, Bytes, Option<SystemTime>)> {
+        self.entries
+            .iter()
+            .filter(|(_, entry)| !entry.is_dir)
+            .map(|(path, entry)| (path.clone(), entry.data.clone(), entry.modified))
+            .collect()
+    }
+
+    fn read(path: &Path, format: ArchiveFormat) -> std::io::Result<Self> {
+        let version = ArchiveVersion::read(path)?;
+
+        let mut index = Self::new(version);
+
+        let file = std::fs::File::open(path)?;
+
+        match format {
+            ArchiveFormat::Tar => index.read_tar(std::io::BufReader::new(file))?,
+            ArchiveFormat::TarGz => {
+                index.read_tar(flate2::read::GzDecoder::new(std::io::BufReader::new(file)))?
+            }
+            ArchiveFormat::Zip => index.read_zip(file)?,
+        }
+
+        // the archive changed while it was read, it will be read again on the next reload.
+        if ArchiveVersion::read(path)? != version {
+            return Err(std::io::Error::other("archive changed while reading"));
+        }
+
+        Ok(index)
+    }
+}
+
+/// A static root served from a `.tar`, `.tar.gz` or `.zip` archive.
+///
+/// The archive is indexed into memory, and replacing the archive file
+/// swaps the whole index at once on the next reload check.
+pub struct ArchiveFs {
+    path: PathBuf,
+    format: ArchiveFormat,
+    index: RwLock<Arc<ArchiveIndex>>,
+}
+
+impl std::fmt::Debug for ArchiveFs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ArchiveFs")
+            .field("path", &self.path)
+            .field("format", &self.format)
+            .finish()
+    }
+}
+
+impl ArchiveFs {
+    pub fn new(path: &Path, format: ArchiveFormat) -> std::io::Result<Self> {
+        let index = ArchiveIndex::read(path, format)?;
+
+        info!(
+            "indexed archive path = {:?} entries = {}",
+            path,
+            index.entries.len()
+        );
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            format,
+            index: RwLock::new(Arc::new(index)),
+        })
+    }
+
+    /// The current version of the archive index. Lookups within one request
+    /// should share a snapshot so a concurrent reload cannot mix versions.
+    pub fn snapshot(&self) -> Arc<ArchiveIndex> {
+        Arc::clone(&self.index.read().unwrap())
+    }
+
+    pub(super) async fn reload_if_changed(&self) -> std::io::Result<()> {
+        let version = ArchiveVersion::read(&self.path)?;
+
+        if version == self.snapshot().version {
+            return Ok(());
+        }
+
+        let path = self.path.clone();
+        let format = self.format;
+
+        let index = tokio::task::spawn_blocking(move || ArchiveIndex::read(&path, format))
+            .await
+            .map_err(std::io::Error::other)??;
+
+        info!(
+            "reloaded archive path = {:?} entries = {}",
+            self.path,
+            index.entries.len()
+        );
+
+        *self.index.write().unwrap() = Arc::new(index);
+
+        Ok(())
+    }
+
+    /// Check the archive for changes every reload_interval.
+    pub fn start_reload_task(self: &Arc<Self>, reload_interval: Duration) {
+        let archive_fs = Arc::clone(self);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(reload_interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+            loop {
+                interval.tick().await;
+
+                debug!("checking archive path = {:?}", archive_fs.path);
+
+                if let Err(e) = archive_fs.reload_if_changed().await {
+                    warn!("archive reload error path = {:?}: {}", archive_fs.path, e);
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_archive_format_from_path() {
+        assert_eq!(
+            ArchiveFormat::from_path(Path::new("/srv/site.tar")),
+            Some(ArchiveFormat::Tar)
+        );
+        assert_eq!(
+            ArchiveFormat::from_path(Path::new("/srv/site.TAR.GZ")),
+            Some(ArchiveFormat::TarGz)
+        );
+        assert_eq!(
+            ArchiveFormat::from_path(Path::new("/srv/site.tgz")),
+            Some(ArchiveFormat::TarGz)
+        );
+        assert_eq!(
+            ArchiveFormat::from_path(Path::new("/srv/site.zip")),
+            Some(ArchiveFormat::Zip)
+        );
+        assert_eq!(ArchiveFormat::from_path(Path::new("/srv/www")), None);
+    }
+
+    #[test]
+    fn test_archive_index() {
+        let mut tar_builder = tar::Builder::new(Vec::new());
+
+        for (path, contents) in [("./index.html", "index"), ("docs/a.txt", "a")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mtime(1_700_000_000);
+            header.set_mode(0o644);
+            header.set_cksum();
+            tar_builder
+                .append_data(&mut header, path, contents.as_bytes())
+                .unwrap();
+        }
+
+        let tar_bytes = tar_builder.into_inner().unwrap();
+
+        let mut index = ArchiveIndex::new(ArchiveVersion {
+            modified: None,
+            size: 0,
+        });
+        index.read_tar(&tar_bytes[..]).unwrap();
+
+        assert!(index.entries[Path::new("")].is_dir);
+        assert!(index.entries[Path::new("docs")].is_dir);
+        assert_eq!(index.entries[Path::new("index.html")].data, "index");
+        assert_eq!(
+            index.entries[Path::new("docs/a.txt")].modified,
+            Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+        );
+
+        assert_eq!(normalize_entry_path(Path::new("../etc/passwd")), None);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_survives_reload() {
+        let tar_bytes = |contents: &str| {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+
+            let mut tar_builder = tar::Builder::new(Vec::new());
+            tar_builder
+                .append_data(&mut header, "index.html", contents.as_bytes())
+                .unwrap();
+            tar_builder.into_inner().unwrap()
+        };
+
+        let archive_path =
+            std::env::temp_dir().join(format!("rhs-archive-test-{}.tar", std::process::id()));
+
+        std::fs::write(&archive_path, tar_bytes("old")).unwrap();
+        let archive_fs = ArchiveFs::new(&archive_path, ArchiveFormat::Tar).unwrap();
+
+        let snapshot = archive_fs.snapshot();
+
+        std::fs::write(&archive_path, tar_bytes("new contents")).unwrap();
+        archive_fs.reload_if_changed().await.unwrap();
+
+        std::fs::remove_file(&archive_path).unwrap();
+
+        let index_html = Path::new("index.html");
+        assert_eq!(snapshot.file(index_html).unwrap().0, "old");
+        assert_eq!(snapshot.open(index_html).unwrap().size, 3);
+        assert_eq!(
+            archive_fs.snapshot().file(index_html).unwrap().0,
+            "new contents"
+        );
+    }
+}
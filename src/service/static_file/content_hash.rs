@@ -0,0 +1,277 @@
+use ahash::AHashMap;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use bytes::Bytes;
+
+use sha2::{Digest, Sha256, Sha384};
+
+use tokio::sync::{OnceCell, RwLock};
+
+use tracing::debug;
+
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+
+/// Digests are cached for up to this many paths, evicting the least recently used.
+const MAX_CACHED_DIGESTS: usize = 4096;
+
+#[derive(Debug)]
+pub struct ContentDigests {
+    pub sha256: [u8; 32],
+    pub sha384: [u8; 48],
+}
+
+impl ContentDigests {
+    fn compute_bytes(bytes: &[u8]) -> Self {
+        Self {
+            sha256: Sha256::digest(bytes).into(),
+            sha384: Sha384::digest(bytes).into(),
+        }
+    }
+
+    fn compute(file_path: &Path) -> std::io::Result<Self> {
+        let mut file = std::fs::File::open(file_path)?;
+
+        let mut sha256 = Sha256::new();
+        let mut sha384 = Sha384::new();
+
+        let mut buffer = vec![0u8; 64 * 1024];
+
+        loop {
+            let bytes_read = file.read(&mut buffer)?;
+            if bytes_read == 0 {
+                break;
+            }
+            sha256.update(&buffer[..bytes_read]);
+            sha384.update(&buffer[..bytes_read]);
+        }
+
+        Ok(Self {
+            sha256: sha256.finalize().into(),
+            sha384: sha384.finalize().into(),
+        })
+    }
+
+    /// Strong ETag derived from the SHA-256 content digest.
+    pub fn etag(&self) -> String {
+        format!("\"{}\"", STANDARD.encode(self.sha256))
+    }
+
+    pub fn sha256_hex(&self) -> String {
+        self.sha256.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Subresource Integrity value for the SHA-256 digest.
+    pub fn sha256_integrity(&self) -> String {
+        format!("sha256-{}", STANDARD.encode(self.sha256))
+    }
+
+    /// Subresource Integrity value for the SHA-384 digest.
+    pub fn sha384_integrity(&self) -> String {
+        format!("sha384-{}", STANDARD.encode(self.sha384))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct FileVersion {
+    modified: Option<SystemTime>,
+    size: u64,
+}
+
+/// Contents of a static file, on the file system or in memory.
+pub enum FileContents {
+    Path(PathBuf),
+    Bytes {
+        key: PathBuf,
+        bytes: Bytes,
+        modified: Option<SystemTime>,
+    },
+}
+
+struct CachedDigests {
+    file_version: FileVersion,
+    digests: Arc<ContentDigests>,
+    last_used: AtomicU64,
+}
+
+/// Cache of content digests keyed by file path and invalidated by mtime and size,
+/// so unchanged files are not rehashed on every request.
+pub struct ContentHashService {
+    path_to_digests: RwLock<AHashMap<PathBuf, CachedDigests>>,
+    use_counter: AtomicU64,
+}
+
+impl ContentHashService {
+    fn new() -> Self {
+        Self {
+            path_to_digests: RwLock::new(AHashMap::new()),
+            use_counter: AtomicU64::new(0),
+        }
+    }
+
+    fn next_use(&self) -> u64 {
+        self.use_counter.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn insert(
+        &self,
+        path_to_digests: &mut AHashMap<PathBuf, CachedDigests>,
+        file_path: PathBuf,
+        file_version: FileVersion,
+        digests: Arc<ContentDigests>,
+    ) {
+        if path_to_digests.len() >= MAX_CACHED_DIGESTS && !path_to_digests.contains_key(&file_path)
+        {
+            if let Some(least_recently_used) = path_to_digests
+                .iter()
+                .min_by_key(|(_, cached)| cached.last_used.load(Ordering::Relaxed))
+                .map(|(path, _)| path.clone())
+            {
+                path_to_digests.remove(&least_recently_used);
+            }
+        }
+
+        path_to_digests.insert(
+            file_path,
+            CachedDigests {
+                file_version,
+                digests,
+                last_used: AtomicU64::new(self.next_use()),
+            },
+        );
+    }
+
+    pub async fn content_digests(
+        &self,
+        file_contents: FileContents,
+    ) -> std::io::Result<Arc<ContentDigests>> {
+        let (file_path, file_version) = match &file_contents {
+            FileContents::Path(path) => {
+                let metadata = match tokio::fs::metadata(path).await {
+                    Ok(metadata) => metadata,
+                    Err(e) => {
+                        // drop digests of files that have disappeared.
+                        if e.kind() == std::io::ErrorKind::NotFound {
+                            self.path_to_digests.write().await.remove(path);
+                        }
+                        return Err(e);
+                    }
+                };
+                (
+                    path.clone(),
+                    FileVersion {
+                        modified: metadata.modified().ok(),
+                        size: metadata.len(),
+                    },
+                )
+            }
+            FileContents::Bytes {
+                key,
+                bytes,
+                modified,
+            } => (
+                key.clone(),
+                FileVersion {
+                    modified: *modified,
+                    size: bytes.len() as u64,
+                },
+            ),
+        };
+
+        if let Some(cached) = self.path_to_digests.read().await.get(&file_path) {
+            if cached.file_version == file_version {
+                cached.last_used.store(self.next_use(), Ordering::Relaxed);
+                return Ok(Arc::clone(&cached.digests));
+            }
+        }
+
+        let digests = Arc::new(
+            tokio::task::spawn_blocking(move || match file_contents {
+                FileContents::Path(path) => ContentDigests::compute(&path),
+                FileContents::Bytes { bytes, .. } => Ok(ContentDigests::compute_bytes(&bytes)),
+            })
+            .await
+            .map_err(std::io::Error::other)??,
+        );
+
+        debug!("computed content digests file_path = {:?}", file_path);
+
+        self.insert(
+            &mut *self.path_to_digests.write().await,
+            file_path,
+            file_version,
+            Arc::clone(&digests),
+        );
+
+        Ok(digests)
+    }
+
+    pub async fn instance() -> &'static Self {
+        static INSTANCE: OnceCell<ContentHashService> = OnceCell::const_new();
+
+        INSTANCE.get_or_init(|| async { Self::new() }).await
+    }
+}
+
+/// Returns true if an If-None-Match header value matches the strong ETag.
+pub fn if_none_match_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || candidate.strip_prefix("W/").unwrap_or(candidate) == etag
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_if_none_match_matches() {
+        assert!(if_none_match_matches("\"abc\"", "\"abc\""));
+        assert!(if_none_match_matches("\"x\", \"abc\"", "\"abc\""));
+        assert!(if_none_match_matches("W/\"abc\"", "\"abc\""));
+        assert!(if_none_match_matches("*", "\"abc\""));
+        assert!(!if_none_match_matches("\"abcd\"", "\"abc\""));
+    }
+
+    #[tokio::test]
+    async fn test_content_digests_cache_is_bounded() {
+        let content_hash_service = ContentHashService::new();
+
+        let bytes_contents = |index: usize| FileContents::Bytes {
+            key: PathBuf::from(format!("/file-{}", index)),
+            bytes: Bytes::from(index.to_string()),
+            modified: None,
+        };
+
+        for index in 0..MAX_CACHED_DIGESTS {
+            content_hash_service
+                .content_digests(bytes_contents(index))
+                .await
+                .unwrap();
+        }
+
+        // touch the oldest entry so the next oldest is evicted instead.
+        content_hash_service
+            .content_digests(bytes_contents(0))
+            .await
+            .unwrap();
+
+        content_hash_service
+            .content_digests(bytes_contents(MAX_CACHED_DIGESTS))
+            .await
+            .unwrap();
+
+        let path_to_digests = content_hash_service.path_to_digests.read().await;
+        assert_eq!(path_to_digests.len(), MAX_CACHED_DIGESTS);
+        assert!(path_to_digests.contains_key(Path::new("/file-0")));
+        assert!(!path_to_digests.contains_key(Path::new("/file-1")));
+    }
+}
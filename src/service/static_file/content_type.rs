@@ -0,0 +1,275 @@
+use ahash::AHashMap;
+
+use anyhow::Context;
+
+use hyper::http::HeaderValue;
+
+use hyper_staticfile::Encoding;
+
+use tracing::debug;
+
+use std::path::Path;
+
+use crate::service::request_matcher::{RequestMatchData, RequestMatcher};
+
+fn validate_content_type(content_type: &str) -> anyhow::Result<()> {
+    if !content_type.contains('/') {
+        anyhow::bail!("invalid content type {:?}", content_type);
+    }
+
+    HeaderValue::try_from(content_type)
+        .with_context(|| format!("invalid content type {:?}", content_type))?;
+
+    Ok(())
+}
+
+/// Path of the file a precompressed variant was built from, so `app.mjs.gz`
+/// gets the content type of `app.mjs`.
+fn uncompressed_path(path: &Path, encoding_option: Option<Encoding>) -> &Path {
+    let suffix = match encoding_option {
+        None => return path,
+        Some(Encoding::Gzip) => ".gz",
+        Some(Encoding::Br) => ".br",
+    };
+
+    path.to_str()
+        .and_then(|path| path.strip_suffix(suffix))
+        .map(Path::new)
+        .unwrap_or(path)
+}
+
+/// Returns true for content types that get the default charset appended.
+fn is_charset_content_type(content_type: &str) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+
+    essence.starts_with("text/") || essence == "application/javascript"
+}
+
+#[derive(Debug)]
+pub struct ContentTypeRules {
+    extension_to_content_type: AHashMap<String, String>,
+    extensionless_content_type: Option<String>,
+    path_rules: Vec<(RequestMatcher, String)>,
+    default_charset: Option<String>,
+}
+
+impl ContentTypeRules {
+    pub fn new(
+        static_file_configuration: &crate::config::StaticFileConfiguration,
+    ) -> anyhow::Result<Self> {
+        let mut extension_to_content_type =
+            AHashMap::with_capacity(static_file_configuration.mime_types.len());
+
+        for (extension, content_type) in &static_file_configuration.mime_types {
+            validate_content_type(content_type).context("ContentTypeRules::new: mime_types")?;
+
+            extension_to_content_type.insert(
+                extension.trim_start_matches('.').to_ascii_lowercase(),
+                content_type.clone(),
+            );
+        }
+
+        if let Some(content_type) = &static_file_configuration.extensionless_mime_type {
+            validate_content_type(content_type)
+                .context("ContentTypeRules::new: extensionless_mime_type")?;
+        }
+
+        let mut path_rules = Vec::with_capacity(static_file_configuration.content_type_rules.len());
+
+        for content_type_rule in &static_file_configuration.content_type_rules {
+            let request_matcher =
+                RequestMatcher::new(&content_type_rule.host_regex, &content_type_rule.path_regex)?;
+
+            validate_content_type(&content_type_rule.content_type)
+                .context("ContentTypeRules::new: content_type_rules")?;
+
+            path_rules.push((request_matcher, content_type_rule.content_type.clone()));
+        }
+
+        if let Some(default_charset) = &static_file_configuration.default_charset {
+            HeaderValue::try_from(default_charset.as_str())
+                .with_context(|| format!("invalid default_charset {:?}", default_charset))?;
+        }
+
+        let content_type_rules = Self {
+            extension_to_content_type,
+            extensionless_content_type: static_file_configuration.extensionless_mime_type.clone(),
+            path_rules,
+            default_charset: static_file_configuration.default_charset.clone(),
+        };
+
+        debug!("content_type_rules = {:?}", content_type_rules);
+
+        Ok(content_type_rules)
+    }
+
+    /// Content type for a resolved file path. Per-path rules take precedence over the
+    /// extension table, the extensionless override, and finally the guessed content type.
+    pub fn content_type(
+        &self,
+        host_option: Option<&str>,
+        path: &Path,
+        guessed_content_type: Option<&str>,
+    ) -> Option<String> {
+        let request_match_data = RequestMatchData {
+            host_option,
+            path_option: path.to_str(),
+        };
+
+        let content_type = self
+            .path_rules
+            .iter()
+            .find(|(matcher, _)| matcher.matches(&request_match_data))
+            .map(|(_, content_type)| content_type.as_str())
+            .or_else(|| match path.extension().and_then(|e| e.to_str()) {
+                Some(extension) => self
+                    .extension_to_content_type
+                    .get(&extension.to_ascii_lowercase())
+                    .map(String::as_str),
+                None => self.extensionless_content_type.as_deref(),
+            })
+            .or(guessed_content_type)?;
+
+        match &self.default_charset {
+            Some(default_charset)
+                if is_charset_content_type(content_type)
+                    && !content_type.to_ascii_lowercase().contains("charset=") =>
+            {
+                Some(format!("{}; charset={}", content_type, default_charset))
+            }
+            _ => Some(content_type.to_owned()),
+        }
+    }
+
+    pub fn apply(
+        &self,
+        host_option: Option<&str>,
+        resolved_file: &mut crate::service::static_file::vfs::ResolvedFile,
+    ) {
+        resolved_file.content_type = self.content_type(
+            host_option,
+            uncompressed_path(&resolved_file.path, resolved_file.encoding),
+            resolved_file.content_type.as_deref(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    fn static_file_configuration(toml: &str) -> crate::config::StaticFileConfiguration {
+        toml::from_str(&format!(
+            "root = '/tmp'\nprecompressed = {{ br = false, gz = false }}\ncache_rules = []\n{}",
+            toml
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_content_type() {
+        let mut configuration = static_file_configuration(
+            r#"
+            extensionless_mime_type = "text/plain"
+            default_charset = "utf-8"
+            content_type_rules = [
+                { path_regex = '^feeds/', content_type = "application/rss+xml" },
+            ]
+            "#,
+        );
+        configuration.mime_types = BTreeMap::from([
+            ("mjs".to_owned(), "application/javascript".to_owned()),
+            (
+                ".webmanifest".to_owned(),
+                "application/manifest+json".to_owned(),
+            ),
+        ]);
+
+        let rules = ContentTypeRules::new(&configuration).unwrap();
+
+        let content_type =
+            |path: &str, guessed: Option<&str>| rules.content_type(None, Path::new(path), guessed);
+
+        assert_eq!(
+            content_type("app.MJS", None).as_deref(),
+            Some("application/javascript; charset=utf-8")
+        );
+        assert_eq!(
+            content_type("site.webmanifest", Some("application/octet-stream")).as_deref(),
+            Some("application/manifest+json")
+        );
+        assert_eq!(
+            content_type("LICENSE", Some("application/octet-stream")).as_deref(),
+            Some("text/plain; charset=utf-8")
+        );
+        assert_eq!(
+            content_type("feeds/all", None).as_deref(),
+            Some("application/rss+xml")
+        );
+        assert_eq!(
+            content_type("index.html", Some("text/html")).as_deref(),
+            Some("text/html; charset=utf-8")
+        );
+        assert_eq!(
+            content_type("image.png", Some("image/png")).as_deref(),
+            Some("image/png")
+        );
+        assert_eq!(content_type("unknown.xyz", None), None);
+    }
+
+    #[test]
+    fn test_precompressed_content_type() {
+        let mut configuration = static_file_configuration(
+            r#"
+            content_type_rules = [
+                { path_regex = '^feeds/all$', content_type = "application/rss+xml" },
+            ]
+            "#,
+        );
+        configuration.mime_types =
+            BTreeMap::from([("mjs".to_owned(), "application/javascript".to_owned())]);
+
+        let rules = ContentTypeRules::new(&configuration).unwrap();
+
+        let content_type = |path: &str, encoding_option: Option<Encoding>| {
+            rules.content_type(
+                None,
+                uncompressed_path(Path::new(path), encoding_option),
+                Some("application/octet-stream"),
+            )
+        };
+
+        assert_eq!(
+            content_type("app.mjs.gz", Some(Encoding::Gzip)).as_deref(),
+            Some("application/javascript")
+        );
+        assert_eq!(
+            content_type("app.mjs.br", Some(Encoding::Br)).as_deref(),
+            Some("application/javascript")
+        );
+        assert_eq!(
+            content_type("feeds/all.gz", Some(Encoding::Gzip)).as_deref(),
+            Some("application/rss+xml")
+        );
+        // a file that is itself a .gz archive keeps its own extension.
+        assert_eq!(
+            content_type("app.mjs.gz", None).as_deref(),
+            Some("application/octet-stream")
+        );
+    }
+
+    #[test]
+    fn test_invalid_content_type() {
+        let mut configuration = static_file_configuration("");
+        configuration.mime_types = BTreeMap::from([("mjs".to_owned(), "javascript".to_owned())]);
+
+        assert!(ContentTypeRules::new(&configuration).is_err());
+    }
+}
@@ -0,0 +1,115 @@
+use anyhow::Context;
+
+use regex::RegexSet;
+
+use tracing::debug;
+
+/// Returns true if any segment of the path starts with '.'.
+fn is_dot_path(path: &str) -> bool {
+    path.starts_with('.') || path.contains("/.")
+}
+
+/// Dot paths are blocked unless allowed by prefix or regex.
+/// Deny regexes block any path, dot path or not.
+#[derive(Debug)]
+pub struct DotPathRules {
+    allow_prefixes: Vec<String>,
+    allow_regexes: RegexSet,
+    deny_regexes: RegexSet,
+}
+
+impl DotPathRules {
+    pub fn new(
+        dot_path_configuration: &crate::config::StaticFileDotPathConfiguration,
+    ) -> anyhow::Result<Self> {
+        if let Some(allow_prefix) = dot_path_configuration
+            .allow_prefixes
+            .iter()
+            .find(|allow_prefix| !allow_prefix.starts_with('/'))
+        {
+            anyhow::bail!(
+                "DotPathRules::new: allow_prefixes entry must start with '/' allow_prefix = {:?}",
+                allow_prefix
+            );
+        }
+
+        let allow_regexes = RegexSet::new(&dot_path_configuration.allow_regexes)
+            .context("DotPathRules::new: error parsing allow_regexes")?;
+
+        let deny_regexes = RegexSet::new(&dot_path_configuration.deny_regexes)
+            .context("DotPathRules::new: error parsing deny_regexes")?;
+
+        let dot_path_rules = Self {
+            allow_prefixes: dot_path_configuration.allow_prefixes.clone(),
+            allow_regexes,
+            deny_regexes,
+        };
+
+        debug!("dot_path_rules = {:?}", dot_path_rules);
+
+        Ok(dot_path_rules)
+    }
+
+    /// Returns true if the URL path must not be served.
+    /// Paths without a leading '/' are treated as relative to the root.
+    pub fn blocks_path(&self, path: &str) -> bool {
+        let path = if path.starts_with('/') {
+            std::borrow::Cow::Borrowed(path)
+        } else {
+            std::borrow::Cow::Owned(format!("/{}", path))
+        };
+
+        if self.deny_regexes.is_match(&path) {
+            return true;
+        }
+
+        if !is_dot_path(&path) {
+            return false;
+        }
+
+        let allowed = self
+            .allow_prefixes
+            .iter()
+            .any(|allow_prefix| path.starts_with(allow_prefix.as_str()))
+            || self.allow_regexes.is_match(&path);
+
+        !allowed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_blocks_path() {
+        let dot_path_rules = DotPathRules::new(&crate::config::StaticFileDotPathConfiguration {
+            allow_prefixes: vec!["/.well-known/".to_owned()],
+            allow_regexes: vec![r"^/\.htaccess-example$".to_owned()],
+            deny_regexes: vec![r"~$".to_owned(), r"\.(bak|swp)$".to_owned()],
+        })
+        .unwrap();
+
+        assert!(!dot_path_rules.blocks_path("/index.html"));
+        assert!(!dot_path_rules.blocks_path("about.html"));
+        assert!(!dot_path_rules.blocks_path("/.well-known/security.txt"));
+        assert!(!dot_path_rules.blocks_path(".well-known/acme-challenge/token"));
+        assert!(!dot_path_rules.blocks_path("/.htaccess-example"));
+
+        assert!(dot_path_rules.blocks_path("/.git/config"));
+        assert!(dot_path_rules.blocks_path("sub/.env"));
+        assert!(dot_path_rules.blocks_path("/.well-known-fake/x"));
+        assert!(dot_path_rules.blocks_path("/index.html~"));
+        assert!(dot_path_rules.blocks_path("/notes.bak"));
+        assert!(dot_path_rules.blocks_path("/.well-known/notes.swp"));
+    }
+
+    #[test]
+    fn test_default_blocks_dot_paths() {
+        let dot_path_rules =
+            DotPathRules::new(&crate::config::StaticFileDotPathConfiguration::default()).unwrap();
+
+        assert!(!dot_path_rules.blocks_path("/index.html"));
+        assert!(dot_path_rules.blocks_path("/.well-known/security.txt"));
+    }
+}
@@ -0,0 +1,477 @@
+use anyhow::Context;
+
+use hyper::http::{header, HeaderMap, Method};
+
+use hyper_staticfile::{vfs::TokioFileOpener, AcceptEncoding, Resolver};
+
+use tokio::{sync::OnceCell, time::Duration};
+
+use tracing::debug;
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    config::StaticFilePrecompressedConfiguration,
+    service::static_file::{
+        archive::{ArchiveFormat, ArchiveFs, ArchiveIndex},
+        content_hash::FileContents,
+        vfs::{ResolveResult, StaticFileOpener},
+    },
+};
+
+const DEFAULT_ARCHIVE_RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Where a mount's files are opened from. Archive openers are built per
+/// snapshot so a reload does not leave a mount holding an old index.
+enum MountSource {
+    Directory(Arc<StaticFileOpener>),
+    Archive(Arc<ArchiveFs>),
+}
+
+pub struct StaticFileMount {
+    url_prefix: String,
+    root: PathBuf,
+    source: MountSource,
+    allowed_encodings: AcceptEncoding,
+    autoindex: bool,
+}
+
+impl std::fmt::Debug for StaticFileMount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StaticFileMount")
+            .field("url_prefix", &self.url_prefix)
+            .field("root", &self.root)
+            .field("archive_fs_option", &self.archive_fs())
+            .field("allowed_encodings", &self.allowed_encodings)
+            .field("autoindex", &self.autoindex)
+            .finish()
+    }
+}
+
+impl StaticFileMount {
+    fn new(
+        url_prefix: &str,
+        root: &str,
+        precompressed: &StaticFilePrecompressedConfiguration,
+        autoindex: bool,
+    ) -> anyhow::Result<Self> {
+        if !url_prefix.starts_with('/') {
+            anyhow::bail!(
+                "StaticFileMount::new: url_prefix must start with '/' url_prefix = {:?}",
+                url_prefix
+            );
+        }
+
+        let root = PathBuf::from(root);
+
+        let source = match ArchiveFormat::from_path(&root) {
+            None => MountSource::Directory(Arc::new(StaticFileOpener::Directory(
+                TokioFileOpener::new(&root),
+            ))),
+            Some(archive_format) => {
+                if autoindex {
+                    anyhow::bail!(
+                        "StaticFileMount::new: autoindex is not supported for archive root = {:?}",
+                        root
+                    );
+                }
+
+                let archive_fs = ArchiveFs::new(&root, archive_format).with_context(|| {
+                    format!("StaticFileMount::new: error reading archive {:?}", root)
+                })?;
+
+                MountSource::Archive(Arc::new(archive_fs))
+            }
+        };
+
+        let mut allowed_encodings = AcceptEncoding::none();
+        allowed_encodings.gzip = precompressed.gz;
+        allowed_encodings.br = precompressed.br;
+
+        Ok(Self {
+            url_prefix: url_prefix.trim_end_matches('/').to_owned(),
+            root,
+            source,
+            allowed_encodings,
+            autoindex,
+        })
+    }
+
+    pub fn url_prefix(&self) -> &str {
+        &self.url_prefix
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn autoindex(&self) -> bool {
+        self.autoindex
+    }
+
+    pub fn archive_fs(&self) -> Option<&ArchiveFs> {
+        match &self.source {
+            MountSource::Directory(_) => None,
+            MountSource::Archive(archive_fs) => Some(archive_fs),
+        }
+    }
+
+    /// Returns the request path relative to this mount, or None if the
+    /// mount does not contain the request path.
+    ///
+    /// A request for the bare prefix returns an empty path so the resolver
+    /// redirects it to the directory with a trailing slash.
+    fn strip_prefix<'a>(&self, request_path: &'a str) -> Option<&'a str> {
+        let remainder = request_path.strip_prefix(&self.url_prefix)?;
+
+        if remainder.is_empty() || remainder.starts_with('/') {
+            Some(remainder)
+        } else {
+            None
+        }
+    }
+
+    /// Pin the current archive contents of this mount, so a file resolved
+    /// through the snapshot is read and hashed from the same archive version.
+    pub fn snapshot(&self) -> MountSnapshot<'_> {
+        let (opener, archive_index_option) = match &self.source {
+            MountSource::Directory(opener) => (Arc::clone(opener), None),
+            MountSource::Archive(archive_fs) => {
+                let archive_index = archive_fs.snapshot();
+                (
+                    Arc::new(StaticFileOpener::Archive(Arc::clone(&archive_index))),
+                    Some(archive_index),
+                )
+            }
+        };
+
+        let resolver = Resolver {
+            opener,
+            allowed_encodings: self.allowed_encodings,
+            rewrite: None,
+        };
+
+        MountSnapshot {
+            mount: self,
+            resolver,
+            archive_index_option,
+        }
+    }
+
+    fn url_space_path(&self, mount_relative_path: &Path) -> PathBuf {
+        Path::new(self.url_prefix.trim_start_matches('/')).join(mount_relative_path)
+    }
+}
+
+/// A mount with its archive contents pinned for the duration of a request.
+pub struct MountSnapshot<'a> {
+    mount: &'a StaticFileMount,
+    resolver: Resolver<StaticFileOpener>,
+    archive_index_option: Option<Arc<ArchiveIndex>>,
+}
+
+impl MountSnapshot<'_> {
+    /// Resolve a path relative to the mount.
+    ///
+    /// Paths in the result are reported in the URL space, so resolved files
+    /// and directory redirects include the mount prefix.
+    pub async fn resolve(
+        &self,
+        method: &Method,
+        headers: &HeaderMap,
+        mount_path: &str,
+    ) -> std::io::Result<ResolveResult> {
+        if !matches!(*method, Method::GET | Method::HEAD) {
+            return Ok(ResolveResult::MethodNotMatched);
+        }
+
+        let accept_encoding = self.resolver.allowed_encodings
+            & headers
+                .get(header::ACCEPT_ENCODING)
+                .map(AcceptEncoding::from_header_value)
+                .unwrap_or(AcceptEncoding::none());
+
+        let resolve_result = self
+            .resolver
+            .resolve_path(mount_path, accept_encoding)
+            .await?;
+
+        Ok(match resolve_result {
+            ResolveResult::Found(mut resolved_file) => {
+                resolved_file.path = self.mount.url_space_path(&resolved_file.path);
+                ResolveResult::Found(resolved_file)
+            }
+            ResolveResult::IsDirectory { redirect_to } => ResolveResult::IsDirectory {
+                redirect_to: format!("{}{}", self.mount.url_prefix, redirect_to),
+            },
+            other => other,
+        })
+    }
+
+    /// Find the sanitized directory for a directory request, relative to the mount's root.
+    pub async fn resolve_directory(&self, mount_path: &str) -> std::io::Result<Option<PathBuf>> {
+        let resolve_result = self
+            .resolver
+            .resolve_path(mount_path.trim_end_matches('/'), AcceptEncoding::none())
+            .await?;
+
+        Ok(match resolve_result {
+            ResolveResult::IsDirectory { redirect_to } => {
+                Some(PathBuf::from(redirect_to.trim_start_matches('/')))
+            }
+            _ => None,
+        })
+    }
+
+    /// Map a resolved URL space path to its contents on the file system or in the archive snapshot.
+    pub fn file_contents(&self, resolved_path: &Path) -> Option<FileContents> {
+        let url_path = format!("/{}", resolved_path.to_string_lossy());
+
+        let mount_path = self.mount.strip_prefix(&url_path)?;
+
+        let relative_path = Path::new(mount_path.trim_start_matches('/'));
+
+        match &self.archive_index_option {
+            None => Some(FileContents::Path(self.mount.root.join(relative_path))),
+            Some(archive_index) => {
+                archive_index
+                    .file(relative_path)
+                    .map(|(bytes, modified)| FileContents::Bytes {
+                        key: self.mount.root.join(relative_path),
+                        bytes,
+                        modified,
+                    })
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct StaticFileMounts {
+    // sorted by descending url_prefix length so the longest prefix matches first.
+    mounts: Vec<StaticFileMount>,
+}
+
+impl StaticFileMounts {
+    fn new() -> anyhow::Result<Self> {
+        let static_file_configuration = &crate::config::instance().static_file_configuration;
+
+        let mut mounts = Vec::with_capacity(static_file_configuration.mounts.len() + 1);
+
+        for mount_configuration in &static_file_configuration.mounts {
+            let mount = StaticFileMount::new(
+                &mount_configuration.url_prefix,
+                &mount_configuration.root,
+                &mount_configuration.precompressed,
+                mount_configuration.autoindex,
+            )
+            .context("StaticFileMounts::new: error creating mount")?;
+
+            if mount.url_prefix.is_empty() {
+                anyhow::bail!(
+                    "StaticFileMounts::new: url_prefix = {:?} is the default root, set static_file_configuration.root instead",
+                    mount_configuration.url_prefix,
+                );
+            }
+
+            if mounts
+                .iter()
+                .any(|m: &StaticFileMount| m.url_prefix == mount.url_prefix)
+            {
+                anyhow::bail!(
+                    "StaticFileMounts::new: duplicate url_prefix = {:?}",
+                    mount_configuration.url_prefix,
+                );
+            }
+
+            mounts.push(mount);
+        }
+
+        mounts.sort_by_key(|m| std::cmp::Reverse(m.url_prefix.len()));
+
+        // the default root is resolved last.
+        mounts.push(StaticFileMount::new(
+            "/",
+            &static_file_configuration.root,
+            &static_file_configuration.precompressed,
+            static_file_configuration.autoindex,
+        )?);
+
+        debug!("mounts = {:?}", mounts);
+
+        let archive_reload_interval = static_file_configuration
+            .archive_reload_interval
+            .unwrap_or(DEFAULT_ARCHIVE_RELOAD_INTERVAL);
+
+        for mount in &mounts {
+            if let MountSource::Archive(archive_fs) = &mount.source {
+                archive_fs.start_reload_task(archive_reload_interval);
+            }
+        }
+
+        Ok(Self { mounts })
+    }
+
+    /// Find the mount with the longest matching prefix and the request path relative to it.
+    pub fn find<'a>(&self, request_path: &'a str) -> (&StaticFileMount, &'a str) {
+        self.mounts
+            .iter()
+            .find_map(|mount| {
+                mount
+                    .strip_prefix(request_path)
+                    .map(|mount_path| (mount, mount_path))
+            })
+            .unwrap_or_else(|| (self.default_mount(), request_path))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &StaticFileMount> {
+        self.mounts.iter()
+    }
+
+    fn default_mount(&self) -> &StaticFileMount {
+        self.mounts.last().unwrap()
+    }
+}
+
+static MOUNTS_INSTANCE: OnceCell<StaticFileMounts> = OnceCell::const_new();
+
+pub fn create_mounts_instance() -> anyhow::Result<()> {
+    let static_file_mounts = StaticFileMounts::new()?;
+
+    MOUNTS_INSTANCE
+        .set(static_file_mounts)
+        .context("MOUNTS_INSTANCE.set error")?;
+
+    Ok(())
+}
+
+pub fn mounts_instance() -> &'static StaticFileMounts {
+    MOUNTS_INSTANCE.get().unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn test_mount(url_prefix: &str) -> StaticFileMount {
+        StaticFileMount::new(
+            url_prefix,
+            "/tmp",
+            &StaticFilePrecompressedConfiguration {
+                br: false,
+                gz: false,
+            },
+            false,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_strip_prefix() {
+        let mount = test_mount("/downloads/");
+
+        assert_eq!(mount.strip_prefix("/downloads"), Some(""));
+        assert_eq!(mount.strip_prefix("/downloads/"), Some("/"));
+        assert_eq!(mount.strip_prefix("/downloads/a/b.txt"), Some("/a/b.txt"));
+        assert_eq!(mount.strip_prefix("/downloadsx/a.txt"), None);
+        assert_eq!(mount.strip_prefix("/docs/a.txt"), None);
+
+        let root_mount = test_mount("/");
+
+        assert_eq!(root_mount.strip_prefix("/"), Some("/"));
+        assert_eq!(root_mount.strip_prefix("/a.txt"), Some("/a.txt"));
+    }
+
+    #[test]
+    fn test_longest_prefix_wins() {
+        let mounts = StaticFileMounts {
+            mounts: vec![
+                test_mount("/docs/api"),
+                test_mount("/docs"),
+                test_mount("/"),
+            ],
+        };
+
+        let (mount, mount_path) = mounts.find("/docs/api/index.html");
+        assert_eq!(mount.url_prefix(), "/docs/api");
+        assert_eq!(mount_path, "/index.html");
+
+        let (mount, mount_path) = mounts.find("/docs/guide.html");
+        assert_eq!(mount.url_prefix(), "/docs");
+        assert_eq!(mount_path, "/guide.html");
+
+        let (mount, mount_path) = mounts.find("/other.html");
+        assert_eq!(mount.url_prefix(), "");
+        assert_eq!(mount_path, "/other.html");
+
+        let (mount, _) = mounts.find("/docs/api/index.html");
+        assert!(matches!(
+            mount.snapshot().file_contents(Path::new("docs/api/index.html")),
+            Some(FileContents::Path(path)) if path == Path::new("/tmp/index.html")
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_archive_mount_releases_old_index() {
+        let tar_bytes = |contents: &str| {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+
+            let mut tar_builder = tar::Builder::new(Vec::new());
+            tar_builder
+                .append_data(&mut header, "index.html", contents.as_bytes())
+                .unwrap();
+            tar_builder.into_inner().unwrap()
+        };
+
+        let archive_path =
+            std::env::temp_dir().join(format!("rhs-mount-test-{}.tar", std::process::id()));
+
+        std::fs::write(&archive_path, tar_bytes("old")).unwrap();
+        let mount = StaticFileMount::new(
+            "/",
+            archive_path.to_str().unwrap(),
+            &StaticFilePrecompressedConfiguration {
+                br: false,
+                gz: false,
+            },
+            false,
+        )
+        .unwrap();
+
+        let old_index = Arc::downgrade(&mount.archive_fs().unwrap().snapshot());
+        assert!(mount
+            .snapshot()
+            .resolve_directory("/")
+            .await
+            .unwrap()
+            .is_some());
+
+        std::fs::write(&archive_path, tar_bytes("new contents")).unwrap();
+        mount
+            .archive_fs()
+            .unwrap()
+            .reload_if_changed()
+            .await
+            .unwrap();
+
+        std::fs::remove_file(&archive_path).unwrap();
+
+        assert_eq!(old_index.strong_count(), 0);
+
+        let mount_snapshot = mount.snapshot();
+        let ResolveResult::Found(resolved_file) = mount_snapshot
+            .resolve(&Method::GET, &HeaderMap::new(), "/index.html")
+            .await
+            .unwrap()
+        else {
+            panic!("index.html not found");
+        };
+        assert_eq!(resolved_file.size, 12);
+    }
+}
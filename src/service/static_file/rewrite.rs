@@ -0,0 +1,332 @@
+use anyhow::Context;
+
+use hyper::http::StatusCode;
+
+use regex::Regex;
+
+use tracing::{debug, warn};
+
+use crate::{
+    config::StaticFileRewriteRuleType,
+    service::request_matcher::{RequestMatchData, RequestMatcher},
+};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RewriteResult {
+    Unchanged,
+    Rewrite(String),
+    Redirect {
+        status_code: StatusCode,
+        location: String,
+    },
+}
+
+#[derive(Debug)]
+struct RewriteRule {
+    host_regex: Option<String>,
+    host_matcher: RequestMatcher,
+    path_regex: Regex,
+    rule_type: StaticFileRewriteRuleType,
+    target: String,
+    status_code: StatusCode,
+}
+
+impl RewriteRule {
+    fn new(rewrite_rule: &crate::config::StaticFileRewriteRule) -> anyhow::Result<Self> {
+        let host_matcher = RequestMatcher::new(&rewrite_rule.host_regex, &None)?;
+
+        let path_regex = Regex::new(&rewrite_rule.path_regex)
+            .context("RewriteRule::new: error parsing path_regex")?;
+
+        let status_code = match rewrite_rule.rule_type {
+            StaticFileRewriteRuleType::Redirect => {
+                let status_code = rewrite_rule.status_code.unwrap_or(301);
+                if !matches!(status_code, 301 | 302 | 307 | 308) {
+                    anyhow::bail!(
+                        "RewriteRule::new: redirect status_code must be 301, 302, 307 or 308 status_code = {}",
+                        status_code
+                    );
+                }
+                StatusCode::from_u16(status_code)?
+            }
+            StaticFileRewriteRuleType::Rewrite => {
+                if rewrite_rule.status_code.is_some() {
+                    anyhow::bail!("RewriteRule::new: status_code is only valid for REDIRECT rules");
+                }
+                if !rewrite_rule.target.starts_with('/') {
+                    anyhow::bail!(
+                        "RewriteRule::new: REWRITE target must start with '/' target = {:?}",
+                        rewrite_rule.target
+                    );
+                }
+                StatusCode::OK
+            }
+        };
+
+        Ok(Self {
+            host_regex: rewrite_rule.host_regex.clone(),
+            host_matcher,
+            path_regex,
+            rule_type: rewrite_rule.rule_type,
+            target: rewrite_rule.target.clone(),
+            status_code,
+        })
+    }
+
+    fn matches_host(&self, host_option: Option<&str>) -> bool {
+        self.host_matcher.matches(&RequestMatchData {
+            host_option,
+            path_option: None,
+        })
+    }
+
+    /// Expand the target template with capture groups from the path, if the path matches.
+    fn expand_target(&self, path: &str) -> Option<String> {
+        let captures = self.path_regex.captures(path)?;
+
+        let mut target = String::new();
+        captures.expand(&self.target, &mut target);
+
+        Some(target)
+    }
+}
+
+/// Returns the path of a target, or None if the target is an external URL.
+fn target_path(target: &str) -> Option<&str> {
+    if target.starts_with("//") || target.contains("://") {
+        return None;
+    }
+
+    Some(target.split('?').next().unwrap_or_default())
+}
+
+#[derive(Debug)]
+pub struct RewriteRules {
+    rules: Vec<RewriteRule>,
+}
+
+impl RewriteRules {
+    pub fn new(rewrite_rules: &[crate::config::StaticFileRewriteRule]) -> anyhow::Result<Self> {
+        let rules = rewrite_rules
+            .iter()
+            .map(RewriteRule::new)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let rewrite_rules = Self { rules };
+
+        rewrite_rules.detect_loops()?;
+
+        debug!("rewrite_rules = {:?}", rewrite_rules);
+
+        Ok(rewrite_rules)
+    }
+
+    fn max_iterations(&self) -> usize {
+        self.rules.len() + 1
+    }
+
+    /// Simulate the rules from each rule's target, with capture group references
+    /// replaced by a placeholder, and fail if a target leads back to a visited path.
+    fn detect_loops(&self) -> anyhow::Result<()> {
+        let capture_reference_regex = Regex::new(r"\$(\d+|\{[^}]*\}|[A-Za-z_][A-Za-z0-9_]*)")?;
+
+        for (index, origin_rule) in self.rules.iter().enumerate() {
+            let sample_target = capture_reference_regex.replace_all(&origin_rule.target, "x");
+
+            let Some(sample_path) = target_path(&sample_target) else {
+                continue;
+            };
+
+            let mut visited = vec![sample_path.to_owned()];
+
+            loop {
+                if visited.len() > self.max_iterations() {
+                    anyhow::bail!(
+                        "RewriteRules::detect_loops: too many rewrites starting from rule {} paths = {:?}",
+                        index,
+                        visited
+                    );
+                }
+
+                let path = visited.last().unwrap();
+
+                // rules for other hosts can not apply after this rule.
+                let next_option = self
+                    .rules
+                    .iter()
+                    .filter(|rule| {
+                        rule.host_regex.is_none() || rule.host_regex == origin_rule.host_regex
+                    })
+                    .find_map(|rule| rule.expand_target(path).map(|target| (rule, target)));
+
+                let Some((rule, target)) = next_option else {
+                    break;
+                };
+
+                let Some(next_path) = target_path(&target) else {
+                    break;
+                };
+
+                // a rewrite to the same path is terminal, a redirect to the same path is a loop.
+                if matches!(rule.rule_type, StaticFileRewriteRuleType::Rewrite) && next_path == path
+                {
+                    break;
+                }
+
+                if visited.iter().any(|visited_path| visited_path == next_path) {
+                    anyhow::bail!(
+                        "RewriteRules::detect_loops: loop detected starting from rule {} target = {:?} paths = {:?}",
+                        index,
+                        origin_rule.target,
+                        visited
+                    );
+                }
+
+                visited.push(next_path.to_owned());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn find_match(&self, host_option: Option<&str>, path: &str) -> Option<(&RewriteRule, String)> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.matches_host(host_option))
+            .find_map(|rule| rule.expand_target(path).map(|target| (rule, target)))
+    }
+
+    /// Apply rules in order. A rewrite restarts evaluation with the new path,
+    /// a redirect ends evaluation.
+    pub fn apply(
+        &self,
+        host_option: Option<&str>,
+        path: &str,
+        query_option: Option<&str>,
+    ) -> RewriteResult {
+        let mut rewritten_path_option: Option<String> = None;
+
+        for _ in 0..self.max_iterations() {
+            let current_path = rewritten_path_option.as_deref().unwrap_or(path);
+
+            let Some((rule, target)) = self.find_match(host_option, current_path) else {
+                break;
+            };
+
+            match rule.rule_type {
+                StaticFileRewriteRuleType::Redirect => {
+                    let location = match query_option {
+                        Some(query) if !target.contains('?') => format!("{}?{}", target, query),
+                        _ => target,
+                    };
+
+                    return RewriteResult::Redirect {
+                        status_code: rule.status_code,
+                        location,
+                    };
+                }
+                StaticFileRewriteRuleType::Rewrite => {
+                    if target == current_path {
+                        break;
+                    }
+                    rewritten_path_option = Some(target);
+                }
+            }
+        }
+
+        match rewritten_path_option {
+            None => RewriteResult::Unchanged,
+            Some(rewritten_path) => {
+                if self.find_match(host_option, &rewritten_path).is_some() {
+                    warn!(
+                        "rewrite rules exceeded max iterations path = {:?} rewritten_path = {:?}",
+                        path, rewritten_path
+                    );
+                }
+                RewriteResult::Rewrite(rewritten_path)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rule(
+        path_regex: &str,
+        rule_type: StaticFileRewriteRuleType,
+        target: &str,
+    ) -> crate::config::StaticFileRewriteRule {
+        crate::config::StaticFileRewriteRule {
+            host_regex: None,
+            path_regex: path_regex.to_owned(),
+            rule_type,
+            target: target.to_owned(),
+            status_code: None,
+        }
+    }
+
+    #[test]
+    fn test_apply() {
+        let rewrite_rules = RewriteRules::new(&[
+            rule(
+                r"^/old/(.*)$",
+                StaticFileRewriteRuleType::Redirect,
+                "/new/$1",
+            ),
+            rule(
+                r"^/blog/(?P<slug>[^/]+)$",
+                StaticFileRewriteRuleType::Rewrite,
+                "/posts/${slug}.html",
+            ),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            rewrite_rules.apply(None, "/old/a/b.html", Some("x=1")),
+            RewriteResult::Redirect {
+                status_code: StatusCode::MOVED_PERMANENTLY,
+                location: "/new/a/b.html?x=1".to_owned(),
+            }
+        );
+        assert_eq!(
+            rewrite_rules.apply(None, "/blog/hello", None),
+            RewriteResult::Rewrite("/posts/hello.html".to_owned())
+        );
+        assert_eq!(
+            rewrite_rules.apply(None, "/other", None),
+            RewriteResult::Unchanged
+        );
+    }
+
+    #[test]
+    fn test_detect_loops() {
+        assert!(RewriteRules::new(&[
+            rule(r"^/a$", StaticFileRewriteRuleType::Redirect, "/b"),
+            rule(r"^/b$", StaticFileRewriteRuleType::Redirect, "/a"),
+        ])
+        .is_err());
+
+        assert!(RewriteRules::new(&[rule(
+            r"^/docs/(.*)$",
+            StaticFileRewriteRuleType::Redirect,
+            "/docs/v2/$1"
+        )])
+        .is_err());
+
+        assert!(RewriteRules::new(&[
+            rule(
+                r"^/app/.*$",
+                StaticFileRewriteRuleType::Rewrite,
+                "/app/index.html"
+            ),
+            rule(
+                r"^/external$",
+                StaticFileRewriteRuleType::Redirect,
+                "https://example.com/external"
+            ),
+        ])
+        .is_ok());
+    }
+}
@@ -0,0 +1,130 @@
+use bytes::Bytes;
+
+use hyper_staticfile::vfs::{
+    FileAccess, FileOpener, FileWithMetadata, IntoFileAccess, TokioFileAccess, TokioFileFuture,
+    TokioFileOpener,
+};
+
+use tokio::io::AsyncSeek;
+
+use std::{
+    future::Future,
+    io::{Cursor, SeekFrom},
+    path::Path,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use crate::service::static_file::archive::ArchiveIndex;
+
+pub type ResolveResult = hyper_staticfile::ResolveResult<StaticFileHandle>;
+
+pub type ResolvedFile = hyper_staticfile::ResolvedFile<StaticFileHandle>;
+
+/// File opener for a mount root that is either a directory or a snapshot of an archive.
+pub enum StaticFileOpener {
+    Directory(TokioFileOpener),
+    Archive(Arc<ArchiveIndex>),
+}
+
+impl FileOpener for StaticFileOpener {
+    type File = StaticFileHandle;
+    type Future = StaticFileFuture;
+
+    fn open(&self, path: &Path) -> Self::Future {
+        match self {
+            Self::Directory(opener) => StaticFileFuture::Directory(opener.open(path)),
+            Self::Archive(archive_index) => StaticFileFuture::Ready(Some(
+                archive_index
+                    .open(path)
+                    .map(|file| map_handle(file, StaticFileHandle::Memory)),
+            )),
+        }
+    }
+}
+
+fn map_handle<F>(
+    file: FileWithMetadata<F>,
+    f: impl FnOnce(F) -> StaticFileHandle,
+) -> FileWithMetadata<StaticFileHandle> {
+    FileWithMetadata {
+        handle: f(file.handle),
+        size: file.size,
+        modified: file.modified,
+        is_dir: file.is_dir,
+    }
+}
+
+pub enum StaticFileFuture {
+    Directory(TokioFileFuture),
+    Ready(Option<std::io::Result<FileWithMetadata<StaticFileHandle>>>),
+}
+
+impl Future for StaticFileFuture {
+    type Output = std::io::Result<FileWithMetadata<StaticFileHandle>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.get_mut() {
+            Self::Directory(future) => Pin::new(future)
+                .poll(cx)
+                .map(|result| result.map(|file| map_handle(file, StaticFileHandle::File))),
+            Self::Ready(result) => Poll::Ready(
+                result
+                    .take()
+                    .expect("StaticFileFuture polled after completion"),
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum StaticFileHandle {
+    File(tokio::fs::File),
+    Memory(Cursor<Bytes>),
+}
+
+impl IntoFileAccess for StaticFileHandle {
+    type Output = StaticFileAccess;
+
+    fn into_file_access(self) -> Self::Output {
+        match self {
+            Self::File(file) => StaticFileAccess::File(file.into_file_access()),
+            Self::Memory(cursor) => StaticFileAccess::Memory(cursor),
+        }
+    }
+}
+
+pub enum StaticFileAccess {
+    File(TokioFileAccess),
+    Memory(Cursor<Bytes>),
+}
+
+impl AsyncSeek for StaticFileAccess {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> std::io::Result<()> {
+        match self.get_mut() {
+            Self::File(file_access) => Pin::new(file_access).start_seek(position),
+            Self::Memory(cursor) => Pin::new(cursor).start_seek(position),
+        }
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<u64>> {
+        match self.get_mut() {
+            Self::File(file_access) => Pin::new(file_access).poll_complete(cx),
+            Self::Memory(cursor) => Pin::new(cursor).poll_complete(cx),
+        }
+    }
+}
+
+impl FileAccess for StaticFileAccess {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        len: usize,
+    ) -> Poll<std::io::Result<Bytes>> {
+        match self.get_mut() {
+            Self::File(file_access) => FileAccess::poll_read(Pin::new(file_access), cx, len),
+            Self::Memory(cursor) => FileAccess::poll_read(Pin::new(cursor), cx, len),
+        }
+    }
+}